@@ -0,0 +1,28 @@
+// Compares the serial and parallel generators at the largest 2x2 preview size.
+// Run with `cargo bench`.
+use std::time::{Duration, Instant};
+use fractals::{create_base_pattern, generate_fractal, generate_fractal_parallel, Color};
+
+const ITERATIONS: u32 = 11;
+const RUNS: u32 = 5;
+
+fn time_runs(generate: impl Fn() -> Vec<Vec<Color>>) -> Duration {
+    // Warm up allocations and the thread pool before measuring
+    generate();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        std::hint::black_box(generate());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let pattern = create_base_pattern();
+
+    let serial = time_runs(|| generate_fractal(ITERATIONS, &pattern, 0.5));
+    let parallel = time_runs(|| generate_fractal_parallel(ITERATIONS, &pattern, 0.5));
+
+    println!("generate_fractal          ({} iterations): {:?}", ITERATIONS, serial);
+    println!("generate_fractal_parallel ({} iterations): {:?}", ITERATIONS, parallel);
+    println!("speedup: {:.2}x", serial.as_secs_f64() / parallel.as_secs_f64());
+}
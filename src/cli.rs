@@ -0,0 +1,370 @@
+// Headless rendering, used instead of the GUI whenever arguments are passed
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use fractals::{
+    check_memory, create_base_pattern, export_format, export_image, fractal_to_json,
+    load_colors_from_image, load_pattern_from_file, pattern_to_json, render_pattern,
+    render_supersampled, save_image, Color, ExportOptions, Pattern, PatternError,
+    DEFAULT_JPEG_QUALITY, DEFAULT_MEMORY_LIMIT,
+};
+
+const USAGE: &str = "Usage: fractals --input <pattern.json> [--output <image.png|jpg|webp|tiff>] [--grid-out <grid.json>] [--grid-format <nested|flat>] [options]
+       fractals batch --dir <patterns> --out-dir <renders> [options]
+       fractals --print-default
+       fractals --print-pattern <pattern.json>
+
+Options: [--iterations <n>] [--decay <0..1>] [--gamma <g>] [--bits <8|16>] [--size <WxH>] [--background <RRGGBB>] [--border <px>] [--border-color <RRGGBB>] [--ssaa <levels>] [--memory-limit <MiB>] [--quality <1..100>] [--colors <image>] [--patch <patch.json>]";
+
+// Settings shared by single and batch rendering
+struct RenderOptions {
+    colors: Option<String>,
+    // Partial pattern JSON merged onto each loaded pattern
+    patch: Option<String>,
+    iterations: u32,
+    decay: f32,
+    gamma: f32,
+    bits: u8,
+    size: Option<(u32, u32)>,
+    background: Option<Color>,
+    border: u32,
+    border_color: Color,
+    // Extra iterations rendered and averaged away for anti-aliasing
+    ssaa: u32,
+    memory_limit: u64,
+    // JPEG quality
+    quality: u8,
+}
+
+// Where and how to write the rendered color grid as JSON
+struct GridOutput {
+    path: String,
+    flat: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            colors: None,
+            patch: None,
+            iterations: 8,
+            decay: 0.5,
+            gamma: 1.0,
+            bits: 8,
+            size: None,
+            background: None,
+            border: 0,
+            border_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            ssaa: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            quality: DEFAULT_JPEG_QUALITY,
+        }
+    }
+}
+
+impl RenderOptions {
+    // Returns false if the flag isn't a render option
+    fn parse_flag(&mut self, flag: &str, value: &str) -> Result<bool, String> {
+        match flag {
+            "--colors" => self.colors = Some(value.to_string()),
+            "--patch" => self.patch = Some(value.to_string()),
+            "--iterations" => self.iterations = parse_value(flag, value)?,
+            "--decay" => self.decay = parse_value(flag, value)?,
+            "--gamma" => self.gamma = parse_value(flag, value)?,
+            "--bits" => self.bits = parse_value(flag, value)?,
+            "--size" => self.size = Some(parse_size(value)?),
+            "--background" => self.background = Some(parse_color(flag, value)?),
+            "--border" => self.border = parse_value(flag, value)?,
+            "--border-color" => self.border_color = parse_color(flag, value)?,
+            "--ssaa" => self.ssaa = parse_value(flag, value)?,
+            "--quality" => self.quality = parse_value(flag, value)?,
+            "--memory-limit" => self.memory_limit = parse_value::<u64>(flag, value)?.saturating_mul(1 << 20),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.iterations == 0 {
+            return Err("--iterations must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.decay) {
+            return Err("--decay must be between 0.0 and 1.0".to_string());
+        }
+        if !(self.gamma.is_finite() && self.gamma > 0.0) {
+            return Err("--gamma must be a finite number greater than 0".to_string());
+        }
+        if self.bits != 8 && self.bits != 16 {
+            return Err("--bits must be 8 or 16".to_string());
+        }
+        if !(1..=100).contains(&self.quality) {
+            return Err("--quality must be between 1 and 100".to_string());
+        }
+        if self.ssaa > 2 {
+            return Err("--ssaa must be 0, 1 or 2".to_string());
+        }
+        // Supersampling renders the extra levels on top of --iterations
+        if self.iterations.checked_add(self.ssaa).is_none() {
+            return Err("--iterations is too large".to_string());
+        }
+        Ok(())
+    }
+
+    fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            gamma: self.gamma,
+            sixteen_bit: self.bits == 16,
+            resize: self.size,
+            background: self.background,
+            border: self.border,
+            border_color: self.border_color,
+            ..ExportOptions::default()
+        }
+    }
+}
+
+// The path flags (--input/--output or --dir/--out-dir) plus render options
+struct RenderArgs {
+    input: String,
+    output: Option<String>,
+    grid: Option<GridOutput>,
+    options: RenderOptions,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid value for --size: {} (expected WxH)", value);
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_color(flag: &str, value: &str) -> Result<Color, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .map(|channel| channel as f32 / 255.0)
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(Color::new(r, g, b, 1.0)),
+        _ => Err(format!("Invalid value for {}: {} (expected RRGGBB)", flag, value)),
+    }
+}
+
+fn parse_args(args: &[String], input_flag: &str, output_flag: &str) -> Result<RenderArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut grid_out = None;
+    let mut grid_flat = false;
+    let mut options = RenderOptions::default();
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        if flag == input_flag {
+            input = Some(value.clone());
+        } else if flag == output_flag {
+            output = Some(value.clone());
+        } else if flag == "--grid-out" {
+            grid_out = Some(value.clone());
+        } else if flag == "--grid-format" {
+            grid_flat = match value.as_str() {
+                "nested" => false,
+                "flat" => true,
+                _ => return Err(format!("Invalid value for --grid-format: {} (expected nested or flat)", value)),
+            };
+        } else if !options.parse_flag(flag, value)? {
+            return Err(format!("Unknown argument: {}", flag));
+        }
+    }
+    options.validate()?;
+
+    Ok(RenderArgs {
+        input: input.ok_or_else(|| format!("Missing {}", input_flag))?,
+        output,
+        grid: grid_out.map(|path| GridOutput { path, flat: grid_flat }),
+        options,
+    })
+}
+
+fn load_pattern(path: &str, options: &RenderOptions) -> Result<Pattern, PatternError> {
+    let mut pattern = load_pattern_from_file(path)?;
+    if let Some(colors) = &options.colors {
+        load_colors_from_image(&mut pattern, colors)?;
+    }
+    if let Some(patch) = &options.patch {
+        let patch: serde_json::Value = serde_json::from_str(&fs::read_to_string(patch)?)?;
+        pattern.apply_patch(&patch)?;
+    }
+    Ok(pattern)
+}
+
+fn render(pattern: &Pattern, options: &RenderOptions) -> Result<Vec<Vec<Color>>, PatternError> {
+    check_memory(pattern.base_rows, pattern.base_dim, options.iterations + options.ssaa, options.memory_limit)?;
+    Ok(if options.ssaa > 0 {
+        render_supersampled(pattern, options.iterations, &[options.decay], options.ssaa)
+    } else {
+        render_pattern(pattern, options.iterations, options.decay)
+    })
+}
+
+fn render_to_file(pattern: &Pattern, options: &RenderOptions, output: &Path) -> Result<(), PatternError> {
+    let fractal = render(pattern, options)?;
+    save_image(&export_image(&fractal, &options.export_options()), output, options.quality)
+}
+
+// Returns the process exit code
+pub fn run(args: &[String]) -> i32 {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    if args.first().map(String::as_str) == Some("batch") {
+        return run_batch(&args[1..]);
+    }
+
+    match args {
+        [flag] if flag == "--print-default" => return print_pattern(Ok(create_base_pattern())),
+        [flag, path] if flag == "--print-pattern" => return print_pattern(load_pattern_from_file(path)),
+        [flag, ..] if flag == "--print-default" || flag == "--print-pattern" => {
+            eprintln!("Wrong arguments for {}\n{}", flag, USAGE);
+            return 2;
+        }
+        _ => {}
+    }
+
+    let args = match parse_args(args, "--input", "--output") {
+        Ok(args) if args.output.is_none() && args.grid.is_none() => {
+            eprintln!("Missing --output or --grid-out\n{}", USAGE);
+            return 2;
+        }
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if let Some(Err(e)) = args.output.as_deref().map(|output| export_format(Path::new(output))) {
+        eprintln!("{}", e);
+        return 2;
+    }
+
+    let pattern = match load_pattern(&args.input, &args.options) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            eprintln!("Failed to load pattern: {}", e);
+            return 1;
+        }
+    };
+
+    let fractal = match render(&pattern, &args.options) {
+        Ok(fractal) => fractal,
+        Err(e) => {
+            eprintln!("Failed to render: {}", e);
+            return 1;
+        }
+    };
+
+    if let Some(output) = &args.output {
+        let image = export_image(&fractal, &args.options.export_options());
+        if let Err(e) = save_image(&image, Path::new(output), args.options.quality) {
+            eprintln!("Failed to export image: {}", e);
+            return 1;
+        }
+    }
+
+    if let Some(grid) = &args.grid {
+        let result = fractal_to_json(&fractal, args.options.iterations, &[args.options.decay], grid.flat)
+            .and_then(|json| Ok(fs::write(&grid.path, json)?));
+        if let Err(e) = result {
+            eprintln!("Failed to export grid: {}", e);
+            return 1;
+        }
+    }
+    0
+}
+
+// Writes a pattern to stdout in the saved file format, so loading and
+// printing a file checks it and normalizes its layout
+fn print_pattern(pattern: Result<Pattern, PatternError>) -> i32 {
+    match pattern.and_then(|pattern| pattern_to_json(&pattern)) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(e) => {
+            eprintln!("Invalid pattern: {}", e);
+            1
+        }
+    }
+}
+
+// Renders every *.json in a directory, reporting failures without stopping
+fn run_batch(args: &[String]) -> i32 {
+    let (dir, out_dir, options) = match parse_args(args, "--dir", "--out-dir") {
+        Ok(RenderArgs { grid: Some(_), .. }) => {
+            eprintln!("--grid-out is not supported with batch\n{}", USAGE);
+            return 2;
+        }
+        Ok(RenderArgs { input, output: Some(out_dir), options, .. }) => (input, out_dir, options),
+        Ok(_) => {
+            eprintln!("Missing --out-dir\n{}", USAGE);
+            return 2;
+        }
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    let mut inputs: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")))
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", dir, e);
+            return 1;
+        }
+    };
+    inputs.sort();
+
+    if let Err(e) = fs::create_dir_all(&out_dir) {
+        eprintln!("Failed to create {}: {}", out_dir, e);
+        return 1;
+    }
+
+    let mut failures = 0;
+    let mut written = HashSet::new();
+    for input in &inputs {
+        // Appending keeps dots in the stem, so foo.v1 and foo.v2 stay apart
+        let stem = input.file_stem().unwrap_or_default();
+        let output = Path::new(&out_dir).join(format!("{}.png", stem.to_string_lossy()));
+        // Inputs differing only in the case of their extension share an output
+        if !written.insert(output.clone()) {
+            eprintln!("Failed {}: {} was already rendered from another pattern", input.display(), output.display());
+            failures += 1;
+            continue;
+        }
+        let result = load_pattern(&input.to_string_lossy(), &options)
+            .and_then(|pattern| render_to_file(&pattern, &options, &output));
+        match result {
+            Ok(()) => println!("Rendered {} -> {}", input.display(), output.display()),
+            Err(e) => {
+                eprintln!("Failed {}: {}", input.display(), e);
+                failures += 1;
+            }
+        }
+    }
+
+    println!("{} rendered, {} failed", inputs.len() - failures, failures);
+    if failures == 0 { 0 } else { 1 }
+}
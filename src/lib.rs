@@ -0,0 +1,3027 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write as _;
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{
+    Delay, DynamicImage, Frame, GenericImage, GenericImageView, ImageBuffer, ImageError, ImageFormat, Rgb,
+    RgbImage, Rgba, RgbaImage,
+};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufReader, BufWriter, Write as _};
+use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+/// Largest preview/export edge length the iteration slider will allow
+pub const MAX_RENDER_SIZE: usize = 2048;
+
+/// An unpremultiplied RGBA color with channels from 0 to 1. RGB is
+/// sRGB-encoded throughout: blending works on these values (decoding to
+/// linear light only where a DecayModel asks for it), and the preview and
+/// exports both write them as sRGB bytes through ExportOptions::encode, so
+/// the preview shows exactly what is saved.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Maps each source cell (row-major index) of a grid to its destination cell.
+/// Grids are NxN for square patterns or rows x cols for rectangular ones.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Permutation {
+    pub mapping: Vec<(usize, usize)>
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pixel {
+    pub color: Color,
+    pub perm: Permutation,
+    /// Extra decay applied at every level to the detail beneath this pixel;
+    /// 1.0, the default for older files, leaves only the global decay
+    #[serde(default = "default_pixel_decay")]
+    pub decay: f32,
+    /// How much this pixel's color holds against the sub-pattern stamped
+    /// beneath it, in place of its alpha: 1 blends by the decay like an
+    /// opaque pixel, 0 lets the sub-pattern replace it like a transparent
+    /// one. None, the default for older files, uses the alpha for both.
+    #[serde(default)]
+    pub weight: Option<f32>,
+}
+
+fn default_pixel_decay() -> f32 {
+    1.0
+}
+
+/// How a sub-pattern color is combined with the parent color it subdivides
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Lerp,
+    Multiply,
+    Screen,
+    Add,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 4] = [BlendMode::Lerp, BlendMode::Multiply, BlendMode::Screen, BlendMode::Add];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Lerp => "Lerp",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Add => "Add",
+        }
+    }
+
+    /// The color the parent fades towards as the blend factor approaches 1
+    pub fn target(self, parent: &Color, base: &Color) -> Color {
+        match self {
+            BlendMode::Lerp => *base,
+            BlendMode::Multiply => parent.multiply(base),
+            BlendMode::Screen => parent.screen(base),
+            BlendMode::Add => parent.add(base),
+        }
+    }
+}
+
+/// The color space a child cell's color is faded towards its target in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DecayModel {
+    /// Interpolate the stored sRGB values directly
+    #[default]
+    Linear,
+    /// Interpolate in linear light, which keeps contrast in deep levels
+    Perceptual,
+}
+
+impl DecayModel {
+    pub const ALL: [DecayModel; 2] = [DecayModel::Linear, DecayModel::Perceptual];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DecayModel::Linear => "Linear",
+            DecayModel::Perceptual => "Perceptual",
+        }
+    }
+
+    pub fn mix(self, from: &Color, to: &Color, t: f32) -> Color {
+        match self {
+            DecayModel::Linear => from.lerp(to, t),
+            DecayModel::Perceptual => from.srgb_to_linear().lerp(&to.srgb_to_linear(), t).linear_to_srgb(),
+        }
+    }
+
+    /// Like mix, but weights RGB by alpha while interpolating so a fading
+    /// color keeps its hue instead of darkening towards a transparent one
+    pub fn mix_premultiplied(self, from: &Color, to: &Color, t: f32) -> Color {
+        match self {
+            DecayModel::Linear => from.premultiplied().lerp(&to.premultiplied(), t).unpremultiplied(),
+            DecayModel::Perceptual => from.srgb_to_linear().premultiplied()
+                .lerp(&to.srgb_to_linear().premultiplied(), t)
+                .unpremultiplied()
+                .linear_to_srgb(),
+        }
+    }
+}
+
+/// How the color channels stored in a pattern are to be read. Generation
+/// works on sRGB-encoded values, so linear colors are encoded first.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// Channels are sRGB-encoded, as in image files; patterns saved before
+    /// the color space was recorded are read this way
+    #[default]
+    Srgb,
+    /// Channels are proportional to light intensity
+    Linear,
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 2] = [ColorSpace::Srgb, ColorSpace::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::Linear => "Linear",
+        }
+    }
+
+    /// A stored color as the sRGB-encoded value generation works with
+    pub fn to_srgb(self, color: Color) -> Color {
+        match self {
+            ColorSpace::Srgb => color,
+            ColorSpace::Linear => color.linear_to_srgb(),
+        }
+    }
+
+    /// An sRGB-encoded color as it is stored in this color space
+    pub fn from_srgb(self, color: Color) -> Color {
+        match self {
+            ColorSpace::Srgb => color,
+            ColorSpace::Linear => color.srgb_to_linear(),
+        }
+    }
+}
+
+/// The order in which a cell's inherited permutation and the permutation of
+/// the base pixel it shows are combined to orient that pixel's sub-pattern.
+/// Both orders agree whenever the two permutations commute, such as when all
+/// of them are rotations, and differ once flips and turns are mixed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ComposeOrder {
+    /// Apply the inherited permutation, then the pixel's own. The pixel's
+    /// permutation acts along the fixed axes of the image, so a flip on a
+    /// pixel flips the same way wherever the pixel ends up.
+    #[default]
+    ParentFirst,
+    /// Apply the pixel's own permutation, then the inherited one. The pixel's
+    /// permutation acts in its parent's frame, so a whole sub-pattern turns
+    /// and flips along with its ancestors as a rigid copy.
+    PixelFirst,
+}
+
+impl ComposeOrder {
+    pub const ALL: [ComposeOrder; 2] = [ComposeOrder::ParentFirst, ComposeOrder::PixelFirst];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ComposeOrder::ParentFirst => "Parent first",
+            ComposeOrder::PixelFirst => "Pixel first",
+        }
+    }
+
+    /// The permutation a child expands through, given the permutation of the
+    /// cell it sits in and that of the base pixel it shows
+    pub fn compose(self, parent: &Permutation, pixel: &Permutation) -> Permutation {
+        match self {
+            ComposeOrder::ParentFirst => parent.compose(pixel),
+            ComposeOrder::PixelFirst => pixel.compose(parent),
+        }
+    }
+}
+
+/// How normalize_range stretches a rendered fractal's colors
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NormalizeMode {
+    /// Map the luminance range to 0..1, scaling the channels of each color
+    /// together so hues are preserved
+    #[default]
+    Luminance,
+    /// Stretch each channel to 0..1 separately, which also neutralizes
+    /// color casts
+    Channels,
+}
+
+impl NormalizeMode {
+    pub const ALL: [NormalizeMode; 2] = [NormalizeMode::Luminance, NormalizeMode::Channels];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NormalizeMode::Luminance => "Luminance",
+            NormalizeMode::Channels => "Per channel",
+        }
+    }
+}
+
+/// Linearly stretches the colors of a rendered fractal to use the full 0..1
+/// range, ignoring fully transparent cells. Grids with no range to stretch
+/// are left unchanged.
+pub fn normalize_range(fractal: &mut [Vec<Color>], mode: NormalizeMode) {
+    let range = |channel: &dyn Fn(&Color) -> f32| {
+        fractal.iter().flatten()
+            .filter(|color| color.a > 0.0)
+            .map(channel)
+            .fold(None, |range: Option<(f32, f32)>, value| match range {
+                Some((min, max)) => Some((min.min(value), max.max(value))),
+                None => Some((value, value)),
+            })
+    };
+    // A channel with a single value has no range and keeps it
+    let stretch = |(min, max): (f32, f32)| move |value: f32| {
+        if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { value }
+    };
+
+    match mode {
+        NormalizeMode::Luminance => {
+            let Some(luminance) = range(&Color::luminance) else {
+                return;
+            };
+            let stretch = stretch(luminance);
+            for color in fractal.iter_mut().flatten().filter(|color| color.a > 0.0) {
+                let before = color.luminance();
+                let scale = if before > 0.0 { stretch(before) / before } else { 0.0 };
+                *color = Color::new(
+                    (color.r * scale).clamp(0.0, 1.0),
+                    (color.g * scale).clamp(0.0, 1.0),
+                    (color.b * scale).clamp(0.0, 1.0),
+                    color.a,
+                );
+            }
+        }
+        NormalizeMode::Channels => {
+            let (Some(r), Some(g), Some(b)) = (range(&|c| c.r), range(&|c| c.g), range(&|c| c.b)) else {
+                return;
+            };
+            let (r, g, b) = (stretch(r), stretch(g), stretch(b));
+            for color in fractal.iter_mut().flatten().filter(|color| color.a > 0.0) {
+                *color = Color::new(r(color.r), g(color.g), b(color.b), color.a);
+            }
+        }
+    }
+}
+
+/// A color vision deficiency to simulate, for checking how a fractal reads
+/// to viewers who have it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorVision {
+    #[default]
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Normal,
+        ColorVision::Protanopia,
+        ColorVision::Deuteranopia,
+        ColorVision::Tritanopia,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorVision::Normal => "Normal",
+            ColorVision::Protanopia => "Protanopia",
+            ColorVision::Deuteranopia => "Deuteranopia",
+            ColorVision::Tritanopia => "Tritanopia",
+        }
+    }
+
+    // Full-severity matrices from Machado, Oliveira and Fernandes (2009),
+    // which apply to linear RGB
+    fn matrix(self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            ColorVision::Normal => None,
+            ColorVision::Protanopia => Some([
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ]),
+            ColorVision::Deuteranopia => Some([
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ]),
+            ColorVision::Tritanopia => Some([
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ]),
+        }
+    }
+
+    /// How the color appears with this deficiency; alpha is unchanged
+    pub fn simulate(self, color: Color) -> Color {
+        let Some(matrix) = self.matrix() else {
+            return color;
+        };
+        let linear = color.srgb_to_linear();
+        let [r, g, b] = matrix.map(|row| {
+            (row[0] * linear.r + row[1] * linear.g + row[2] * linear.b).clamp(0.0, 1.0)
+        });
+        Color::new(r, g, b, color.a).linear_to_srgb()
+    }
+}
+
+/// Applies ColorVision::simulate to every cell of a rendered grid
+pub fn simulate_color_vision(fractal: &[Vec<Color>], vision: ColorVision) -> Vec<Vec<Color>> {
+    fractal.iter()
+        .map(|row| row.iter().map(|&color| vision.simulate(color)).collect())
+        .collect()
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    /// Columns of the base tile, which is also its height for square patterns.
+    /// Patterns saved before NxN support have no base_dim; it is inferred on load.
+    #[serde(default)]
+    pub base_dim: usize,
+    /// Rows of the base tile; inferred on load for patterns saved before
+    /// rectangular tiles
+    #[serde(default)]
+    pub base_rows: usize,
+    pub pixels: Vec<Vec<Pixel>>,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    #[serde(default)]
+    pub decay_model: DecayModel,
+    /// Mirror the top-left quadrant into the others for a centered result
+    #[serde(default)]
+    pub symmetry: bool,
+    /// Let transparent cells take on more sub-pattern detail instead of less
+    #[serde(default)]
+    pub invert_alpha: bool,
+    /// Blend transparent cells like any other color instead of treating
+    /// them as holes that stop recursion detail
+    #[serde(default)]
+    pub transparent_as_color: bool,
+    /// Blend with premultiplied alpha, which avoids dark fringes where
+    /// colors fade towards transparent ones
+    #[serde(default)]
+    pub premultiplied_alpha: bool,
+    /// Stretch the rendered colors to the full 0..1 range after generation
+    #[serde(default)]
+    pub normalize: bool,
+    #[serde(default)]
+    pub normalize_mode: NormalizeMode,
+    /// Pixels placed at the top level in place of pixels, which are then
+    /// only used for the expansions below it; None seeds with pixels
+    #[serde(default)]
+    pub initial_pixels: Option<Vec<Vec<Pixel>>>,
+    /// Human-readable name for telling patterns apart; empty when unnamed
+    #[serde(default)]
+    pub name: String,
+    /// Number of levels, counting the top one, whose cells expand through
+    /// their composed permutations. Deeper levels place the base pattern
+    /// unpermuted, which changes the orientation of the finest detail. None
+    /// composes permutations at every level.
+    #[serde(default)]
+    pub perm_depth_limit: Option<u32>,
+    /// How the stored pixel colors are to be read
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    /// How inherited and per-pixel permutations combine
+    #[serde(default)]
+    pub compose_order: ComposeOrder,
+    /// Cells with alpha below this expand as fully transparent holes in the
+    /// recursion instead of blending faintly with their sub-pattern; their
+    /// own color is kept. 0 blends every alpha as is.
+    #[serde(default)]
+    pub alpha_cutoff: f32,
+}
+
+
+impl Permutation {
+    fn from_fn(rows: usize, cols: usize, f: impl Fn(usize, usize) -> (usize, usize)) -> Self {
+        Permutation {
+            mapping: (0..rows * cols).map(|i| f(i / cols, i % cols)).collect()
+        }
+    }
+
+    pub fn identity(dim: usize) -> Self {
+        Self::identity_rect(dim, dim)
+    }
+    
+    pub fn rotate_90(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (x, dim - 1 - y))
+    }
+    
+    pub fn rotate_180(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (dim - 1 - y, dim - 1 - x))
+    }
+    
+    pub fn rotate_270(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (dim - 1 - x, y))
+    }
+    
+    pub fn flip_h(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (y, dim - 1 - x))
+    }
+    
+    pub fn flip_v(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (dim - 1 - y, x))
+    }
+
+    /// Mirror across the main (top-left to bottom-right) diagonal
+    pub fn transpose(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (x, y))
+    }
+
+    /// Mirror across the anti-diagonal (top-right to bottom-left)
+    pub fn anti_transpose(dim: usize) -> Self {
+        Self::from_fn(dim, dim, |y, x| (dim - 1 - x, dim - 1 - y))
+    }
+
+    pub fn identity_rect(rows: usize, cols: usize) -> Self {
+        Self::from_fn(rows, cols, |y, x| (y, x))
+    }
+
+    /// The named presets that map a rows x cols grid onto itself. Square grids
+    /// get all eight; quarter turns and diagonal mirrors would change the
+    /// shape of a rectangle, leaving the identity, half turn and both flips.
+    pub fn symmetries(rows: usize, cols: usize) -> Vec<Permutation> {
+        if rows == cols {
+            return Self::presets(rows).to_vec();
+        }
+        Self::rect_symmetries(rows, cols).into_iter().map(|(_, perm)| perm).collect()
+    }
+
+    fn rect_symmetries(rows: usize, cols: usize) -> [(&'static str, Permutation); 4] {
+        [
+            ("Identity", Self::identity_rect(rows, cols)),
+            ("Rotate 180°", Self::from_fn(rows, cols, |y, x| (rows - 1 - y, cols - 1 - x))),
+            ("Flip H", Self::from_fn(rows, cols, |y, x| (y, cols - 1 - x))),
+            ("Flip V", Self::from_fn(rows, cols, |y, x| (rows - 1 - y, x))),
+        ]
+    }
+
+    /// All named presets for a grid of the given size
+    pub fn presets(dim: usize) -> [Permutation; 8] {
+        [
+            Self::identity(dim),
+            Self::rotate_90(dim),
+            Self::rotate_180(dim),
+            Self::rotate_270(dim),
+            Self::flip_h(dim),
+            Self::flip_v(dim),
+            Self::transpose(dim),
+            Self::anti_transpose(dim),
+        ]
+    }
+
+    /// Side length of the square grid this permutation acts on
+    pub fn dim(&self) -> usize {
+        let mut dim = 0;
+        while dim * dim < self.mapping.len() {
+            dim += 1;
+        }
+        dim
+    }
+
+    /// (rows, cols) of the grid this permutation acts on. A valid permutation
+    /// reaches every cell, so the largest destination gives the shape.
+    pub fn shape(&self) -> (usize, usize) {
+        self.mapping.iter()
+            .fold((0, 0), |(rows, cols), &(y, x)| (rows.max(y + 1), cols.max(x + 1)))
+    }
+    
+    /// The permutation that applies `self` first and then `other`, so
+    /// `a.compose(&b).apply(grid) == b.apply(&a.apply(grid))`
+    pub fn compose(&self, other: &Permutation) -> Permutation {
+        let cols = self.shape().1;
+        Permutation {
+            mapping: self.mapping.iter().map(|&(y, x)| other.mapping[y * cols + x]).collect()
+        }
+    }
+    
+    /// The permutation that undoes this one
+    pub fn inverse(&self) -> Permutation {
+        let (rows, cols) = self.shape();
+        let mut mapping = vec![(0, 0); rows * cols];
+        for (i, &(y, x)) in self.mapping.iter().enumerate() {
+            mapping[y * cols + x] = (i / cols, i % cols);
+        }
+        Permutation { mapping }
+    }
+
+    pub fn apply<T: Clone>(&self, grid: &[Vec<T>]) -> Vec<Vec<T>> {
+        let cols = grid.first().map_or(0, Vec::len);
+        let mut result = grid.to_vec();
+        for (i, &(to_y, to_x)) in self.mapping.iter().enumerate() {
+            result[to_y][to_x] = grid[i / cols][i % cols].clone();
+        }
+        result
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        let (rows, cols) = self.shape();
+        if rows != cols {
+            return Self::rect_symmetries(rows, cols).into_iter()
+                .find(|(_, perm)| perm == self)
+                .map_or("Custom", |(name, _)| name);
+        }
+        let dim = rows;
+        if *self == Self::identity(dim) {
+            "Identity"
+        } else if *self == Self::rotate_90(dim) {
+            "Rotate 90°"
+        } else if *self == Self::rotate_180(dim) {
+            "Rotate 180°"
+        } else if *self == Self::rotate_270(dim) {
+            "Rotate 270°"
+        } else if *self == Self::flip_h(dim) {
+            "Flip H"
+        } else if *self == Self::flip_v(dim) {
+            "Flip V"
+        } else if *self == Self::transpose(dim) {
+            "Transpose"
+        } else if *self == Self::anti_transpose(dim) {
+            "Anti-transpose"
+        } else {
+            "Custom"
+        }
+    }
+}
+
+// Hashes the exact bit patterns so patterns can be fingerprinted cheaply
+impl Hash for Color {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for channel in [self.r, self.g, self.b, self.a] {
+            channel.to_bits().hash(state);
+        }
+    }
+}
+
+impl Hash for Pixel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.color.hash(state);
+        self.perm.hash(state);
+        self.decay.to_bits().hash(state);
+        self.weight.map(f32::to_bits).hash(state);
+    }
+}
+
+// Covers only what changes the render, so renaming keeps cached renders
+impl Hash for Pattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.base_dim.hash(state);
+        self.base_rows.hash(state);
+        self.pixels.hash(state);
+        self.blend_mode.hash(state);
+        self.decay_model.hash(state);
+        self.symmetry.hash(state);
+        self.invert_alpha.hash(state);
+        self.transparent_as_color.hash(state);
+        self.premultiplied_alpha.hash(state);
+        self.normalize.hash(state);
+        self.normalize_mode.hash(state);
+        self.initial_pixels.hash(state);
+        self.perm_depth_limit.hash(state);
+        self.color_space.hash(state);
+        self.compose_order.hash(state);
+        self.alpha_cutoff.to_bits().hash(state);
+    }
+}
+
+impl Color {
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    pub fn lerp(&self, other: &Color, t: f32) -> Color {
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+
+    // The blend modes combine RGB and take alpha from other
+
+    pub fn multiply(&self, other: &Color) -> Color {
+        Color::new(self.r * other.r, self.g * other.g, self.b * other.b, other.a)
+    }
+
+    pub fn screen(&self, other: &Color) -> Color {
+        let screen = |a: f32, b: f32| 1.0 - (1.0 - a) * (1.0 - b);
+        Color::new(screen(self.r, other.r), screen(self.g, other.g), screen(self.b, other.b), other.a)
+    }
+
+    pub fn add(&self, other: &Color) -> Color {
+        Color::new(
+            (self.r + other.r).min(1.0),
+            (self.g + other.g).min(1.0),
+            (self.b + other.b).min(1.0),
+            other.a,
+        )
+    }
+
+    /// Builds a color from hue in degrees (wrapped to 0..360), saturation and
+    /// value in 0..1, and alpha
+    pub fn from_hsv(h: f32, s: f32, v: f32, a: f32) -> Color {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = v - c;
+        Color::new(r + m, g + m, b + m, a)
+    }
+
+    /// Returns (hue in degrees, saturation, value, alpha). Achromatic colors
+    /// report a hue of 0.
+    pub fn to_hsv(&self) -> (f32, f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let delta = max - min;
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / delta).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / delta + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+        (h, s, max, self.a)
+    }
+
+    /// RGB multiplied by alpha
+    pub fn premultiplied(self) -> Color {
+        Color::new(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+    }
+
+    /// Inverse of premultiplied; fully transparent colors come back black
+    pub fn unpremultiplied(self) -> Color {
+        if self.a == 0.0 {
+            return Color::new(0.0, 0.0, 0.0, 0.0);
+        }
+        Color::new(self.r / self.a, self.g / self.a, self.b / self.a, self.a)
+    }
+
+    /// Decodes sRGB-encoded RGB to linear light; alpha is unchanged
+    pub fn srgb_to_linear(self) -> Color {
+        let decode = |c: f32| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Color::new(decode(self.r), decode(self.g), decode(self.b), self.a)
+    }
+
+    /// Encodes linear-light RGB as sRGB; alpha is unchanged
+    pub fn linear_to_srgb(self) -> Color {
+        let encode = |c: f32| {
+            if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        };
+        Color::new(encode(self.r), encode(self.g), encode(self.b), self.a)
+    }
+
+    /// Mean of the colors in linear light, the building block for any
+    /// resampling. RGB is weighted by alpha so transparent colors don't pull
+    /// the result towards black; alpha is a plain mean. Averaging sRGB values
+    /// directly would darken midtones: black and white give sRGB 0.735, not 0.5.
+    /// No colors, or only transparent ones, give transparent black.
+    pub fn average_linear(colors: &[Color]) -> Color {
+        let (mut r, mut g, mut b, mut a) = (0.0, 0.0, 0.0, 0.0);
+        for color in colors {
+            let linear = color.srgb_to_linear();
+            r += linear.r * color.a;
+            g += linear.g * color.a;
+            b += linear.b * color.a;
+            a += color.a;
+        }
+        if a == 0.0 {
+            return Color::new(0.0, 0.0, 0.0, 0.0);
+        }
+        Color::new(r / a, g / a, b / a, a / colors.len() as f32).linear_to_srgb()
+    }
+
+    /// Whether every channel lies within 0..=1, before any clamping on export
+    pub fn in_gamut(&self) -> bool {
+        [self.r, self.g, self.b, self.a].iter().all(|channel| (0.0..=1.0).contains(channel))
+    }
+
+    /// Rec. 601 luma of the RGB channels
+    pub fn luminance(&self) -> f32 {
+        0.299 * self.r + 0.587 * self.g + 0.114 * self.b
+    }
+
+    /// Composites this (unpremultiplied) color over a background with the
+    /// standard "over" operator
+    pub fn over(&self, background: &Color) -> Color {
+        let a = self.a + background.a * (1.0 - self.a);
+        if a == 0.0 {
+            return Color::new(0.0, 0.0, 0.0, 0.0);
+        }
+        let channel = |fg: f32, bg: f32| (fg * self.a + bg * background.a * (1.0 - self.a)) / a;
+        Color::new(
+            channel(self.r, background.r),
+            channel(self.g, background.g),
+            channel(self.b, background.b),
+            a,
+        )
+    }
+
+    pub fn to_rgba(self) -> Rgba<u8> {
+        // Round to nearest rather than truncating, which darkens and bands
+        // gradients; clamping covers any overshoot from blending
+        let to_u8 = |channel: f32| (channel * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgba([
+            to_u8(self.r),
+            to_u8(self.g),
+            to_u8(self.b),
+            to_u8(self.a),
+        ])
+    }
+
+    /// Maps each channel to the full 16-bit range, rounding to nearest
+    pub fn to_rgba16(self) -> [u16; 4] {
+        let to_u16 = |channel: f32| (channel * 65535.0).round().clamp(0.0, 65535.0) as u16;
+        [to_u16(self.r), to_u16(self.g), to_u16(self.b), to_u16(self.a)]
+    }
+
+    /// Applies gamma encoding to the RGB channels. Alpha is left linear.
+    pub fn encode_gamma(self, gamma: f32) -> Color {
+        if gamma == 1.0 {
+            return self;
+        }
+        let encode = |channel: f32| channel.max(0.0).powf(1.0 / gamma);
+        Color::new(encode(self.r), encode(self.g), encode(self.b), self.a)
+    }
+
+    /// Like to_rgba, but gamma-encodes the RGB channels first
+    pub fn to_rgba_gamma(self, gamma: f32) -> Rgba<u8> {
+        self.encode_gamma(gamma).to_rgba()
+    }
+}
+
+impl Pattern {
+    /// The larger of the base tile's width and height, which bounds how many
+    /// iterations fit in a given output size
+    pub fn longest_side(&self) -> usize {
+        self.base_dim.max(self.base_rows)
+    }
+
+    /// Returns a copy of this pattern with a new rows x cols base size, keeping
+    /// the colors of overlapping cells. Permutations are reset since they
+    /// depend on the size.
+    pub fn resized(&self, rows: usize, cols: usize) -> Pattern {
+        let resize = |pixels: &Vec<Vec<Pixel>>| -> Vec<Vec<Pixel>> {
+            (0..rows).map(|y| {
+                (0..cols).map(|x| {
+                    let (color, decay, weight) = pixels.get(y)
+                        .and_then(|row| row.get(x))
+                        .map_or((Color::new(0.0, 0.0, 0.0, 0.0), 1.0, None), |pixel| (pixel.color, pixel.decay, pixel.weight));
+                    Pixel { color, perm: Permutation::identity_rect(rows, cols), decay, weight }
+                }).collect()
+            }).collect()
+        };
+        Pattern {
+            base_dim: cols,
+            base_rows: rows,
+            pixels: resize(&self.pixels),
+            blend_mode: self.blend_mode,
+            decay_model: self.decay_model,
+            symmetry: self.symmetry,
+            invert_alpha: self.invert_alpha,
+            transparent_as_color: self.transparent_as_color,
+            premultiplied_alpha: self.premultiplied_alpha,
+            normalize: self.normalize,
+            normalize_mode: self.normalize_mode,
+            initial_pixels: self.initial_pixels.as_ref().map(resize),
+            name: self.name.clone(),
+            perm_depth_limit: self.perm_depth_limit,
+            color_space: self.color_space,
+            compose_order: self.compose_order,
+            alpha_cutoff: self.alpha_cutoff,
+        }
+    }
+
+    /// A rows x cols pattern with random colors and preset permutations; the
+    /// same seed always gives the same pattern
+    pub fn random(rows: usize, cols: usize, seed: u64) -> Pattern {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let presets = Permutation::symmetries(rows, cols);
+        let pixels = (0..rows).map(|_| {
+            (0..cols).map(|_| Pixel {
+                color: Color::new(rng.gen(), rng.gen(), rng.gen(), rng.gen()),
+                perm: presets[rng.gen_range(0..presets.len())].clone(),
+                decay: 1.0,
+                weight: None,
+            }).collect()
+        }).collect();
+        Pattern {
+            base_dim: cols,
+            base_rows: rows,
+            pixels,
+            blend_mode: BlendMode::default(),
+            decay_model: DecayModel::default(),
+            symmetry: false,
+            invert_alpha: false,
+            transparent_as_color: false,
+            premultiplied_alpha: false,
+            normalize: false,
+            normalize_mode: NormalizeMode::default(),
+            initial_pixels: None,
+            name: String::new(),
+            perm_depth_limit: None,
+            color_space: ColorSpace::default(),
+            compose_order: ComposeOrder::default(),
+            alpha_cutoff: 0.0,
+        }
+    }
+
+    /// Replaces every permutation with a random preset, keeping the colors
+    pub fn randomize_permutations(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let presets = Permutation::symmetries(self.base_rows, self.base_dim);
+        for pixel in self.pixels.iter_mut().flatten() {
+            pixel.perm = presets[rng.gen_range(0..presets.len())].clone();
+        }
+    }
+
+    /// Replaces every color with a random one, keeping the permutations
+    pub fn randomize_colors(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for pixel in self.pixels.iter_mut().flatten() {
+            pixel.color = Color::new(rng.gen(), rng.gen(), rng.gen(), rng.gen());
+        }
+    }
+
+    /// A copy with small random changes: each color channel moves by up to
+    /// strength, and each permutation is replaced by a random preset with
+    /// probability strength. Strength runs from 0 (an exact copy) to 1, and
+    /// the same seed always gives the same mutation.
+    pub fn mutated(&self, strength: f32, seed: u64) -> Pattern {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let presets = Permutation::symmetries(self.base_rows, self.base_dim);
+        let strength = strength.clamp(0.0, 1.0);
+        let mut pattern = self.clone();
+        for pixel in pattern.pixels.iter_mut().flatten() {
+            let mut nudge = |channel: f32| (channel + rng.gen_range(-1.0..=1.0) * strength).clamp(0.0, 1.0);
+            pixel.color = Color::new(nudge(pixel.color.r), nudge(pixel.color.g), nudge(pixel.color.b), nudge(pixel.color.a));
+            if rng.gen::<f32>() < strength {
+                pixel.perm = presets[rng.gen_range(0..presets.len())].clone();
+            }
+        }
+        pattern
+    }
+
+    /// A copy with pixel colors and decays moved fraction t of the way
+    /// towards other's, which must be the same size. Everything else,
+    /// permutations included, stays as in self.
+    pub fn interpolated(&self, other: &Pattern, t: f32) -> Pattern {
+        let mut pattern = self.clone();
+        for (pixel, target) in pattern.pixels.iter_mut().flatten().zip(other.pixels.iter().flatten()) {
+            pixel.color = pixel.color.lerp(&target.color, t);
+            pixel.decay += (target.decay - pixel.decay) * t;
+        }
+        pattern
+    }
+
+    /// Whether other differs from self only in pixel colors and decays, so
+    /// interpolated reaches it exactly
+    pub fn can_interpolate_to(&self, other: &Pattern) -> bool {
+        if (self.base_rows, self.base_dim) != (other.base_rows, other.base_dim) {
+            return false;
+        }
+        let mut pattern = self.clone();
+        for (pixel, target) in pattern.pixels.iter_mut().flatten().zip(other.pixels.iter().flatten()) {
+            pixel.color = target.color;
+            pixel.decay = target.decay;
+        }
+        pattern == *other
+    }
+
+    /// Merges a partial pattern in JSON onto this one. Objects are merged
+    /// field by field, arrays can be patched by index with an object such as
+    /// `{"pixels": {"0": {"1": {"color": {"r": 1.0}}}}}`, and any other value
+    /// replaces the one it targets. The result is validated like a loaded
+    /// file; on error the pattern is left unchanged.
+    pub fn apply_patch(&mut self, patch: &serde_json::Value) -> Result<(), PatternError> {
+        let mut value = serde_json::to_value(&*self)?;
+        merge_patch(&mut value, patch, "pattern")?;
+        *self = finish_loaded_pattern(serde_json::from_value(value)?)?;
+        Ok(())
+    }
+}
+
+pub fn create_base_pattern() -> Pattern {
+    Pattern {
+        base_dim: 2,
+        base_rows: 2,
+        pixels: vec![
+            vec![
+                Pixel {
+                    color: Color::new(0.2, 0.4, 0.6, 1.0), // blue
+                    perm: Permutation::rotate_90(2),
+                    decay: 1.0,
+                    weight: None,
+                },
+                Pixel {
+                    color: Color::new(0.6, 0.4, 0.2, 1.0), // bronze
+                    perm: Permutation::flip_h(2),
+                    decay: 1.0,
+                    weight: None,
+                },
+            ],
+            vec![
+                Pixel {
+                    color: Color::new(0.0, 0.0, 0.0, 1.0), // black
+                    perm: Permutation::flip_v(2),
+                    decay: 1.0,
+                    weight: None,
+                },
+                Pixel {
+                    color: Color::new(0.0, 0.0, 0.0, 0.0), // transparent
+                    perm: Permutation::identity(2),
+                    decay: 1.0,
+                    weight: None,
+                },
+            ],
+        ],
+        blend_mode: BlendMode::Lerp,
+        decay_model: DecayModel::Linear,
+        symmetry: false,
+        invert_alpha: false,
+        transparent_as_color: false,
+        premultiplied_alpha: false,
+        normalize: false,
+        normalize_mode: NormalizeMode::default(),
+        initial_pixels: None,
+        name: String::new(),
+        perm_depth_limit: None,
+        color_space: ColorSpace::default(),
+        compose_order: ComposeOrder::default(),
+        alpha_cutoff: 0.0,
+    }
+}
+
+/// Highest iteration count whose output still fits within MAX_RENDER_SIZE
+pub fn max_iterations(base_dim: usize) -> u32 {
+    iterations_within(base_dim, MAX_RENDER_SIZE)
+}
+
+/// Highest iteration count whose output is at most `max_size` pixels across,
+/// never less than one
+pub fn iterations_within(base_dim: usize, max_size: usize) -> u32 {
+    let mut iterations = 1;
+    while base_dim.pow(iterations + 1) <= max_size {
+        iterations += 1;
+    }
+    iterations
+}
+
+// Permutations reached during generation are always compositions of the pattern's
+// own permutations, so only a handful of distinct ones occur. Interning them lets
+// each cell carry a table index and expand without allocating.
+struct PermTable {
+    perms: Vec<Permutation>,
+    index: HashMap<Permutation, usize>,
+    // The base pattern as seen through each interned permutation: every cell's
+    // color, the index of its composed permutation and its pixel decay
+    expansions: Vec<Vec<Vec<Cell>>>,
+}
+
+impl PermTable {
+    fn new() -> Self {
+        PermTable {
+            perms: Vec::new(),
+            index: HashMap::new(),
+            expansions: Vec::new(),
+        }
+    }
+
+    fn intern(&mut self, perm: Permutation) -> usize {
+        if let Some(&idx) = self.index.get(&perm) {
+            return idx;
+        }
+        let idx = self.perms.len();
+        self.perms.push(perm.clone());
+        self.index.insert(perm, idx);
+        idx
+    }
+
+    // Computes expansions for every permutation interned since the last call.
+    // Permutations discovered along the way are left for the next level.
+    fn expand_pending(&mut self, pattern: &Pattern) {
+        let pending = self.perms.len();
+        while self.expansions.len() < pending {
+            let perm = self.perms[self.expansions.len()].clone();
+            let expansion = perm.apply(&pattern.pixels).iter()
+                .map(|row| row.iter()
+                    .map(|base_pixel| Cell {
+                        color: pattern.color_space.to_srgb(base_pixel.color),
+                        perm: self.intern(pattern.compose_order.compose(&perm, &base_pixel.perm)),
+                        weight: base_pixel.decay,
+                        stamp: base_pixel.weight,
+                    })
+                    .collect())
+                .collect();
+            self.expansions.push(expansion);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Cell {
+    color: Color,
+    perm: usize,
+    // Product of the pixel decays of this cell and its ancestors, scaling the
+    // blend of its children
+    weight: f32,
+    // Weight of the pixel this cell came from, if it has one; None uses the
+    // cell's alpha
+    stamp: Option<f32>,
+}
+
+/// Default cap on the memory a single render may need, in bytes
+pub const DEFAULT_MEMORY_LIMIT: u64 = 4 << 30;
+
+/// Approximate peak memory in bytes for rendering `iterations` levels of a
+/// rows x cols pattern: the working grid of cells plus the color grid it is
+/// converted to. None when the size doesn't fit in a u64 at all.
+pub fn estimate_memory(rows: usize, cols: usize, iterations: u32) -> Option<u64> {
+    let per_cell = (std::mem::size_of::<Cell>() + std::mem::size_of::<Color>()) as u64;
+    (rows as u64)
+        .checked_pow(iterations)
+        .zip((cols as u64).checked_pow(iterations))
+        .and_then(|(height, width)| height.checked_mul(width))
+        .and_then(|cells| cells.checked_mul(per_cell))
+}
+
+/// Refuses renders whose estimated memory exceeds limit, returning the
+/// estimate otherwise. Renders too large to estimate are always refused,
+/// whatever the limit.
+pub fn check_memory(rows: usize, cols: usize, iterations: u32, limit: u64) -> Result<u64, PatternError> {
+    let Some(estimate) = estimate_memory(rows, cols, iterations) else {
+        return Err(PatternError::ValidationError(format!(
+            "{} iterations of a {}x{} pattern would need more memory than can be counted",
+            iterations, cols, rows
+        )));
+    };
+    if estimate > limit {
+        return Err(PatternError::ValidationError(format!(
+            "{} iterations of a {}x{} pattern would need about {} MiB, over the {} MiB limit",
+            iterations, cols, rows, estimate >> 20, limit >> 20
+        )));
+    }
+    Ok(estimate)
+}
+
+// Output sizes at or above this are generated on the rayon thread pool
+const PARALLEL_THRESHOLD: usize = 512;
+
+fn seed_cells(pattern: &Pattern, table: &mut PermTable) -> Vec<Vec<Cell>> {
+    pattern.initial_pixels.as_ref().unwrap_or(&pattern.pixels).iter()
+        .map(|row| row.iter()
+            .map(|pixel| Cell {
+                color: pattern.color_space.to_srgb(pixel.color),
+                perm: table.intern(pixel.perm.clone()),
+                weight: pixel.decay,
+                stamp: pixel.weight,
+            })
+            .collect())
+        .collect()
+}
+
+// Blends a base pattern cell (as found in PermTable::expansions) into the
+// region covered by its parent
+fn child_cell(parent: &Cell, base: &Cell, blend: f32, pattern: &Pattern) -> Cell {
+    let alpha = if let Some(stamp) = parent.stamp {
+        stamp
+    } else if pattern.transparent_as_color {
+        1.0
+    } else {
+        let alpha = if parent.color.a < pattern.alpha_cutoff { 0.0 } else { parent.color.a };
+        if pattern.invert_alpha { 1.0 - alpha } else { alpha }
+    };
+    let color = Color { a: 1.0, ..parent.color };
+    let blend_factor = 1.0 - (1.0 - blend * parent.weight) * alpha;
+    let target = pattern.blend_mode.target(&color, &base.color);
+    Cell {
+        color: if pattern.premultiplied_alpha {
+            pattern.decay_model.mix_premultiplied(&color, &target, blend_factor)
+        } else {
+            pattern.decay_model.mix(&color, &target, blend_factor)
+        },
+        perm: base.perm,
+        weight: parent.weight * base.weight,
+        stamp: base.stamp,
+    }
+}
+
+pub fn generate_fractal(iterations: u32, pattern: &Pattern, decay: f32) -> Vec<Vec<Color>> {
+    generate_fractal_with_curve(iterations, pattern, &[decay])
+}
+
+/// Like generate_fractal, but places initial at the top level and expands it
+/// with pattern at every level below. Both must be the same size; passing the
+/// same pattern twice is the same as generate_fractal.
+pub fn generate_fractal_seeded(
+    iterations: u32,
+    initial: &Pattern,
+    pattern: &Pattern,
+    decay: f32,
+) -> Result<Vec<Vec<Color>>, PatternError> {
+    if (initial.base_rows, initial.base_dim) != (pattern.base_rows, pattern.base_dim) {
+        return Err(PatternError::ValidationError(format!(
+            "Initial pattern is {}x{} but the recursion pattern is {}x{}",
+            initial.base_dim, initial.base_rows, pattern.base_dim, pattern.base_rows
+        )));
+    }
+    let mut pattern = pattern.clone();
+    pattern.initial_pixels = Some(initial.pixels.clone());
+    Ok(generate_fractal(iterations, &pattern, decay))
+}
+
+/// Like generate_fractal, but the blend is multiplied by `decay_curve[i]` on
+/// the i-th expansion instead of a constant. Levels past the end of the curve
+/// repeat its last value; an empty curve means no decay.
+pub fn generate_fractal_with_curve(iterations: u32, pattern: &Pattern, decay_curve: &[f32]) -> Vec<Vec<Color>> {
+    generate_serial(iterations, pattern, decay_curve, &mut |_, _| true)
+        .expect("generation without cancellation always completes")
+}
+
+/// Same output as generate_fractal, but each level is double-buffered so the
+/// parent rows can be expanded independently across threads
+pub fn generate_fractal_parallel(iterations: u32, pattern: &Pattern, decay: f32) -> Vec<Vec<Color>> {
+    generate_parallel(iterations, pattern, &[decay], &mut |_, _| true)
+        .expect("generation without cancellation always completes")
+}
+
+// Decay factor for the given expansion (0 is the first)
+fn decay_at(decay_curve: &[f32], expansion: usize) -> f32 {
+    decay_curve.get(expansion).or(decay_curve.last()).copied().unwrap_or(1.0)
+}
+
+// Both generators call on_level with the number of levels completed so far
+// (the seed counts as level 1) and stop, returning None, if it returns false.
+// The second argument copies out the colors of the level just finished; it
+// is only called on demand since most callers just want the count.
+type LevelCallback<'a> = dyn FnMut(u32, &dyn Fn() -> Vec<Vec<Color>>) -> bool + 'a;
+
+fn generate_serial(
+    iterations: u32,
+    pattern: &Pattern,
+    decay_curve: &[f32],
+    on_level: &mut LevelCallback,
+) -> Option<Vec<Vec<Color>>> {
+    let (rows, cols) = (pattern.base_rows, pattern.base_dim);
+    let mut table = PermTable::new();
+    let identity = table.intern(Permutation::identity_rect(rows, cols));
+    let mut result = vec![vec![Cell {
+        color: Color::new(0.0, 0.0, 0.0, 0.0),
+        perm: 0,
+        weight: 1.0,
+        stamp: None,
+    }; cols.pow(iterations)]; rows.pow(iterations)];
+    
+    // Initialize with base pattern
+    for (y, row) in seed_cells(pattern, &mut table).into_iter().enumerate() {
+        result[y][..cols].copy_from_slice(&row);
+    }
+
+    let mut blend = 1.0;
+    let (mut current_height, mut current_width) = (rows, cols);
+    let mut level = 1;
+    
+    while level < iterations {
+        blend *= decay_at(decay_curve, level as usize - 1);
+        table.expand_pending(pattern);
+        let composing = composes_permutations(pattern, level);
+
+        // Expand in place, back to front so unread parents aren't overwritten
+        for y in (0..current_height).rev() {
+            for x in (0..current_width).rev() {
+                let cell = result[y][x];
+                let y_start = y * rows;
+                let x_start = x * cols;
+                let perm = if composing { cell.perm } else { identity };
+                
+                // Place the permuted base pattern blended with its parent
+                for (dy, base_row) in table.expansions[perm].iter().enumerate() {
+                    for (dx, base) in base_row.iter().enumerate() {
+                        result[y_start + dy][x_start + dx] = child_cell(&cell, base, blend, pattern);
+                    }
+                }
+            }
+        }
+        
+        current_height *= rows;
+        current_width *= cols;
+        level += 1;
+        // The finished level is the top-left corner of the working buffer
+        let snapshot = || level_colors(&result[..current_height], current_width, pattern);
+        if !on_level(level, &snapshot) {
+            return None;
+        }
+    }
+
+    Some(final_colors(result, pattern))
+}
+
+fn generate_parallel(
+    iterations: u32,
+    pattern: &Pattern,
+    decay_curve: &[f32],
+    on_level: &mut LevelCallback,
+) -> Option<Vec<Vec<Color>>> {
+    let (rows, cols) = (pattern.base_rows, pattern.base_dim);
+    let mut table = PermTable::new();
+    let identity = table.intern(Permutation::identity_rect(rows, cols));
+    let mut level = seed_cells(pattern, &mut table);
+    let mut level_count = 1;
+
+    let mut blend = 1.0;
+
+    while level_count < iterations {
+        blend *= decay_at(decay_curve, level_count as usize - 1);
+        table.expand_pending(pattern);
+        let table = &table;
+        let composing = composes_permutations(pattern, level_count);
+
+        let new_width = level[0].len() * cols;
+        // Built row by row, as cloning a Vec doesn't keep its capacity
+        let mut next: Vec<Vec<Cell>> = (0..level.len() * rows).map(|_| Vec::with_capacity(new_width)).collect();
+
+        // Each parent row owns the block of output rows beneath it
+        next.par_chunks_mut(rows)
+            .zip(level.par_iter())
+            .for_each(|(out_rows, row)| {
+                for cell in row {
+                    let perm = if composing { cell.perm } else { identity };
+                    for (dy, base_row) in table.expansions[perm].iter().enumerate() {
+                        out_rows[dy].extend(base_row.iter()
+                            .map(|base| child_cell(cell, base, blend, pattern)));
+                    }
+                }
+            });
+
+        level = next;
+        level_count += 1;
+        let snapshot = || level_colors(&level, new_width, pattern);
+        if !on_level(level_count, &snapshot) {
+            return None;
+        }
+    }
+
+    Some(final_colors(level, pattern))
+}
+
+// Whether the cells of the given level (the top one being 1) expand through
+// their own permutations rather than the identity
+fn composes_permutations(pattern: &Pattern, level: u32) -> bool {
+    pattern.perm_depth_limit.is_none_or(|limit| level <= limit)
+}
+
+// Copies the colors of the first width cells of each row, mirrored like final_colors
+fn level_colors(cells: &[Vec<Cell>], width: usize, pattern: &Pattern) -> Vec<Vec<Color>> {
+    let colors: Vec<Vec<Color>> = cells.iter()
+        .map(|row| row[..width].iter().map(|cell| cell.color).collect())
+        .collect();
+    if pattern.symmetry {
+        mirror_quadrant(&colors)
+    } else {
+        colors
+    }
+}
+
+// Extracts the colors of the last level, mirroring it if the pattern asks for symmetry
+fn final_colors(cells: Vec<Vec<Cell>>, pattern: &Pattern) -> Vec<Vec<Color>> {
+    let colors: Vec<Vec<Color>> = cells.into_iter()
+        .map(|row| row.into_iter().map(|cell| cell.color).collect())
+        .collect();
+    if pattern.symmetry {
+        mirror_quadrant(&colors)
+    } else {
+        colors
+    }
+}
+
+/// Reflects the top-left quadrant across both center lines, giving a result
+/// that is symmetric horizontally and vertically. Odd sizes share the middle
+/// row and column.
+pub fn mirror_quadrant<T: Clone>(grid: &[Vec<T>]) -> Vec<Vec<T>> {
+    let (width, height) = grid_size(grid);
+    (0..height).map(|y| {
+        let row = &grid[y.min(height - 1 - y)];
+        (0..width).map(|x| row[x.min(width - 1 - x)].clone()).collect()
+    }).collect()
+}
+
+/// Pairs of subtree permutations symmetry_group explores per element before
+/// giving up on it; only patterns with large custom permutations get close
+const MAX_SYMMETRY_STATES: usize = 1 << 16;
+
+/// The rotations and mirrors that map a pattern's fractal onto itself
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymmetryGroup {
+    /// Symmetries of the base grid (see Permutation::symmetries) that leave
+    /// the fractal unchanged, always including the identity
+    pub elements: Vec<Permutation>,
+}
+
+impl SymmetryGroup {
+    /// Schoenflies-style name: Cn for n rotations, Dn for n rotations
+    /// combined with mirrors
+    pub fn name(&self) -> &'static str {
+        let (rows, cols) = self.elements[0].shape();
+        let half_turn = Permutation::from_fn(rows, cols, |y, x| (rows - 1 - y, cols - 1 - x));
+        match self.elements.len() {
+            1 => "trivial",
+            2 if self.elements.contains(&half_turn) => "C2",
+            2 => "D1",
+            4 if self.elements.contains(&Permutation::rotate_90(rows)) => "C4",
+            4 => "D2",
+            _ => "D4",
+        }
+    }
+}
+
+/// Finds which grid symmetries leave the fractal of a pattern unchanged at
+/// every depth, from its arrangement of colors, decays and permutations
+/// (including the initial pixels, if any).
+/// The pattern's symmetry option, which mirrors the result afterwards, and
+/// its permutation depth limit are not taken into account.
+///
+/// A symmetry g of the whole grid acts on every level of the recursion at
+/// once, so it maps a cell expanded with permutation R onto one expanded
+/// with some R'. Starting from the top level, where both are the identity,
+/// each such pair matches the base cells k and R'^-1(g(R(k))), which must
+/// look the same and lead on to further pairs.
+pub fn symmetry_group(pattern: &Pattern) -> SymmetryGroup {
+    let (rows, cols) = (pattern.base_rows, pattern.base_dim);
+    let pixels: Vec<&Pixel> = pattern.pixels.iter().flatten().collect();
+    let initial: Vec<&Pixel> = pattern.initial_pixels.as_ref().unwrap_or(&pattern.pixels).iter().flatten().collect();
+    let index = |(y, x): (usize, usize)| y * cols + x;
+    let looks_same = |pixel: &Pixel, image: &Pixel| {
+        pixel.color == image.color && pixel.decay == image.decay && pixel.weight == image.weight
+    };
+
+    let is_symmetry = |g: &Permutation| {
+        // The top level holds the initial pixels, which g matches directly
+        let mut pending = Vec::new();
+        for (k, &target) in g.mapping.iter().enumerate() {
+            let (pixel, image) = (initial[k], initial[index(target)]);
+            if !looks_same(pixel, image) {
+                return false;
+            }
+            pending.push((pixel.perm.clone(), image.perm.clone()));
+        }
+
+        let mut seen = HashSet::new();
+        while let Some((from, to)) = pending.pop() {
+            if !seen.insert((from.clone(), to.clone())) {
+                continue;
+            }
+            if seen.len() > MAX_SYMMETRY_STATES {
+                return false;
+            }
+            let matching = from.compose(g).compose(&to.inverse());
+            for (k, &target) in matching.mapping.iter().enumerate() {
+                let (pixel, image) = (pixels[k], pixels[index(target)]);
+                if !looks_same(pixel, image) {
+                    return false;
+                }
+                pending.push((
+                    pattern.compose_order.compose(&from, &pixel.perm),
+                    pattern.compose_order.compose(&to, &image.perm),
+                ));
+            }
+        }
+        true
+    };
+
+    SymmetryGroup {
+        elements: Permutation::symmetries(rows, cols).into_iter().filter(|g| is_symmetry(g)).collect(),
+    }
+}
+
+/// (width, height) of a rendered grid, which is wider than tall for patterns
+/// with more columns than rows
+pub fn grid_size<T>(grid: &[Vec<T>]) -> (usize, usize) {
+    (grid.first().map_or(0, Vec::len), grid.len())
+}
+
+/// Renders a pattern to a grid of colors, picking the serial or parallel
+/// generator based on the output size
+pub fn render_pattern(pattern: &Pattern, iterations: u32, decay: f32) -> Vec<Vec<Color>> {
+    render_pattern_with_curve(pattern, iterations, &[decay])
+}
+
+/// render_pattern with a per-level decay curve; see generate_fractal_with_curve
+pub fn render_pattern_with_curve(pattern: &Pattern, iterations: u32, decay_curve: &[f32]) -> Vec<Vec<Color>> {
+    render_pattern_with_progress(pattern, iterations, decay_curve, |_| true)
+        .expect("generation without cancellation always completes")
+}
+
+/// Like render_pattern_with_curve, but calls on_level after each recursion
+/// level with the number of levels completed out of iterations. Returning
+/// false from on_level cancels the render and yields None.
+pub fn render_pattern_with_progress(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    mut on_level: impl FnMut(u32) -> bool,
+) -> Option<Vec<Vec<Color>>> {
+    generate_with(pattern, iterations, decay_curve, &mut |level, _| on_level(level))
+}
+
+/// Like render_pattern_with_progress, but also hands on_level the colors of
+/// each intermediate level, so a caller can show the fractal as it grows.
+/// Each grid is a copy, free to keep or send to another thread. The last
+/// level isn't passed since it is the return value.
+pub fn render_pattern_progressive(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    mut on_level: impl FnMut(u32, &[Vec<Color>]) -> bool,
+) -> Option<Vec<Vec<Color>>> {
+    generate_with(pattern, iterations, decay_curve, &mut |level, snapshot| {
+        level >= iterations || on_level(level, &snapshot())
+    })
+}
+
+/// Renders only the width x height block of the final grid starting at
+/// (x, y), with the same colors the whole render has there. Each level keeps
+/// just the cells whose descendants fall in the block, so memory follows the
+/// block's size rather than the image's. Normalizing looks at the whole
+/// image, so patterns with it on are refused.
+pub fn generate_region(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    (x, y): (usize, usize),
+    (width, height): (usize, usize),
+) -> Result<Vec<Vec<Color>>, PatternError> {
+    if pattern.normalize {
+        return Err(PatternError::ValidationError(
+            "Normalizing needs the whole image, so it can't be rendered in parts".to_string()
+        ));
+    }
+    let full_size = pattern.base_dim.checked_pow(iterations).zip(pattern.base_rows.checked_pow(iterations));
+    let Some((full_width, full_height)) = full_size else {
+        return Err(PatternError::ValidationError(format!("{} iterations is too many to render", iterations)));
+    };
+    if x.saturating_add(width) > full_width || y.saturating_add(height) > full_height {
+        return Err(PatternError::ValidationError(format!(
+            "Region {}x{} at ({}, {}) lies outside the {}x{} image",
+            width, height, x, y, full_width, full_height
+        )));
+    }
+    if width == 0 || height == 0 {
+        return Ok(vec![Vec::new(); height]);
+    }
+    if !pattern.symmetry {
+        return Ok(generate_window(pattern, iterations, decay_curve, (x, y), (width, height)));
+    }
+
+    // Mirrored cells come from the top-left quadrant (see mirror_quadrant),
+    // so render the part of it the region maps onto and look them up there
+    let source_range = |start: usize, len: usize, full: usize| {
+        let mirror = move |i: usize| i.min(full - 1 - i);
+        let (first, last) = (mirror(start), mirror(start + len - 1));
+        let middle = (full - 1) / 2;
+        let high = if start <= middle && middle < start + len { middle } else { first.max(last) };
+        (first.min(last), high + 1 - first.min(last), mirror)
+    };
+    let (source_x, source_width, mirror_x) = source_range(x, width, full_width);
+    let (source_y, source_height, mirror_y) = source_range(y, height, full_height);
+    let source = generate_window(pattern, iterations, decay_curve, (source_x, source_y), (source_width, source_height));
+    Ok((y..y + height).map(|row| {
+        let source_row = &source[mirror_y(row) - source_y];
+        (x..x + width).map(|col| source_row[mirror_x(col) - source_x]).collect()
+    }).collect())
+}
+
+// generate_region without mirroring, for a block known to lie in the image
+fn generate_window(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    (x, y): (usize, usize),
+    (width, height): (usize, usize),
+) -> Vec<Vec<Color>> {
+    let (rows, cols) = (pattern.base_rows, pattern.base_dim);
+    let mut table = PermTable::new();
+    let identity = table.intern(Permutation::identity_rect(rows, cols));
+    let seeds = seed_cells(pattern, &mut table);
+
+    // Cells of the given level covering the block: first row and column,
+    // and one past the last
+    let window_at = |level: u32| {
+        let (span_y, span_x) = (rows.pow(iterations - level), cols.pow(iterations - level));
+        (y / span_y, (y + height).div_ceil(span_y), x / span_x, (x + width).div_ceil(span_x))
+    };
+    let (mut top, bottom, mut left, right) = window_at(1);
+    let mut cells: Vec<Vec<Cell>> = seeds[top..bottom].iter().map(|row| row[left..right].to_vec()).collect();
+
+    let mut blend = 1.0;
+    for level in 1..iterations {
+        blend *= decay_at(decay_curve, level as usize - 1);
+        table.expand_pending(pattern);
+        let composing = composes_permutations(pattern, level);
+        let (next_top, next_bottom, next_left, next_right) = window_at(level + 1);
+        cells = (next_top..next_bottom).map(|cy| {
+            (next_left..next_right).map(|cx| {
+                let parent = &cells[cy / rows - top][cx / cols - left];
+                let perm = if composing { parent.perm } else { identity };
+                child_cell(parent, &table.expansions[perm][cy % rows][cx % cols], blend, pattern)
+            }).collect()
+        }).collect();
+        (top, left) = (next_top, next_left);
+    }
+
+    cells.into_iter()
+        .map(|row| row.into_iter().map(|cell| cell.color).collect())
+        .collect()
+}
+
+fn generate_with(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    on_level: &mut LevelCallback,
+) -> Option<Vec<Vec<Color>>> {
+    let mut fractal = if pattern.longest_side().pow(iterations) >= PARALLEL_THRESHOLD {
+        generate_parallel(iterations, pattern, decay_curve, on_level)?
+    } else {
+        generate_serial(iterations, pattern, decay_curve, on_level)?
+    };
+    if pattern.normalize {
+        normalize_range(&mut fractal, pattern.normalize_mode);
+    }
+    Some(fractal)
+}
+
+/// A separately rendered pattern composited over the base fractal
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    #[serde(default)]
+    pub name: String,
+    pub pattern: Pattern,
+    /// How the layer's colors combine with the ones beneath it
+    pub blend_mode: BlendMode,
+    pub opacity: f32,
+}
+
+// Like Pattern, leaves out the name, which doesn't change the render
+impl Hash for Layer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pattern.hash(state);
+        self.blend_mode.hash(state);
+        self.opacity.to_bits().hash(state);
+    }
+}
+
+/// Renders each layer with `render` and composites it over `base`, first
+/// layer lowest. Each layer's color is blended with what is beneath using its
+/// mode and then laid over it with alpha scaled by its opacity. Layers whose
+/// render doesn't match the base size are skipped.
+pub fn composite_layers(
+    base: &mut [Vec<Color>],
+    layers: &[Layer],
+    mut render: impl FnMut(&Pattern) -> Vec<Vec<Color>>,
+) {
+    for layer in layers {
+        if layer.opacity <= 0.0 {
+            continue;
+        }
+        let top = render(&layer.pattern);
+        if grid_size(&top) != grid_size(base) {
+            continue;
+        }
+        for (base_row, top_row) in base.iter_mut().zip(&top) {
+            for (below, above) in base_row.iter_mut().zip(top_row) {
+                let blended = layer.blend_mode.target(below, above);
+                let color = Color { a: above.a * layer.opacity, ..blended };
+                *below = color.over(below);
+            }
+        }
+    }
+}
+
+/// Renders extra_levels more iterations than requested and box-filters the
+/// result back down to the size of `iterations`, for anti-aliased output
+pub fn render_supersampled(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    extra_levels: u32,
+) -> Vec<Vec<Color>> {
+    let fractal = render_pattern_with_curve(pattern, iterations + extra_levels, decay_curve);
+    downsample_rect(&fractal, pattern.base_dim.pow(extra_levels), pattern.base_rows.pow(extra_levels))
+}
+
+/// Averages each factor x factor block into one cell with Color::average_linear
+pub fn downsample(fractal: &[Vec<Color>], factor: usize) -> Vec<Vec<Color>> {
+    downsample_rect(fractal, factor, factor)
+}
+
+/// downsample with separate horizontal and vertical factors, for renders of
+/// rectangular patterns
+pub fn downsample_rect(fractal: &[Vec<Color>], factor_x: usize, factor_y: usize) -> Vec<Vec<Color>> {
+    if factor_x <= 1 && factor_y <= 1 {
+        return fractal.to_vec();
+    }
+    let (factor_x, factor_y) = (factor_x.max(1), factor_y.max(1));
+    let (width, height) = grid_size(fractal);
+    let mut block = Vec::with_capacity(factor_x * factor_y);
+    (0..height / factor_y).map(|y| {
+        (0..width / factor_x).map(|x| {
+            block.clear();
+            for row in &fractal[y * factor_y..(y + 1) * factor_y] {
+                block.extend_from_slice(&row[x * factor_x..(x + 1) * factor_x]);
+            }
+            Color::average_linear(&block)
+        }).collect()
+    }).collect()
+}
+
+/// The width x height block of cells starting at (x, y), clamped to the
+/// grid's bounds
+pub fn crop_fractal(fractal: &[Vec<Color>], x: usize, y: usize, width: usize, height: usize) -> Vec<Vec<Color>> {
+    let (grid_width, grid_height) = grid_size(fractal);
+    let (x, y) = (x.min(grid_width), y.min(grid_height));
+    let (right, bottom) = ((x + width).min(grid_width), (y + height).min(grid_height));
+    fractal[y..bottom].iter().map(|row| row[x..right].to_vec()).collect()
+}
+
+/// Renders a pattern straight to an 8-bit RGBA image
+pub fn render_image(pattern: &Pattern, iterations: u32, decay: f32) -> RgbaImage {
+    fractal_to_image(&render_pattern(pattern, iterations, decay))
+}
+
+/// Converts a generated color grid to an 8-bit RGBA image
+pub fn fractal_to_image(fractal: &[Vec<Color>]) -> RgbaImage {
+    fractal_to_image_with(fractal, Color::to_rgba)
+}
+
+/// The base pattern's own colors, without recursion, as an image with each
+/// cell drawn as a scale x scale block
+pub fn base_tile_image(pattern: &Pattern, scale: u32) -> RgbaImage {
+    let colors: Vec<Vec<Color>> = pattern.pixels.iter()
+        .map(|row| row.iter().map(|pixel| pattern.color_space.to_srgb(pixel.color)).collect())
+        .collect();
+    let image = fractal_to_image(&colors);
+    let scale = scale.max(1);
+    imageops::resize(&image, image.width() * scale, image.height() * scale, FilterType::Nearest)
+}
+
+fn fractal_to_image_with(fractal: &[Vec<Color>], to_rgba: impl Fn(Color) -> Rgba<u8>) -> RgbaImage {
+    let (width, height) = grid_size(fractal);
+    let mut image = RgbaImage::new(width as u32, height as u32);
+    for (y, row) in fractal.iter().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            image.put_pixel(x as u32, y as u32, to_rgba(color));
+        }
+    }
+    image
+}
+
+/// Settings applied when turning a rendered fractal into an exported image
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    /// Gamma encoding applied to RGB; 1.0 writes the blended values unchanged
+    pub gamma: f32,
+    /// Write 16 bits per channel instead of 8 to preserve subtle gradients
+    pub sixteen_bit: bool,
+    /// Width and height to resample the image to; None keeps the natural size
+    pub resize: Option<(u32, u32)>,
+    /// Resample bilinearly instead of with nearest-neighbour sampling
+    pub smooth_resize: bool,
+    /// Color composited underneath the fractal; None keeps transparency
+    pub background: Option<Color>,
+    /// Blend opposite edges so the image wraps without a visible seam
+    pub seamless: bool,
+    /// Write a 2x2 grid of the image to check how it tiles
+    pub tiled: bool,
+    /// Bake a color vision simulation into the image
+    pub color_vision: ColorVision,
+    /// Width in pixels of a solid margin added around the image; 0 for none
+    pub border: u32,
+    /// Color of the margin, gamma-encoded like the fractal so it matches an
+    /// identical background
+    pub border_color: Color,
+    /// Text drawn into a corner of the finished image; None for no caption
+    pub caption: Option<Caption>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            gamma: 1.0,
+            sixteen_bit: false,
+            resize: None,
+            smooth_resize: false,
+            background: None,
+            seamless: false,
+            tiled: false,
+            color_vision: ColorVision::Normal,
+            border: 0,
+            border_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            caption: None,
+        }
+    }
+}
+
+/// Font captions are drawn with, bundled so exports look the same everywhere
+const CAPTION_FONT: &[u8] = include_bytes!("../assets/Hack-Regular.ttf");
+
+/// Smallest caption text height in pixels, used for small images
+const MIN_CAPTION_SIZE: f32 = 12.0;
+
+/// Corner of the image a caption is drawn in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CaptionCorner {
+    BottomLeft,
+    #[default]
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+impl CaptionCorner {
+    pub const ALL: [CaptionCorner; 4] = [
+        CaptionCorner::BottomLeft,
+        CaptionCorner::BottomRight,
+        CaptionCorner::TopLeft,
+        CaptionCorner::TopRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CaptionCorner::BottomLeft => "Bottom left",
+            CaptionCorner::BottomRight => "Bottom right",
+            CaptionCorner::TopLeft => "Top left",
+            CaptionCorner::TopRight => "Top right",
+        }
+    }
+}
+
+/// Text stamped onto an exported image, such as attribution or the settings
+/// it was rendered with. Each line of the text is aligned to the corner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caption {
+    pub text: String,
+    pub corner: CaptionCorner,
+    pub color: Color,
+}
+
+impl ExportOptions {
+    /// The per-color steps of an export: compositing over the background,
+    /// color vision simulation, then gamma encoding
+    pub fn encode(&self, color: Color) -> Color {
+        let color = match &self.background {
+            Some(background) => color.over(background),
+            None => color,
+        };
+        self.color_vision.simulate(color).encode_gamma(self.gamma)
+    }
+}
+
+/// 8-bit image of a grid with only the per-color export steps applied (see
+/// ExportOptions::encode). The preview is built with this too, so the bytes
+/// it displays match the ones written.
+pub fn encode_fractal(fractal: &[Vec<Color>], options: &ExportOptions) -> RgbaImage {
+    fractal_to_image_with(fractal, |color| options.encode(color).to_rgba())
+}
+
+/// Converts a generated color grid to an image using the given export settings
+pub fn export_image(fractal: &[Vec<Color>], options: &ExportOptions) -> DynamicImage {
+    let blended;
+    let fractal = if options.seamless {
+        blended = seamless_tile(fractal);
+        &blended
+    } else {
+        fractal
+    };
+
+    let image = if options.sixteen_bit {
+        let (width, height) = grid_size(fractal);
+        DynamicImage::ImageRgba16(ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+            Rgba(options.encode(fractal[y as usize][x as usize]).to_rgba16())
+        }))
+    } else {
+        DynamicImage::ImageRgba8(encode_fractal(fractal, options))
+    };
+
+    let image = match options.resize {
+        Some((width, height)) if (width, height) != (image.width(), image.height()) => {
+            let filter = if options.smooth_resize { FilterType::Triangle } else { FilterType::Nearest };
+            image.resize_exact(width, height, filter)
+        }
+        _ => image,
+    };
+
+    let image = if options.tiled {
+        tile_image(&image, 2, 2)
+    } else {
+        image
+    };
+
+    let mut image = if options.border > 0 {
+        let color = options.color_vision.simulate(options.border_color).encode_gamma(options.gamma);
+        add_border(image, options.border, color)
+    } else {
+        image
+    };
+
+    if let Some(caption) = &options.caption {
+        draw_caption(&mut image, caption);
+    }
+    image
+}
+
+/// Draws a caption over an image with the bundled font, scaled to the image
+/// so it stays legible on large exports and fits on small ones
+pub fn draw_caption(image: &mut DynamicImage, caption: &Caption) {
+    let font = FontRef::try_from_slice(CAPTION_FONT).expect("the bundled font is valid");
+    let size = (image.width().min(image.height()) as f32 / 24.0).max(MIN_CAPTION_SIZE);
+    let font = font.as_scaled(PxScale::from(size));
+    let margin = size / 2.0;
+    let lines: Vec<&str> = caption.text.lines().collect();
+    let line_height = font.height() + font.line_gap();
+    let top = match caption.corner {
+        CaptionCorner::TopLeft | CaptionCorner::TopRight => margin,
+        CaptionCorner::BottomLeft | CaptionCorner::BottomRight => {
+            image.height() as f32 - margin - line_height * lines.len() as f32
+        }
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        let glyph_ids: Vec<_> = line.chars().map(|c| font.glyph_id(c)).collect();
+        let width: f32 = glyph_ids.iter().map(|&id| font.h_advance(id)).sum::<f32>()
+            + glyph_ids.windows(2).map(|pair| font.kern(pair[0], pair[1])).sum::<f32>();
+        let mut caret = match caption.corner {
+            CaptionCorner::TopLeft | CaptionCorner::BottomLeft => margin,
+            CaptionCorner::TopRight | CaptionCorner::BottomRight => image.width() as f32 - margin - width,
+        };
+        let baseline = top + i as f32 * line_height + font.ascent();
+
+        for (j, &id) in glyph_ids.iter().enumerate() {
+            if j > 0 {
+                caret += font.kern(glyph_ids[j - 1], id);
+            }
+            let glyph = id.with_scale_and_position(size, point(caret, baseline));
+            caret += font.h_advance(id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let (x, y) = (bounds.min.x as i64 + x as i64, bounds.min.y as i64 + y as i64);
+                if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+                    return;
+                }
+                let (x, y) = (x as u32, y as u32);
+                let [r, g, b, a] = image.get_pixel(x, y).0;
+                let background = Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0);
+                let text = Color { a: caption.color.a * coverage.min(1.0), ..caption.color };
+                image.put_pixel(x, y, text.over(&background).to_rgba());
+            });
+        }
+    }
+}
+
+/// Surrounds an image with a margin of the given width. The image replaces
+/// the pixels it covers rather than being composited, so its transparent
+/// cells stay transparent instead of showing the border color.
+pub fn add_border(image: DynamicImage, width: u32, color: Color) -> DynamicImage {
+    let (framed_width, framed_height) = (image.width() + 2 * width, image.height() + 2 * width);
+    match image {
+        DynamicImage::ImageRgba16(inner) => {
+            let mut framed = ImageBuffer::from_pixel(framed_width, framed_height, Rgba(color.to_rgba16()));
+            imageops::replace(&mut framed, &inner, width as i64, width as i64);
+            DynamicImage::ImageRgba16(framed)
+        }
+        image => {
+            let mut framed = RgbaImage::from_pixel(framed_width, framed_height, color.to_rgba());
+            imageops::replace(&mut framed, &image.into_rgba8(), width as i64, width as i64);
+            DynamicImage::ImageRgba8(framed)
+        }
+    }
+}
+
+/// Whether the first row and column equal the last, so copies placed side by
+/// side continue without a jump
+pub fn edges_wrap(fractal: &[Vec<Color>]) -> bool {
+    let (Some(first), Some(last)) = (fractal.first(), fractal.last()) else {
+        return true;
+    };
+    first == last && fractal.iter().all(|row| row.first() == row.last())
+}
+
+/// Cross-fades each edge band with the opposite edge. The outermost rows and
+/// columns become the average of both sides, so the result always satisfies
+/// `edges_wrap`.
+pub fn seamless_tile(fractal: &[Vec<Color>]) -> Vec<Vec<Color>> {
+    let (width, height) = grid_size(fractal);
+    let mut result = fractal.to_vec();
+
+    // Columns first, then rows so the corners settle on a four-way average
+    let band = (width / 8).max(1);
+    for row in result.iter_mut() {
+        for i in 0..band.min(width / 2) {
+            let t = 0.5 * (1.0 - i as f32 / band as f32);
+            let (left, right) = (row[i], row[width - 1 - i]);
+            (row[i], row[width - 1 - i]) = cross_fade(left, right, t);
+        }
+    }
+    let band = (height / 8).max(1);
+    for i in 0..band.min(height / 2) {
+        let t = 0.5 * (1.0 - i as f32 / band as f32);
+        let (upper, lower) = result.split_at_mut(height - 1 - i);
+        for (top, bottom) in upper[i].iter_mut().zip(lower[0].iter_mut()) {
+            (*top, *bottom) = cross_fade(*top, *bottom, t);
+        }
+    }
+    result
+}
+
+// Moves two colors toward each other by t; at t = 0.5 both become the exact
+// same value, which lerp alone doesn't guarantee in floating point
+fn cross_fade(a: Color, b: Color, t: f32) -> (Color, Color) {
+    if t == 0.5 {
+        let mid = a.lerp(&b, 0.5);
+        (mid, mid)
+    } else {
+        (a.lerp(&b, t), b.lerp(&a, t))
+    }
+}
+
+/// JPEG quality used when none is given, from 1 (smallest) to 100 (best)
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// The export format for a path's extension: PNG, JPEG, WebP or TIFF
+pub fn export_format(path: &Path) -> Result<ImageFormat, PatternError> {
+    match ImageFormat::from_path(path) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Tiff)) => Ok(format),
+        _ => Err(PatternError::ValidationError(format!(
+            "Unsupported export format for {}; use .png, .jpg, .webp or .tiff",
+            path.display()
+        ))),
+    }
+}
+
+/// Writes an exported image in the format given by the path's extension.
+/// JPEG has no alpha channel, so any transparency left in the image is
+/// flattened onto white; WebP is written losslessly at 8 bits per channel.
+pub fn save_image(image: &DynamicImage, path: &Path, jpeg_quality: u8) -> Result<(), PatternError> {
+    match export_format(path)? {
+        ImageFormat::Jpeg => {
+            let white = Color::new(1.0, 1.0, 1.0, 1.0);
+            let rgba = image.to_rgba8();
+            let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let [r, g, b, a] = rgba.get_pixel(x, y).0;
+                let color = Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0);
+                let [r, g, b, _] = color.over(&white).to_rgba().0;
+                Rgb([r, g, b])
+            });
+            let file = BufWriter::new(fs::File::create(path)?);
+            JpegEncoder::new_with_quality(file, jpeg_quality.clamp(1, 100)).encode_image(&rgb)?;
+        }
+        ImageFormat::WebP => image.to_rgba8().save_with_format(path, ImageFormat::WebP)?,
+        format => image.save_with_format(path, format)?,
+    }
+    Ok(())
+}
+
+/// Repeats an image in a cols x rows grid
+pub fn tile_image(image: &DynamicImage, cols: u32, rows: u32) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let mut tiled = DynamicImage::new(width * cols, height * rows, image.color());
+    for row in 0..rows {
+        for col in 0..cols {
+            imageops::replace(&mut tiled, image, (col * width) as i64, (row * height) as i64);
+        }
+    }
+    tiled
+}
+
+/// Luminance distribution of a rendered fractal
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    /// Counts of visible cells in equal-width luminance bins from 0 to 1
+    pub bins: Vec<u32>,
+    /// Fully transparent cells, which are left out of the bins
+    pub transparent: u32,
+}
+
+/// Sorts the visible cells into bin_count luminance bins, counting fully
+/// transparent cells separately. With no bins only the transparent cells
+/// are counted.
+pub fn luminance_histogram(fractal: &[Vec<Color>], bin_count: usize) -> Histogram {
+    let mut histogram = Histogram { bins: vec![0; bin_count], transparent: 0 };
+    for color in fractal.iter().flatten() {
+        if color.a == 0.0 {
+            histogram.transparent += 1;
+            continue;
+        }
+        if bin_count == 0 {
+            continue;
+        }
+        let bin = (color.luminance().clamp(0.0, 1.0) * bin_count as f32) as usize;
+        histogram.bins[bin.min(bin_count - 1)] += 1;
+    }
+    histogram
+}
+
+/// Box-counting estimate of a rendered fractal's dimension
+#[derive(Clone, Debug)]
+pub struct BoxDimension {
+    /// Slope of log(occupied boxes) against log(1 / box size)
+    pub dimension: f32,
+    /// Edge length of the boxes at each scale with the number of boxes
+    /// holding at least one counted cell, smallest boxes first
+    pub counts: Vec<(usize, usize)>,
+}
+
+/// Estimates the box-counting dimension of the cells whose alpha is above
+/// alpha_threshold. Boxes double in size from single cells while at least
+/// two fit along the shorter side, and a least-squares line through the
+/// log-log counts gives the dimension. None when nothing is counted or the
+/// grid is too small for two scales.
+pub fn box_counting_dimension(fractal: &[Vec<Color>], alpha_threshold: f32) -> Option<BoxDimension> {
+    let (width, height) = grid_size(fractal);
+    let mut occupied: Vec<Vec<bool>> = fractal.iter()
+        .map(|row| row.iter().map(|color| color.a > alpha_threshold).collect())
+        .collect();
+    let mut counts = Vec::new();
+    let mut size = 1;
+    while size * 2 <= width.min(height) {
+        counts.push((size, occupied.iter().flatten().filter(|&&cell| cell).count()));
+        // Each box of the next scale covers a 2x2 block of this one's,
+        // which is cut short at the right and bottom edges
+        occupied = occupied.chunks(2)
+            .map(|rows| (0..rows[0].len().div_ceil(2))
+                .map(|x| rows.iter().any(|row| row[2 * x..(2 * x + 2).min(row.len())].iter().any(|&cell| cell)))
+                .collect())
+            .collect();
+        size *= 2;
+    }
+    if counts.len() < 2 || counts[0].1 == 0 {
+        return None;
+    }
+
+    let points: Vec<(f32, f32)> = counts.iter()
+        .map(|&(size, count)| (-(size as f32).ln(), (count as f32).ln()))
+        .collect();
+    let n = points.len() as f32;
+    let mean_x = points.iter().map(|p| p.0).sum::<f32>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f32>() / n;
+    let covariance: f32 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let variance: f32 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    Some(BoxDimension { dimension: covariance / variance, counts })
+}
+
+/// FNV-1a over the grid size and its 8-bit RGBA bytes in row-major order.
+/// Unlike std's hashers the result is fixed across Rust versions and
+/// platforms, so it can be recorded and compared later.
+pub fn fractal_digest(fractal: &[Vec<Color>]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let size = (fractal.len() as u64).to_le_bytes();
+    let bytes = fractal.iter().flatten().flat_map(|color| color.to_rgba().0);
+    size.into_iter().chain(bytes).fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// Renders a pattern and returns the digest of its output, for checking that
+/// the same inputs still produce the same image
+pub fn render_digest(pattern: &Pattern, iterations: u32, decay: f32) -> u64 {
+    fractal_digest(&render_pattern(pattern, iterations, decay))
+}
+
+/// Distinct colors in a rendered fractal after quantizing to 8 bits per channel
+pub struct ColorCounts {
+    /// Number of cells counted
+    pub cells: usize,
+    /// Number of distinct quantized colors
+    pub unique: usize,
+    /// The most frequent colors with their cell counts, most common first
+    pub top: Vec<(Rgba<u8>, u32)>,
+}
+
+/// Counts distinct 8-bit colors, keeping the top_n most frequent. Ties are
+/// ordered by color so the result is deterministic.
+pub fn color_counts(fractal: &[Vec<Color>], top_n: usize) -> ColorCounts {
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for color in fractal.iter().flatten() {
+        *counts.entry(color.to_rgba().0).or_insert(0) += 1;
+    }
+    let unique = counts.len();
+    let mut top: Vec<_> = counts.into_iter().collect();
+    top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    top.truncate(top_n);
+    ColorCounts {
+        cells: fractal.iter().map(Vec::len).sum(),
+        unique,
+        top: top.into_iter().map(|(rgba, count)| (Rgba(rgba), count)).collect(),
+    }
+}
+
+// Rendered grid as written by fractal_to_json
+#[derive(Serialize)]
+struct GridFile<'a> {
+    iterations: u32,
+    decay: &'a [f32],
+    // Edge length, written only for square grids as before rectangular tiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    width: usize,
+    height: usize,
+    colors: GridColors<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum GridColors<'a> {
+    Nested(&'a [Vec<Color>]),
+    Flat(Vec<f32>),
+}
+
+/// Serializes a rendered grid with the settings it came from. `flat` writes
+/// the colors as one row-major [r, g, b, a, r, g, b, a, ...] array, which is
+/// much smaller than the default rows of {r, g, b, a} objects.
+pub fn fractal_to_json(
+    fractal: &[Vec<Color>],
+    iterations: u32,
+    decay_curve: &[f32],
+    flat: bool,
+) -> Result<String, PatternError> {
+    let colors = if flat {
+        GridColors::Flat(fractal.iter().flatten().flat_map(|c| [c.r, c.g, c.b, c.a]).collect())
+    } else {
+        GridColors::Nested(fractal)
+    };
+    let (width, height) = grid_size(fractal);
+    let size = (width == height).then_some(width);
+    let grid = GridFile { iterations, decay: decay_curve, size, width, height, colors };
+    Ok(serde_json::to_string(&grid)?)
+}
+
+/// Largest fractal width or height exported as SVG; every cell becomes a <rect>
+pub const MAX_SVG_SIZE: usize = 256;
+
+/// Writes one rect per fractal cell into an SVG document. Fully transparent
+/// cells are omitted and partial alpha becomes fill-opacity.
+pub fn fractal_to_svg(fractal: &[Vec<Color>]) -> String {
+    let (width, height) = grid_size(fractal);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" shape-rendering="crispEdges">"#
+    );
+    for (y, row) in fractal.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            let [r, g, b, a] = color.to_rgba().0;
+            if a == 0 {
+                continue;
+            }
+            let _ = write!(svg, r##"<rect x="{x}" y="{y}" width="1" height="1" fill="#{r:02x}{g:02x}{b:02x}""##);
+            if a < 255 {
+                let _ = write!(svg, r#" fill-opacity="{:.3}""#, a as f32 / 255.0);
+            }
+            svg.push_str("/>\n");
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Display time of each frame in an exported animation
+pub const ANIMATION_FRAME_MS: u32 = 100;
+
+/// Renders the pattern with its layers once per frame with decay swept
+/// linearly from start_decay to end_decay, converting each frame with
+/// export_image and writing them as a looping GIF, which is always 8-bit.
+/// Frames are rendered in parallel and encoded in order. on_frame is called
+/// from the rendering threads with the number of frames rendered so far,
+/// so calls may arrive slightly out of order.
+#[allow(clippy::too_many_arguments)]
+pub fn export_decay_animation(
+    pattern: &Pattern,
+    layers: &[Layer],
+    iterations: u32,
+    start_decay: f32,
+    end_decay: f32,
+    frame_count: usize,
+    options: &ExportOptions,
+    path: &Path,
+    on_frame: impl Fn(usize) + Sync,
+) -> Result<(), ImageError> {
+    let decay_at_frame = |frame: usize| {
+        let t = if frame_count > 1 {
+            frame as f32 / (frame_count - 1) as f32
+        } else {
+            0.0
+        };
+        start_decay + (end_decay - start_decay) * t
+    };
+    write_animation(
+        path,
+        frame_count,
+        |frame| {
+            let decay = decay_at_frame(frame);
+            let mut fractal = render_pattern(pattern, iterations, decay);
+            composite_layers(&mut fractal, layers, |layer| render_pattern(layer, iterations, decay));
+            export_image(&fractal, options).to_rgba8()
+        },
+        on_frame,
+    )
+}
+
+/// Saves every recursion level as level_01.png through level_N.png in dir,
+/// N being iterations. Each level is scaled up without smoothing to the size
+/// of the last one, so the images line up when compared.
+pub fn export_levels(pattern: &Pattern, iterations: u32, decay_curve: &[f32], dir: &Path) -> Result<(), ImageError> {
+    let (width, height) = (pattern.base_dim.pow(iterations) as u32, pattern.base_rows.pow(iterations) as u32);
+    let save_level = |level: u32, fractal: &[Vec<Color>]| {
+        let image = imageops::resize(&fractal_to_image(fractal), width, height, FilterType::Nearest);
+        image.save(dir.join(format!("level_{:02}.png", level)))
+    };
+
+    // The seed isn't passed to the level callback, so it is rendered on its own
+    save_level(1, &render_pattern_with_curve(pattern, 1, decay_curve))?;
+    if iterations <= 1 {
+        return Ok(());
+    }
+    let mut error = None;
+    let fractal = render_pattern_progressive(pattern, iterations, decay_curve, |level, fractal| {
+        match save_level(level, fractal) {
+            Ok(()) => true,
+            Err(e) => {
+                error = Some(e);
+                false
+            }
+        }
+    });
+    match (fractal, error) {
+        (_, Some(e)) => Err(e),
+        (Some(fractal), None) => save_level(iterations, &fractal),
+        (None, None) => unreachable!("levels are only cancelled after a failed save"),
+    }
+}
+
+/// Side length of the blocks export_png_chunked renders at a time
+pub const CHUNK_SIZE: usize = 512;
+
+/// Writes the fractal as a PNG without ever holding the whole image: it is
+/// rendered in CHUNK_SIZE blocks with generate_region, a band of rows at a
+/// time, and each band is streamed to the encoder. Only the per-color export
+/// steps and the bit depth of options apply; resizing, tiling, borders and
+/// captions need the whole image. on_band gets the number of bands written.
+pub fn export_png_chunked(
+    pattern: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    options: &ExportOptions,
+    path: &Path,
+    on_band: impl Fn(usize),
+) -> Result<(), PatternError> {
+    let too_large = || PatternError::ValidationError(format!("{} iterations is too large for a PNG", iterations));
+    let width = pattern.base_dim.checked_pow(iterations).ok_or_else(too_large)?;
+    let height = pattern.base_rows.checked_pow(iterations).ok_or_else(too_large)?;
+    let (Ok(png_width), Ok(png_height)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err(too_large());
+    };
+    let png_error = |e: png::EncodingError| {
+        PatternError::ImageError(ImageError::Encoding(image::error::EncodingError::new(ImageFormat::Png.into(), e)))
+    };
+
+    let mut encoder = png::Encoder::new(BufWriter::new(fs::File::create(path)?), png_width, png_height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(if options.sixteen_bit { png::BitDepth::Sixteen } else { png::BitDepth::Eight });
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    let mut stream = writer.stream_writer().map_err(png_error)?;
+
+    for (band, top) in (0..height).step_by(CHUNK_SIZE).enumerate() {
+        let band_height = CHUNK_SIZE.min(height - top);
+        // Blocks render in parallel; each comes back as its encoded rows
+        let blocks: Vec<Vec<Vec<u8>>> = (0..width).step_by(CHUNK_SIZE)
+            .collect::<Vec<_>>()
+            .par_iter()
+            .map(|&left| {
+                let block = generate_region(
+                    pattern, iterations, decay_curve, (left, top), (CHUNK_SIZE.min(width - left), band_height),
+                )?;
+                Ok(block.iter().map(|row| {
+                    row.iter().flat_map(|&color| {
+                        let color = options.encode(color);
+                        if options.sixteen_bit {
+                            color.to_rgba16().iter().flat_map(|channel| channel.to_be_bytes()).collect()
+                        } else {
+                            color.to_rgba().0.to_vec()
+                        }
+                    }).collect()
+                }).collect())
+            })
+            .collect::<Result<_, PatternError>>()?;
+        for row in 0..band_height {
+            for block in &blocks {
+                stream.write_all(&block[row])?;
+            }
+        }
+        on_band(band + 1);
+    }
+    stream.finish().map_err(png_error)?;
+    Ok(())
+}
+
+/// Renders frames in parallel, a batch per thread count at a time so only a
+/// few are held in memory, and encodes them in order as a looping GIF
+fn write_animation(
+    path: &Path,
+    frame_count: usize,
+    render_frame: impl Fn(usize) -> RgbaImage + Sync,
+    on_frame: impl Fn(usize) + Sync,
+) -> Result<(), ImageError> {
+    let file = fs::File::create(path)?;
+    let mut encoder = GifEncoder::new(BufWriter::new(file));
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let rendered = AtomicUsize::new(0);
+    let frames: Vec<usize> = (0..frame_count).collect();
+    for batch in frames.chunks(rayon::current_num_threads().max(1)) {
+        // Indexed parallel iterators collect in order, whichever frame finishes first
+        let images: Vec<RgbaImage> = batch.par_iter()
+            .map(|&frame| {
+                let image = render_frame(frame);
+                on_frame(rendered.fetch_add(1, Ordering::Relaxed) + 1);
+                image
+            })
+            .collect();
+        for image in images {
+            encoder.encode_frame(Frame::from_parts(
+                image,
+                0,
+                0,
+                Delay::from_numer_denom_ms(ANIMATION_FRAME_MS, 1),
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// How export_morph_animation gets from one pattern to the other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MorphMode {
+    /// Interpolate pixel colors and decays and render every frame. Only
+    /// possible when the patterns differ in nothing else, since
+    /// permutations can't be interpolated; otherwise frames cross-fade.
+    #[default]
+    Interpolate,
+    /// Render both patterns once and blend the finished images
+    CrossFade,
+}
+
+impl MorphMode {
+    pub const ALL: [MorphMode; 2] = [MorphMode::Interpolate, MorphMode::CrossFade];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MorphMode::Interpolate => "Interpolate",
+            MorphMode::CrossFade => "Cross-fade",
+        }
+    }
+}
+
+/// Writes a looping GIF that morphs from pattern a to pattern b over
+/// frame_count frames, which must be the same size. Returns the mode that
+/// was used, which is CrossFade if Interpolate was asked for but the
+/// patterns differ in more than colors and decays. on_frame is called as in
+/// export_decay_animation.
+#[allow(clippy::too_many_arguments)]
+pub fn export_morph_animation(
+    a: &Pattern,
+    b: &Pattern,
+    iterations: u32,
+    decay_curve: &[f32],
+    frame_count: usize,
+    mode: MorphMode,
+    path: &Path,
+    on_frame: impl Fn(usize) + Sync,
+) -> Result<MorphMode, PatternError> {
+    if (a.base_rows, a.base_dim) != (b.base_rows, b.base_dim) {
+        return Err(PatternError::ValidationError(format!(
+            "Patterns must be the same size to morph ({}x{} and {}x{})",
+            a.base_dim, a.base_rows, b.base_dim, b.base_rows
+        )));
+    }
+    let mode = if mode == MorphMode::Interpolate && a.can_interpolate_to(b) {
+        MorphMode::Interpolate
+    } else {
+        MorphMode::CrossFade
+    };
+
+    let t_at_frame = |frame: usize| {
+        if frame_count > 1 {
+            frame as f32 / (frame_count - 1) as f32
+        } else {
+            0.0
+        }
+    };
+    if mode == MorphMode::Interpolate {
+        write_animation(
+            path,
+            frame_count,
+            |frame| fractal_to_image(&render_pattern_with_curve(&a.interpolated(b, t_at_frame(frame)), iterations, decay_curve)),
+            on_frame,
+        )?;
+    } else {
+        let (from, to) = rayon::join(
+            || render_pattern_with_curve(a, iterations, decay_curve),
+            || render_pattern_with_curve(b, iterations, decay_curve),
+        );
+        write_animation(
+            path,
+            frame_count,
+            |frame| {
+                let t = t_at_frame(frame);
+                let blended: Vec<Vec<Color>> = from.iter().zip(&to)
+                    .map(|(from, to)| from.iter().zip(to).map(|(from, to)| from.lerp(to, t)).collect())
+                    .collect();
+                fractal_to_image(&blended)
+            },
+            on_frame,
+        )?;
+    }
+    Ok(mode)
+}
+
+/// Gap in pixels around and between contact sheet cells
+const SHEET_PADDING: u32 = 8;
+/// Size of each pixel of the label font
+const LABEL_SCALE: u32 = 3;
+
+/// 3x5 bitmap glyphs for the digits and '.', one row per byte with the
+/// leftmost column in bit 2
+const LABEL_GLYPHS: [[u8; 5]; 11] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b000, 0b000, 0b000, 0b000, 0b010],
+];
+
+/// Draws digits and '.' at (x, y) with the built-in label font; other
+/// characters are left blank
+fn draw_label(image: &mut RgbaImage, text: &str, x: u32, y: u32, color: Rgba<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match c {
+            '0'..='9' => LABEL_GLYPHS[c as usize - '0' as usize],
+            '.' => LABEL_GLYPHS[10],
+            _ => continue,
+        };
+        let left = x + i as u32 * 4 * LABEL_SCALE;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let (px, py) = (left + col * LABEL_SCALE + dx, y + row as u32 * LABEL_SCALE + dy);
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the pattern once per decay value and lays the renders out in a
+/// grid of `columns`, each labeled with its decay beneath it, on a light
+/// background
+pub fn contact_sheet(pattern: &Pattern, iterations: u32, decays: &[f32], columns: usize) -> RgbaImage {
+    let columns = columns.clamp(1, decays.len().max(1)) as u32;
+    let rows = (decays.len() as u32).div_ceil(columns);
+    let cell_width = (pattern.base_dim as u32).pow(iterations);
+    let cell_height = (pattern.base_rows as u32).pow(iterations);
+    let label_height = 5 * LABEL_SCALE + SHEET_PADDING;
+    let mut sheet = RgbaImage::from_pixel(
+        SHEET_PADDING + columns * (cell_width + SHEET_PADDING),
+        SHEET_PADDING + rows * (cell_height + label_height + SHEET_PADDING),
+        Rgba([240, 240, 240, 255]),
+    );
+
+    for (i, &decay) in decays.iter().enumerate() {
+        let x = SHEET_PADDING + (i as u32 % columns) * (cell_width + SHEET_PADDING);
+        let y = SHEET_PADDING + (i as u32 / columns) * (cell_height + label_height + SHEET_PADDING);
+        let render = render_image(pattern, iterations, decay);
+        imageops::overlay(&mut sheet, &render, x as i64, y as i64);
+        let label_y = y + cell_height + SHEET_PADDING / 2;
+        draw_label(&mut sheet, &format!("{:.2}", decay), x, label_y, Rgba([32, 32, 32, 255]));
+    }
+    sheet
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum PatternError {
+    FileError(std::io::Error),
+    ParseError(serde_json::Error),
+    ImageError(ImageError),
+    ValidationError(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::FileError(e) => write!(f, "File error: {}", e),
+            PatternError::ParseError(e) => write!(f, "JSON parse error: {}", e),
+            PatternError::ImageError(e) => write!(f, "Image error: {}", e),
+            PatternError::ValidationError(msg) => write!(f, "Pattern validation error: {}", msg),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+impl From<std::io::Error> for PatternError {
+    fn from(err: std::io::Error) -> PatternError {
+        PatternError::FileError(err)
+    }
+}
+
+impl From<serde_json::Error> for PatternError {
+    fn from(err: serde_json::Error) -> PatternError {
+        PatternError::ParseError(err)
+    }
+}
+
+impl From<ImageError> for PatternError {
+    fn from(err: ImageError) -> PatternError {
+        PatternError::ImageError(err)
+    }
+}
+
+/// Checks that a permutation is a bijection on a dim x dim grid
+pub fn validate_permutation(perm: &Permutation, rows: usize, cols: usize) -> Result<(), PatternError> {
+    if perm.mapping.len() != rows * cols {
+        return Err(PatternError::ValidationError(
+            format!("Permutation mapping must have {} entries", rows * cols)
+        ));
+    }
+
+    let mut used_positions = vec![vec![false; cols]; rows];
+    
+    // Check each mapping in the permutation
+    for &(y, x) in &perm.mapping {
+        // Validate coordinates are in range
+        if y >= rows || x >= cols {
+            return Err(PatternError::ValidationError(
+                format!("Permutation mapping coordinates must be within {} rows and {} columns", rows, cols)
+            ));
+        }
+        
+        // Check for duplicate mappings
+        if used_positions[y][x] {
+            return Err(PatternError::ValidationError(
+                "Permutation mapping contains duplicate positions".to_string()
+            ));
+        }
+        
+        used_positions[y][x] = true;
+    }
+    
+    // Verify all positions are used
+    if !used_positions.iter().all(|row| row.iter().all(|&used| used)) {
+        return Err(PatternError::ValidationError(
+            "Permutation mapping must use all positions".to_string()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_pattern(pattern: &Pattern) -> Result<(), PatternError> {
+    let (rows, cols) = (pattern.base_rows, pattern.base_dim);
+
+    // Validate the pixel grid matches base_rows x base_dim
+    if rows < 2 || cols < 2 {
+        return Err(PatternError::ValidationError(
+            "Base pattern must be at least 2x2".to_string()
+        ));
+    }
+    if pattern.pixels.len() != rows || pattern.pixels.iter().any(|row| row.len() != cols) {
+        return Err(PatternError::ValidationError(
+            format!("Base pattern must be a {}x{} grid", cols, rows)
+        ));
+    }
+    if let Some(initial) = &pattern.initial_pixels {
+        if initial.len() != rows || initial.iter().any(|row| row.len() != cols) {
+            return Err(PatternError::ValidationError(
+                format!("Initial pattern must be a {}x{} grid like the recursion pattern", cols, rows)
+            ));
+        }
+    }
+
+    // Validate color values are in range [0.0, 1.0]
+    for row in pattern.pixels.iter().chain(pattern.initial_pixels.iter().flatten()) {
+        for pixel in row {
+            let color = &pixel.color;
+            if color.r < 0.0 || color.r > 1.0 ||
+               color.g < 0.0 || color.g > 1.0 ||
+               color.b < 0.0 || color.b > 1.0 ||
+               color.a < 0.0 || color.a > 1.0 {
+                return Err(PatternError::ValidationError(
+                    "Color values must be between 0.0 and 1.0".to_string()
+                ));
+            }
+            if !(0.0..=1.0).contains(&pixel.decay) {
+                return Err(PatternError::ValidationError(
+                    "Pixel decay must be between 0.0 and 1.0".to_string()
+                ));
+            }
+            if pixel.weight.is_some_and(|weight| !(0.0..=1.0).contains(&weight)) {
+                return Err(PatternError::ValidationError(
+                    "Pixel weight must be between 0.0 and 1.0".to_string()
+                ));
+            }
+        }
+    }
+
+    if !(0.0..=1.0).contains(&pattern.alpha_cutoff) {
+        return Err(PatternError::ValidationError(
+            "Alpha cutoff must be between 0.0 and 1.0".to_string()
+        ));
+    }
+
+    // Validate permutation mappings
+    for row in pattern.pixels.iter().chain(pattern.initial_pixels.iter().flatten()) {
+        for pixel in row {
+            validate_permutation(&pixel.perm, rows, cols)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Advisory messages about patterns that are valid but probably not what was
+/// intended, such as ones that render as a flat fill. Unlike validate_pattern
+/// these never stop a pattern from loading or rendering.
+pub fn validate_pattern_warnings(pattern: &Pattern) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let initial = pattern.initial_pixels.as_ref();
+    let colors: Vec<Color> = pattern.pixels.iter()
+        .chain(initial.into_iter().flatten())
+        .flatten()
+        .map(|pixel| pixel.color)
+        .collect();
+
+    if colors.iter().all(|color| color.a == 0.0) {
+        warnings.push("Every pixel is fully transparent, so the fractal is empty".to_string());
+    } else if colors.windows(2).all(|pair| pair[0] == pair[1]) {
+        warnings.push("Every pixel has the same color, so the fractal is a flat fill".to_string());
+    }
+    if initial.is_some_and(|initial| *initial == pattern.pixels) {
+        warnings.push("The initial pattern matches the recursion pattern, so it has no effect".to_string());
+    }
+    warnings
+}
+
+/// Version written into saved pattern files; bump it whenever the saved layout changes
+pub const PATTERN_FORMAT_VERSION: u32 = 1;
+
+// Saved files wrap the pattern so the loader can tell which layout it holds
+#[derive(Serialize)]
+struct PatternFile<'a> {
+    version: u32,
+    pattern: &'a Pattern,
+}
+
+/// Serializes a pattern in the versioned file format
+pub fn pattern_to_json(pattern: &Pattern) -> Result<String, PatternError> {
+    Ok(serde_json::to_string_pretty(&PatternFile {
+        version: PATTERN_FORMAT_VERSION,
+        pattern,
+    })?)
+}
+
+/// Parses and validates a saved pattern, accepting both the versioned format
+/// and bare patterns written before versioning
+pub fn pattern_from_json(json: &str) -> Result<Pattern, PatternError> {
+    let mut value: serde_json::Value = serde_json::from_str(json)?;
+
+    let pattern: Pattern = match value.get("version") {
+        Some(version) => {
+            if version.as_u64() != Some(PATTERN_FORMAT_VERSION as u64) {
+                return Err(PatternError::ValidationError(format!(
+                    "Unsupported pattern file version {} (expected {})",
+                    version, PATTERN_FORMAT_VERSION
+                )));
+            }
+            let pattern = value.get_mut("pattern").map(serde_json::Value::take).ok_or_else(|| {
+                PatternError::ValidationError("Pattern file is missing its pattern".to_string())
+            })?;
+            serde_json::from_value(pattern)?
+        }
+        None => serde_json::from_value(value)?,
+    };
+    finish_loaded_pattern(pattern)
+}
+
+// Merges patch into target for Pattern::apply_patch; path names the
+// target in errors
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value, path: &str) -> Result<(), PatternError> {
+    use serde_json::Value;
+    match (target, patch) {
+        (Value::Object(fields), Value::Object(patch)) => {
+            for (key, value) in patch {
+                let field = fields.get_mut(key).ok_or_else(|| {
+                    PatternError::ValidationError(format!("Patch sets unknown field {}.{}", path, key))
+                })?;
+                merge_patch(field, value, &format!("{}.{}", path, key))?;
+            }
+        }
+        (Value::Array(items), Value::Object(patch)) => {
+            for (key, value) in patch {
+                let len = items.len();
+                let item = key.parse::<usize>().ok().and_then(|index| items.get_mut(index)).ok_or_else(|| {
+                    PatternError::ValidationError(format!(
+                        "Patch index {}[{}] is not between 0 and {}", path, key, len.saturating_sub(1)
+                    ))
+                })?;
+                merge_patch(item, value, &format!("{}[{}]", path, key))?;
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+    Ok(())
+}
+
+// Fills in sizes missing from older files and validates the result
+fn finish_loaded_pattern(mut pattern: Pattern) -> Result<Pattern, PatternError> {
+    // Older files were always square grids without an explicit size
+    if pattern.base_dim == 0 {
+        pattern.base_dim = pattern.pixels.len();
+    }
+    if pattern.base_rows == 0 {
+        pattern.base_rows = pattern.pixels.len();
+    }
+    
+    // Validate the pattern
+    validate_pattern(&pattern)?;
+    
+    Ok(pattern)
+}
+
+pub fn load_pattern_from_file(path: &str) -> Result<Pattern, PatternError> {
+    let json = fs::read_to_string(path)?;
+    pattern_from_json(&json)
+}
+
+/// Extension of saved FractalDocuments, which plain pattern files don't use
+pub const DOCUMENT_EXTENSION: &str = "fractal";
+
+/// Version written into saved documents; bump it whenever the saved layout changes
+pub const DOCUMENT_FORMAT_VERSION: u32 = 1;
+
+/// A pattern saved together with the settings it was rendered with, so a
+/// project reopens as it was left. The blend mode is part of the pattern.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FractalDocument {
+    pub pattern: Pattern,
+    pub iterations: u32,
+    pub decay: f32,
+    /// Color composited underneath the fractal, if any
+    #[serde(default)]
+    pub background: Option<Color>,
+    /// Decay factor for each expansion, used in place of decay when set
+    #[serde(default)]
+    pub level_decays: Option<Vec<f32>>,
+    /// Patterns composited over the main one, lowest first
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Serialize)]
+struct DocumentFile<'a> {
+    version: u32,
+    document: &'a FractalDocument,
+}
+
+/// Serializes a document in the versioned bundle format
+pub fn document_to_json(document: &FractalDocument) -> Result<String, PatternError> {
+    Ok(serde_json::to_string_pretty(&DocumentFile {
+        version: DOCUMENT_FORMAT_VERSION,
+        document,
+    })?)
+}
+
+/// Parses a saved document, checking its pattern like pattern_from_json and
+/// its settings
+pub fn document_from_json(json: &str) -> Result<FractalDocument, PatternError> {
+    let mut value: serde_json::Value = serde_json::from_str(json)?;
+    let version = value.get("version").and_then(serde_json::Value::as_u64);
+    if version != Some(DOCUMENT_FORMAT_VERSION as u64) {
+        return Err(PatternError::ValidationError(format!(
+            "Unsupported document version {} (expected {})",
+            value.get("version").unwrap_or(&serde_json::Value::Null), DOCUMENT_FORMAT_VERSION
+        )));
+    }
+    let document = value.get_mut("document").map(serde_json::Value::take).ok_or_else(|| {
+        PatternError::ValidationError("Document file is missing its document".to_string())
+    })?;
+    let document: FractalDocument = serde_json::from_value(document)?;
+
+    if document.iterations == 0 {
+        return Err(PatternError::ValidationError("Document iterations must be at least 1".to_string()));
+    }
+    if !(0.0..=1.0).contains(&document.decay) {
+        return Err(PatternError::ValidationError(format!(
+            "Document decay {} is outside 0..1", document.decay
+        )));
+    }
+    if let Some(decay) = document.level_decays.iter().flatten().find(|decay| !(0.0..=1.0).contains(*decay)) {
+        return Err(PatternError::ValidationError(format!(
+            "Document level decay {} is outside 0..1", decay
+        )));
+    }
+    let layers = document.layers.into_iter()
+        .map(|layer| {
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(PatternError::ValidationError(format!(
+                    "Layer opacity {} is outside 0..1", layer.opacity
+                )));
+            }
+            Ok(Layer { pattern: finish_loaded_pattern(layer.pattern)?, ..layer })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(FractalDocument {
+        pattern: finish_loaded_pattern(document.pattern)?,
+        layers,
+        ..document
+    })
+}
+
+pub fn load_document_from_file(path: &str) -> Result<FractalDocument, PatternError> {
+    let json = fs::read_to_string(path)?;
+    document_from_json(&json)
+}
+
+/// Keyword of the PNG text chunk holding the document an image was exported from
+pub const PARAMETERS_KEYWORD: &str = "Fractal-Parameters";
+
+/// Saves an image as PNG with the document that generated it in a
+/// Fractal-Parameters iTXt chunk, serialized as by document_to_json, and the
+/// app version in a Software chunk. 16-bit images keep their depth.
+pub fn save_png_with_document(image: &DynamicImage, path: &Path, document: &FractalDocument) -> Result<(), PatternError> {
+    let png_error = |e: png::EncodingError| {
+        PatternError::ImageError(ImageError::Encoding(image::error::EncodingError::new(ImageFormat::Png.into(), e)))
+    };
+    let sixteen_bit = image.color().bytes_per_pixel() > image.color().channel_count();
+
+    let mut encoder = png::Encoder::new(BufWriter::new(fs::File::create(path)?), image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(if sixteen_bit { png::BitDepth::Sixteen } else { png::BitDepth::Eight });
+    encoder.add_text_chunk("Software".to_string(), format!("Fractals {}", env!("CARGO_PKG_VERSION"))).map_err(png_error)?;
+    encoder.add_itxt_chunk(PARAMETERS_KEYWORD.to_string(), document_to_json(document)?).map_err(png_error)?;
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    if sixteen_bit {
+        let bytes: Vec<u8> = image.to_rgba16().into_raw().iter().flat_map(|channel| channel.to_be_bytes()).collect();
+        writer.write_image_data(&bytes).map_err(png_error)?;
+    } else {
+        writer.write_image_data(&image.to_rgba8()).map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)?;
+    Ok(())
+}
+
+/// Reads back the document written by save_png_with_document
+pub fn document_from_png(path: &str) -> Result<FractalDocument, PatternError> {
+    let png_error = |e: png::DecodingError| {
+        PatternError::ImageError(ImageError::Decoding(image::error::DecodingError::new(ImageFormat::Png.into(), e)))
+    };
+    let reader = png::Decoder::new(BufReader::new(fs::File::open(path)?)).read_info().map_err(png_error)?;
+    let chunk = reader.info().utf8_text.iter()
+        .find(|chunk| chunk.keyword == PARAMETERS_KEYWORD)
+        .ok_or_else(|| PatternError::ValidationError(format!("Image has no {} chunk", PARAMETERS_KEYWORD)))?;
+    document_from_json(&chunk.get_text().map_err(png_error)?)
+}
+
+/// Replaces the pattern's colors with the pixels of an image the size of the
+/// base tile, keeping the existing permutations
+pub fn load_colors_from_image(pattern: &mut Pattern, path: &str) -> Result<(), PatternError> {
+    let colors = read_tile_colors(pattern, path)?;
+    for (row, colors) in pattern.pixels.iter_mut().zip(colors) {
+        for (pixel, color) in row.iter_mut().zip(colors) {
+            pixel.color = color;
+        }
+    }
+    Ok(())
+}
+
+/// The pixels of an image the size of the pattern's base tile, as colors in
+/// the pattern's color space, row by row
+pub fn read_tile_colors(pattern: &Pattern, path: &str) -> Result<Vec<Vec<Color>>, PatternError> {
+    let image = image::open(path)?.to_rgba8();
+    let (width, height) = (pattern.base_dim as u32, pattern.base_rows as u32);
+    if image.dimensions() != (width, height) {
+        return Err(PatternError::ValidationError(format!(
+            "Color image must be {}x{} pixels, found {}x{}",
+            width, height, image.width(), image.height()
+        )));
+    }
+
+    Ok(image.rows()
+        .map(|row| row.map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            pattern.color_space.from_srgb(Color::new(
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0,
+                a as f32 / 255.0,
+            ))
+        }).collect())
+        .collect())
+}
+
+/// Parses a GIMP palette (.gpl): a `GIMP Palette` header, optional
+/// `Name:`/`Columns:` lines and `#` comments, then one `R G B [name]` row per
+/// color with channels from 0 to 255
+pub fn parse_gpl(text: &str) -> Result<Vec<Color>, PatternError> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Palette") {
+        return Err(PatternError::ValidationError("Palette must start with a \"GIMP Palette\" line".to_string()));
+    }
+
+    let mut colors = Vec::new();
+    for (index, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let mut channel = || fields.next().and_then(|field| field.parse::<u8>().ok());
+        match (channel(), channel(), channel()) {
+            (Some(r), Some(g), Some(b)) => colors.push(Color::new(
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0,
+                1.0,
+            )),
+            _ => {
+                return Err(PatternError::ValidationError(format!(
+                    "Invalid palette color on line {}: {}",
+                    index + 1, line
+                )));
+            }
+        }
+    }
+    Ok(colors)
+}
+
+/// Reads the colors of a GIMP palette file
+pub fn load_gpl_palette(path: &str) -> Result<Vec<Color>, PatternError> {
+    parse_gpl(&fs::read_to_string(path)?)
+}
@@ -1,350 +1,699 @@
-use std::error::Error;
-use std::fmt;
-use image::{ImageBuffer, Rgba};
-use eframe::egui::{self, Vec2};
-use serde::{Deserialize, Serialize};
+mod cli;
+
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use eframe::egui;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use fractals::{
+    base_tile_image, box_counting_dimension, color_counts, composite_layers, contact_sheet,
+    create_base_pattern, crop_fractal, document_from_png, document_to_json, draw_caption,
+    edges_wrap, encode_fractal, estimate_memory, export_decay_animation, export_format,
+    export_image, export_levels, export_morph_animation, export_png_chunked, fractal_digest,
+    fractal_to_json, fractal_to_svg, grid_size, iterations_within, load_document_from_file,
+    load_gpl_palette, load_pattern_from_file, luminance_histogram, max_iterations, pattern_to_json,
+    read_tile_colors, render_pattern_progressive, render_pattern_with_curve, render_supersampled,
+    save_image, save_png_with_document, symmetry_group, validate_pattern_warnings,
+    validate_permutation, BlendMode, BoxDimension, Caption, CaptionCorner, Color, ColorCounts,
+    ColorSpace, ColorVision, ComposeOrder, DecayModel, ExportOptions, FractalDocument, Histogram,
+    Layer, MorphMode, NormalizeMode, Pattern, PatternError, Permutation, Pixel, SymmetryGroup,
+    CHUNK_SIZE, DEFAULT_JPEG_QUALITY, DEFAULT_MEMORY_LIMIT, DOCUMENT_EXTENSION, MAX_SVG_SIZE,
+};
 
-// Add Serialize/Deserialize to our existing structs
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-struct Color {
-    r: f32,
-    g: f32,
-    b: f32,
-    a: f32,
-}
+// Maximum number of pattern snapshots kept for undo
+const UNDO_LIMIT: usize = 50;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-struct Permutation {
-    mapping: [(usize, usize); 4]
-}
+// Largest width or height accepted for a resampled PNG export
+const MAX_OUTPUT_SIZE: u32 = 16384;
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
-struct Pixel {
-    color: Color,
-    perm: Permutation,
-}
+const HISTOGRAM_BINS: usize = 256;
 
-#[derive(Clone, Serialize, Deserialize)]
-struct Pattern {
-    pixels: [[Pixel; 2]; 2]
-}
+// Most frequent colors kept from each preview for the color report
+const MAX_TOP_COLORS: usize = 32;
 
+// Share of the image (or of the preview, when zoomed past it) that panning
+// always leaves on screen
+const MIN_VISIBLE_FRACTION: f32 = 0.25;
 
-impl Permutation {
-    fn identity() -> Self {
-        Permutation {
-            mapping: [(0,0), (0,1), (1,0), (1,1)]
-        }
-    }
-    
-    fn rotate_90() -> Self {
-        Permutation {
-            mapping: [(0,1), (1,1), (0,0), (1,0)]
-        }
-    }
-    
-    fn rotate_270() -> Self {
-        Permutation {
-            mapping: [(1,0), (0,0), (1,1), (0,1)]
-        }
-    }
-    
-    fn flip_h() -> Self {
-        Permutation {
-            mapping: [(0,1), (0,0), (1,1), (1,0)]
-        }
-    }
-    
-    fn flip_v() -> Self {
-        Permutation {
-            mapping: [(1,0), (1,1), (0,0), (0,1)]
-        }
-    }
-    
-    fn compose(&self, other: &Permutation) -> Permutation {
-        let mut result = [(0,0); 4];
-        for i in 0..4 {
-            let (y, x) = self.mapping[i];
-            let idx = y * 2 + x;
-            result[i] = other.mapping[idx];
-        }
-        Permutation { mapping: result }
-    }
-    
-    fn apply<T: Copy>(&self, grid: [[T; 2]; 2]) -> [[T; 2]; 2] {
-        let mut result = [[grid[0][0]; 2]; 2];
-        for i in 0..4 {
-            let (from_y, from_x) = (i / 2, i % 2);
-            let (to_y, to_x) = self.mapping[i];
-            result[to_y][to_x] = grid[from_y][from_x];
-        }
-        result
-    }
-
-    fn get_name(&self) -> &'static str {
-        if self.mapping == Self::identity().mapping {
-            "Identity"
-        } else if self.mapping == Self::rotate_90().mapping {
-            "Rotate 90°"
-        } else if self.mapping == Self::rotate_270().mapping {
-            "Rotate 270°"
-        } else if self.mapping == Self::flip_h().mapping {
-            "Flip H"
-        } else if self.mapping == Self::flip_v().mapping {
-            "Flip V"
-        } else {
-            "Custom"
-        }
-    }
+// Largest edge of each render in a contact sheet
+const CONTACT_CELL_SIZE: usize = 512;
+
+// Image files accepted for loading colors, by extension
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "webp"];
+
+// Grid edge length above which a nested JSON export asks for confirmation
+const GRID_JSON_WARN_SIZE: usize = 512;
+
+// Variations shown by the mutation explorer, and the side length they're
+// rendered at
+const MUTATION_COUNT: u64 = 8;
+const MUTATION_THUMBNAIL_SIZE: usize = 64;
+const MUTATION_COLUMNS: usize = 4;
+
+// Width in points of the area that grabs the A/B divider
+const COMPARE_HANDLE_WIDTH: f32 = 12.0;
+
+// Widest margin offered for exported images
+const MAX_BORDER_WIDTH: u32 = 1024;
+
+// Largest side length rendered for supersampled export
+const MAX_SSAA_SIZE: usize = 4096;
+
+const PALETTE_FILE: &str = "palette.json";
+const SETTINGS_FILE: &str = "settings.json";
+// Snapshot of the open document, left behind if the app doesn't exit cleanly
+const RECOVERY_FILE: &str = "recovery.fractal";
+
+// Seconds between checks for edits to write to the recovery file
+const AUTOSAVE_INTERVAL: f32 = 30.0;
+
+// Patterns listed under Recent, newest first
+const MAX_RECENT_FILES: usize = 8;
+
+// Distance in points an arrow key moves the view at a zoom level of 1. It is
+// scaled by the zoom, so each press covers the same share of the image
+// however far the preview is zoomed.
+const KEY_PAN_STEP: f32 = 32.0;
+
+// Physical screen pixels one fractal pixel may cover at the highest zoom,
+// by default and at most
+const DEFAULT_MAX_PIXEL_SIZE: f32 = 64.0;
+const MAX_PIXEL_SIZE_LIMIT: f32 = 256.0;
+
+// Largest side length offered for chunked export, which never holds the
+// whole image but still takes time and disk space in proportion to it
+const MAX_CHUNKED_SIZE: usize = 1 << 16;
+
+// Largest block size a base tile cell can be exported at
+const MAX_TILE_SCALE: u32 = 256;
+
+// Completed preview renders averaged for the stats panel
+const RENDER_TIME_SAMPLES: usize = 5;
+
+// Largest side length of the low-resolution render shown while editing
+const LIVE_PREVIEW_SIZE: usize = 64;
+
+const APP_TITLE: &str = "Fractal Generator";
+
+const SAVE_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+const LOAD_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::O);
+const EXPORT_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::E);
+const PREVIEW_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::R);
+const RESET_VIEW_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Num0);
+const COMMAND_PALETTE_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::P);
+
+const COMMAND_PALETTE_WIDTH: f32 = 320.0;
+
+enum HistoryAction {
+    Undo,
+    Redo,
 }
 
-impl Color {
-    fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
-        Color { r, g, b, a }
-    }
+// Actions reachable from the command palette
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    SaveProject,
+    Open,
+    ExportPatternOnly,
+    ExportImage,
+    ExportSvg,
+    ExportGrid,
+    ExportContactSheet,
+    ExportBaseTile,
+    ExportAnimation,
+    UpdatePreview,
+    ResetView,
+    PixelPerfect,
+    CopyView,
+    CopyImage,
+    Randomize,
+    ResetPattern,
+    Undo,
+    Redo,
+    ToggleLivePreview,
+    ToggleGrid,
+    ToggleCompare,
+}
+
+impl Command {
+    const ALL: [Command; 21] = [
+        Command::SaveProject,
+        Command::Open,
+        Command::ExportPatternOnly,
+        Command::ExportImage,
+        Command::ExportSvg,
+        Command::ExportGrid,
+        Command::ExportContactSheet,
+        Command::ExportBaseTile,
+        Command::ExportAnimation,
+        Command::UpdatePreview,
+        Command::ResetView,
+        Command::PixelPerfect,
+        Command::CopyView,
+        Command::CopyImage,
+        Command::Randomize,
+        Command::ResetPattern,
+        Command::Undo,
+        Command::Redo,
+        Command::ToggleLivePreview,
+        Command::ToggleGrid,
+        Command::ToggleCompare,
+    ];
 
-    fn lerp(&self, other: &Color, t: f32) -> Color {
-        Color {
-            r: self.r + (other.r - self.r) * t,
-            g: self.g + (other.g - self.g) * t,
-            b: self.b + (other.b - self.b) * t,
-            a: self.a + (other.a - self.a) * t,
+    fn name(self) -> &'static str {
+        match self {
+            Command::SaveProject => "Save Project",
+            Command::Open => "Open",
+            Command::ExportPatternOnly => "Export Pattern Only",
+            Command::ExportImage => "Export Image",
+            Command::ExportSvg => "Export SVG",
+            Command::ExportGrid => "Export Grid JSON",
+            Command::ExportContactSheet => "Export Contact Sheet",
+            Command::ExportBaseTile => "Export Base Tile",
+            Command::ExportAnimation => "Export Animation",
+            Command::UpdatePreview => "Update Preview",
+            Command::ResetView => "Reset View",
+            Command::PixelPerfect => "Zoom to 100%",
+            Command::CopyView => "Copy View",
+            Command::CopyImage => "Copy Image",
+            Command::Randomize => "Randomize Pattern",
+            Command::ResetPattern => "Reset Pattern",
+            Command::Undo => "Undo",
+            Command::Redo => "Redo",
+            Command::ToggleLivePreview => "Toggle Live Preview",
+            Command::ToggleGrid => "Toggle Grid Overlay",
+            Command::ToggleCompare => "Toggle A/B Compare",
         }
     }
 
-    fn to_rgba(&self) -> Rgba<u8> {
-        Rgba([
-            (self.r * 255.0) as u8,
-            (self.g * 255.0) as u8,
-            (self.b * 255.0) as u8,
-            (self.a * 255.0) as u8,
-        ])
+    // Every word of the query appears in the name, ignoring case
+    fn matches(self, query: &str) -> bool {
+        let name = self.name().to_lowercase();
+        query.to_lowercase().split_whitespace().all(|word| name.contains(word))
     }
 }
 
-fn create_base_pattern() -> Pattern {
-    Pattern { pixels:
-        [
-            [
-                Pixel {
-                    color: Color::new(0.2, 0.4, 0.6, 1.0), // blue
-                    perm: Permutation::rotate_90(),
-                },
-                Pixel {
-                    color: Color::new(0.6, 0.4, 0.2, 1.0), // bronze
-                    perm: Permutation::flip_h(),
-                },
-            ],
-            [
-                Pixel {
-                    color: Color::new(0.0, 0.0, 0.0, 1.0), // black
-                    perm: Permutation::flip_v(),
-                },
-                Pixel {
-                    color: Color::new(0.0, 0.0, 0.0, 0.0), // transparent
-                    perm: Permutation::identity(),
-                },
-            ],
-        ],
-    }
+// The open command palette's search text and highlighted entry
+#[derive(Default)]
+struct CommandPalette {
+    query: String,
+    selected: usize,
 }
 
-fn generate_fractal(iterations: u32, pattern: &Pattern, decay: f32) -> Vec<Vec<Color>> {
-    let final_size = 1 << iterations;
-    let mut result = vec![vec![Pixel {
-        color: Color::new(0.0, 0.0, 0.0, 0.0),
-        perm: Permutation::identity()
-    }; final_size]; final_size];
-    
-    // Initialize with base pattern
-    let base = pattern.pixels;
-    for y in 0..2 {
-        for x in 0..2 {
-            result[y][x] = base[y][x];
+// Quality settings chosen together, for users who don't know which
+// iteration counts and export options suit what they're making
+#[derive(Clone, Copy, PartialEq)]
+enum Preset {
+    Draft,
+    Standard,
+    High,
+    Print,
+}
+
+impl Preset {
+    const ALL: [Preset; 4] = [Preset::Draft, Preset::Standard, Preset::High, Preset::Print];
+
+    // Decay every preset starts from
+    const DECAY: f32 = 0.5;
+
+    fn name(self) -> &'static str {
+        match self {
+            Preset::Draft => "Draft",
+            Preset::Standard => "Standard",
+            Preset::High => "High",
+            Preset::Print => "Print",
         }
     }
 
-    let mut blend = 1.0;
-    let mut current_size = 2;
-    
-    while current_size < final_size {
-        blend *= decay;
-        let new_size = current_size * 2;
-
-        for y in (0..current_size).rev() {
-            for x in (0..current_size).rev() {
-                let pixel = result[y][x];
-                let alpha = pixel.color.a;
-                let color = Color { a: 1.0, ..pixel.color };
-                
-                let y_start = y * 2;
-                let x_start = x * 2;
-                
-                // Get base pattern and apply current permutation
-                let base = pattern.pixels;
-                let permuted_base = pixel.perm.apply(base);
-                
-                let blend_factor = 1.0 - (1.0 - blend) * alpha;
-                
-                // Place blended region with composed permutations
-                for dy in 0..2 {
-                    for dx in 0..2 {
-                        let base_pixel = permuted_base[dy][dx];
-                        let new_perm = if current_size * 2 < final_size {
-                            pixel.perm.compose(&base_pixel.perm)
-                        } else {
-                            Permutation::identity()
-                        };
-                        
-                        result[y_start + dy][x_start + dx] = Pixel {
-                            color: color.lerp(&base_pixel.color, blend_factor),
-                            perm: new_perm,
-                        };
-                    }
-                }
-            }
+    // Wanted iteration count, lowered to what the pattern can render
+    fn iterations(self, longest_side: usize) -> u32 {
+        let iterations = match self {
+            Preset::Draft => 6,
+            Preset::Standard => 8,
+            Preset::High => 9,
+            Preset::Print => 10,
+        };
+        iterations.min(max_iterations(longest_side))
+    }
+
+    // Extra levels rendered for anti-aliasing, None for no anti-aliasing
+    fn ssaa_levels(self) -> Option<u32> {
+        match self {
+            Preset::Draft | Preset::Standard => None,
+            Preset::High => Some(1),
+            Preset::Print => Some(2),
         }
-        
-        current_size = new_size;
     }
 
-    // Extract final colors
-    result.into_iter()
-        .map(|row| row.into_iter().map(|pixel| pixel.color).collect())
-        .collect()
+    fn sixteen_bit(self) -> bool {
+        self == Preset::Print
+    }
 }
 
-#[derive(Debug)]
-pub enum PatternError {
-    FileError(std::io::Error),
-    ParseError(serde_json::Error),
-    ValidationError(String),
+// How rendered colors are turned into preview textures. A change only
+// needs the textures uploaded again, not a new render.
+#[derive(Clone, PartialEq)]
+struct DisplayOptions {
+    // The per-color export steps, so the preview matches what's saved
+    encode: ExportOptions,
+    // Sampling when the preview is drawn larger or smaller than the texture
+    filter: egui::TextureFilter,
 }
 
-impl fmt::Display for PatternError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PatternError::FileError(e) => write!(f, "File error: {}", e),
-            PatternError::ParseError(e) => write!(f, "JSON parse error: {}", e),
-            PatternError::ValidationError(msg) => write!(f, "Pattern validation error: {}", msg),
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            encode: ExportOptions::default(),
+            filter: egui::TextureFilter::Nearest,
         }
     }
 }
 
-impl Error for PatternError {}
-
-impl From<std::io::Error> for PatternError {
-    fn from(err: std::io::Error) -> PatternError {
-        PatternError::FileError(err)
-    }
+// Inputs that determine a render; equal keys produce identical fractals
+#[derive(Clone, Copy, PartialEq)]
+struct RenderKey {
+    iterations: u32,
+    decay_hash: u64,
+    pattern_hash: u64,
 }
 
-impl From<serde_json::Error> for PatternError {
-    fn from(err: serde_json::Error) -> PatternError {
-        PatternError::ParseError(err)
-    }
+// Session state restored on the next launch
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    iterations: u32,
+    decay: f32,
+    zoom_level: f32,
+    pan_offset: [f32; 2],
+    // Pattern last saved or loaded, reopened if it still exists
+    pattern_path: Option<PathBuf>,
+    recent_files: Vec<PathBuf>,
+    // Pattern names of the recent files that have one
+    recent_names: HashMap<PathBuf, String>,
+    max_pixel_size: f32,
 }
 
-fn validate_pattern(pattern: &Pattern) -> Result<(), PatternError> {
-    // Validate color values are in range [0.0, 1.0]
-    for row in &pattern.pixels {
-        for pixel in row {
-            let color = &pixel.color;
-            if color.r < 0.0 || color.r > 1.0 ||
-               color.g < 0.0 || color.g > 1.0 ||
-               color.b < 0.0 || color.b > 1.0 ||
-               color.a < 0.0 || color.a > 1.0 {
-                return Err(PatternError::ValidationError(
-                    "Color values must be between 0.0 and 1.0".to_string()
-                ));
-            }
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            iterations: 8,
+            decay: 0.5,
+            zoom_level: 1.0,
+            pan_offset: [0.0, 0.0],
+            pattern_path: None,
+            recent_files: Vec::new(),
+            recent_names: HashMap::new(),
+            max_pixel_size: DEFAULT_MAX_PIXEL_SIZE,
         }
     }
+}
 
-    // Validate permutation mappings
-    for row in &pattern.pixels {
-        for pixel in row {
-            let mut used_positions = [[false; 2]; 2];
-            
-            // Check each mapping in the permutation
-            for &(y, x) in &pixel.perm.mapping {
-                // Validate coordinates are in range
-                if y >= 2 || x >= 2 {
-                    return Err(PatternError::ValidationError(
-                        "Permutation mapping coordinates must be less than 2".to_string()
-                    ));
-                }
-                
-                // Check for duplicate mappings
-                if used_positions[y][x] {
-                    return Err(PatternError::ValidationError(
-                        "Permutation mapping contains duplicate positions".to_string()
-                    ));
-                }
-                
-                used_positions[y][x] = true;
-            }
-            
-            // Verify all positions are used
-            if !used_positions.iter().all(|row| row.iter().all(|&used| used)) {
-                return Err(PatternError::ValidationError(
-                    "Permutation mapping must use all positions".to_string()
-                ));
-            }
-        }
-    }
+// A rendered fractal, or the message of the panic that stopped the render
+type RenderOutcome = Result<Vec<Vec<Color>>, String>;
 
-    Ok(())
+// A preview being generated on a background thread
+struct PreviewJob {
+    key: RenderKey,
+    levels_done: Arc<AtomicUsize>,
+    total_levels: u32,
+    cancel: Arc<AtomicBool>,
+    // Textures of the levels finished so far, uploaded by the render thread
+    levels: mpsc::Receiver<egui::TextureHandle>,
+    // The finished fractal and how long generating it took
+    result: mpsc::Receiver<(RenderOutcome, Duration)>,
+    // Preview shown before the job started, put back if rendering fails
+    previous: Option<(egui::TextureHandle, Option<RenderKey>)>,
 }
 
-fn load_pattern_from_file(path: &str) -> Result<Pattern, PatternError> {
-    // Read and parse the JSON file
-    let json = fs::read_to_string(path)?;
-    let pattern: Pattern = serde_json::from_str(&json)?;
-    
-    // Validate the pattern
-    validate_pattern(&pattern)?;
-    
-    Ok(pattern)
+// A decay animation being rendered on a background thread
+struct AnimationExport {
+    frames_done: Arc<AtomicUsize>,
+    frame_count: usize,
+    // Status message to show on success, or the error
+    result: mpsc::Receiver<Result<&'static str, String>>,
+}
+
+// A PNG being rendered block by block on a background thread
+struct ChunkedExport {
+    bands_done: Arc<AtomicUsize>,
+    band_count: usize,
+    result: mpsc::Receiver<Result<(), String>>,
 }
 
 struct FractalApp {
     pattern: Pattern,
+    // File the pattern was last saved to or loaded from
+    pattern_path: Option<PathBuf>,
+    // Patterns recently saved or loaded, newest first
+    recent_files: Vec<PathBuf>,
+    // Pattern names shown in place of the file names of recent files
+    recent_names: HashMap<PathBuf, String>,
+    // Title last given to the window, so it is only sent when the name changes
+    window_title: String,
     preview_texture: Option<egui::TextureHandle>,
+    // Size of the preview texture the current view was set up for
+    last_texture_size: Option<egui::Vec2>,
+    // Preview area from the last frame, for view actions triggered outside it
+    last_preview_rect: Option<egui::Rect>,
     iterations: u32,
     decay: f32,
     status_message: Option<(String, bool)>, // (message, is_error)
     status_timer: Option<f32>,
     pan_offset: egui::Vec2,
     zoom_level: f32,
+    // Physical screen pixels one fractal pixel covers at the highest zoom
+    max_pixel_size: f32,
     dragging: bool,
+    // Whether the pointer was over the preview last frame, so arrow keys pan
+    // the view there and move the selected cell elsewhere
+    preview_hovered: bool,
+    undo_stack: Vec<Pattern>,
+    redo_stack: Vec<Pattern>,
+    // Pattern as it was before the edit currently in progress
+    pending_edit: Option<Pattern>,
+    // Whether the Name field has focus this frame; typing a name is one
+    // edit, committed once the field loses focus
+    editing_name: bool,
+    // Cell whose permutation is open in the custom editor, with the mapping being edited
+    custom_perm_edit: Option<((usize, usize), Permutation)>,
+    animation_start_decay: f32,
+    animation_end_decay: f32,
+    animation_frames: usize,
+    morph_mode: MorphMode,
+    animation_export: Option<AnimationExport>,
+    // Iterations for chunked export, which may go past what fits in memory
+    chunked_iterations: u32,
+    chunked_export: Option<ChunkedExport>,
+    // Document found in the recovery file at startup, until the user
+    // restores or discards it
+    recovery: Option<FractalDocument>,
+    // Seconds since the last autosave check
+    autosave_timer: f32,
+    // Document as last written to the recovery file, saved or opened at
+    // startup; None writes at the next check
+    autosaved: Option<FractalDocument>,
+    gamma_correction: bool,
+    gamma: f32,
+    sixteen_bit: bool,
+    ssaa: bool,
+    // Extra iterations rendered and averaged away when exporting with SSAA
+    ssaa_levels: u32,
+    use_decay_curve: bool,
+    // Decay factor for each expansion when use_decay_curve is set
+    level_decays: Vec<f32>,
+    // Exported PNG dimensions when they shouldn't follow the iteration count
+    custom_output_size: bool,
+    output_width: u32,
+    output_height: u32,
+    // Keep the output size at the rendered grid's width to height ratio
+    lock_aspect: bool,
+    smooth_resize: bool,
+    use_background: bool,
+    background: Color,
+    // Margin added around exported images; 0 for none
+    border_width: u32,
+    border_color: Color,
+    // Text drawn into a corner of exported images; empty text stands for the
+    // settings summary
+    use_caption: bool,
+    caption_text: String,
+    caption_corner: CaptionCorner,
+    caption_color: Color,
+    seamless_tile: bool,
+    tiled_export: bool,
+    // Write exported grids as a flat number array instead of color objects
+    grid_flat: bool,
+    preview_job: Option<PreviewJob>,
+    // Most recent render, reused while its inputs are unchanged
+    fractal_cache: Option<(RenderKey, Vec<Vec<Color>>)>,
+    // Settings the preview texture was rendered from
+    preview_key: Option<RenderKey>,
+    // Whether the preview texture is an unfinished level of the running job
+    preview_partial: bool,
+    // Durations of the latest completed preview renders, oldest first
+    render_times: VecDeque<Duration>,
+    live_preview: bool,
+    live_texture: Option<egui::TextureHandle>,
+    // Low-resolution render shown by the live texture, with the settings it came from
+    live_render: Option<(RenderKey, Vec<Vec<Color>>)>,
+    // Whether the live texture is newer than the full preview and shown in its place
+    showing_live: bool,
+    // Settings the last full render triggered by live preview was started for
+    live_full_key: Option<RenderKey>,
+    // Base pattern cell that palette swatches apply to, as (y, x)
+    selected_cell: (usize, usize),
+    // Set when the selection moved by keyboard so the editor scrolls to it
+    scroll_to_selection: bool,
+    palette: Vec<Color>,
+    show_hsv: bool,
+    random_seed: u64,
+    // Color the Fill button paints every pixel of the edited grid with
+    fill_color: Color,
+    // Luminance distribution of the current preview
+    histogram: Option<Histogram>,
+    // Distinct colors in the current preview
+    color_counts: Option<ColorCounts>,
+    // How many of the most frequent colors are shown as swatches
+    top_colors: usize,
+    // Box-counting dimension of the current preview's cells above
+    // dimension_threshold alpha
+    box_dimension: Option<BoxDimension>,
+    dimension_threshold: f32,
+    // Patterns composited over the main one, lowest first
+    layers: Vec<Layer>,
+    // Comma-separated decay values rendered side by side in a contact sheet
+    contact_decays: String,
+    contact_columns: usize,
+    contact_iterations: u32,
+    // Subdivision lines drawn over the preview
+    show_grid: bool,
+    // Recursion depth whose cell boundaries the grid marks
+    grid_level: u32,
+    grid_color: egui::Color32,
+    // Export only the part of the image visible in the preview
+    export_view: bool,
+    // Size in pixels of each cell in an exported base tile
+    tile_scale: u32,
+    // Quality of JPEG exports, from 1 to 100
+    jpeg_quality: u8,
+    // Tint cells whose unclamped color falls outside 0..=1
+    show_gamut: bool,
+    // Color vision deficiency simulated in the preview
+    color_vision: ColorVision,
+    // Also apply color_vision to exported images
+    export_color_vision: bool,
+    // Interpolate the preview smoothly instead of showing crisp cells
+    smooth_preview: bool,
+    // Settings the preview textures were uploaded with
+    display_options: DisplayOptions,
+    // Overlay for the displayed render, with the settings it came from and
+    // the number of flagged cells
+    gamut_overlay: Option<(RenderKey, egui::TextureHandle, usize)>,
+    // Second pattern for the A/B comparison; the edited pattern is A
+    compare_pattern: Option<Pattern>,
+    // Show pattern B right of a divider over the preview
+    compare: bool,
+    // Divider position as a fraction of the preview width
+    compare_split: f32,
+    // Most recent render of pattern B, reused while its inputs are unchanged
+    compare_render: Option<(RenderKey, Vec<Vec<Color>>)>,
+    compare_texture: Option<egui::TextureHandle>,
+    // Render of pattern B running in the background
+    compare_job: Option<(RenderKey, mpsc::Receiver<RenderOutcome>)>,
+    command_palette: Option<CommandPalette>,
+    // How far the explorer's variations stray from the current pattern, 0..1
+    mutation_strength: f32,
+    // Variations of the current pattern with their thumbnails
+    mutations: Vec<(Pattern, egui::TextureHandle)>,
+    // Whether the editor shows the initial pixels rather than the recursion pattern
+    edit_initial: bool,
+    // Whether clicking the preview descends into the clicked subdivision
+    descend_mode: bool,
+    // Subdivisions descended into from the whole image, as (row, column)
+    // within each level's rows x columns split
+    descent: Vec<(usize, usize)>,
+    // Symmetry group of the pattern it was last worked out for
+    symmetry_readout: Option<(Pattern, SymmetryGroup)>,
+    // Opened on first copy and kept, since on some platforms the copied image
+    // only stays available while the clipboard handle is alive
+    clipboard: Option<arboard::Clipboard>,
 }
 
 impl FractalApp {
     fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
-            pattern: create_base_pattern(),
+        let settings = load_settings();
+        let pattern = settings.pattern_path.as_ref()
+            .and_then(|path| read_pattern(path).ok());
+        let pattern_path = pattern.is_some().then_some(settings.pattern_path).flatten();
+        // A recovery file that outlived the last session means it ended
+        // without a clean exit; an unreadable one is not worth offering
+        let recovery = config_path(RECOVERY_FILE)
+            .filter(|path| path.exists())
+            .and_then(|path| load_document_from_file(path.to_str().unwrap_or_default()).ok());
+
+        let mut app = Self {
+            pattern: pattern.unwrap_or_else(create_base_pattern),
+            pattern_path,
+            recent_files: settings.recent_files,
+            recent_names: settings.recent_names,
+            window_title: APP_TITLE.to_string(),
             preview_texture: None,
-            iterations: 8,
-            decay: 0.5,
+            last_texture_size: None,
+            last_preview_rect: None,
+            iterations: settings.iterations.max(1),
+            decay: settings.decay.clamp(0.0, 1.0),
             status_message: None,
             status_timer: None,
-            pan_offset: egui::Vec2::ZERO,
-            zoom_level: 1.0,
+            pan_offset: egui::Vec2::from(settings.pan_offset),
+            zoom_level: if settings.zoom_level.is_finite() && settings.zoom_level > 0.0 {
+                settings.zoom_level
+            } else {
+                1.0
+            },
+            max_pixel_size: settings.max_pixel_size.clamp(1.0, MAX_PIXEL_SIZE_LIMIT),
             dragging: false,
+            preview_hovered: false,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            pending_edit: None,
+            editing_name: false,
+            custom_perm_edit: None,
+            animation_start_decay: 0.0,
+            animation_end_decay: 1.0,
+            animation_frames: 30,
+            morph_mode: MorphMode::default(),
+            animation_export: None,
+            chunked_iterations: 12,
+            chunked_export: None,
+            recovery,
+            autosave_timer: 0.0,
+            autosaved: None,
+            gamma_correction: false,
+            gamma: 2.2,
+            sixteen_bit: false,
+            ssaa: false,
+            ssaa_levels: 1,
+            use_decay_curve: false,
+            level_decays: Vec::new(),
+            custom_output_size: false,
+            lock_aspect: false,
+            output_width: 1080,
+            output_height: 1080,
+            smooth_resize: false,
+            use_background: false,
+            background: Color::new(1.0, 1.0, 1.0, 1.0),
+            border_width: 0,
+            use_caption: false,
+            caption_text: String::new(),
+            caption_corner: CaptionCorner::default(),
+            caption_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            border_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            seamless_tile: false,
+            tiled_export: false,
+            grid_flat: false,
+            preview_job: None,
+            fractal_cache: None,
+            preview_key: None,
+            preview_partial: false,
+            render_times: VecDeque::new(),
+            live_preview: false,
+            live_texture: None,
+            live_render: None,
+            showing_live: false,
+            live_full_key: None,
+            selected_cell: (0, 0),
+            scroll_to_selection: false,
+            palette: load_palette(),
+            show_hsv: false,
+            random_seed: 0,
+            fill_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            histogram: None,
+            color_counts: None,
+            box_dimension: None,
+            dimension_threshold: 0.5,
+            top_colors: 8,
+            layers: Vec::new(),
+            contact_decays: "0.2, 0.4, 0.6, 0.8".to_string(),
+            contact_columns: 2,
+            contact_iterations: 5,
+            show_grid: false,
+            grid_level: 1,
+            grid_color: egui::Color32::from_rgba_unmultiplied(255, 255, 255, 160),
+            export_view: false,
+            tile_scale: 16,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+            show_gamut: false,
+            color_vision: ColorVision::Normal,
+            export_color_vision: false,
+            smooth_preview: false,
+            display_options: DisplayOptions::default(),
+            compare_pattern: None,
+            compare: false,
+            compare_split: 0.5,
+            compare_render: None,
+            compare_texture: None,
+            compare_job: None,
+            command_palette: None,
+            mutation_strength: 0.1,
+            mutations: Vec::new(),
+            edit_initial: false,
+            descend_mode: false,
+            descent: Vec::new(),
+            symmetry_readout: None,
+            clipboard: None,
+            gamut_overlay: None,
+        };
+        // The document as opened has nothing to recover
+        app.autosaved = Some(app.current_document());
+        app
+    }
+
+    // Records the snapshot taken before the current edit as one undo entry
+    fn commit_edit(&mut self) {
+        if let Some(snapshot) = self.pending_edit.take() {
+            self.undo_stack.push(snapshot);
+            if self.undo_stack.len() > UNDO_LIMIT {
+                self.undo_stack.remove(0);
+            }
+            self.redo_stack.clear();
+        }
+    }
+
+    fn undo(&mut self) {
+        self.commit_edit();
+        if let Some(pattern) = self.undo_stack.pop() {
+            self.redo_stack.push(std::mem::replace(&mut self.pattern, pattern));
+        }
+    }
+
+    fn redo(&mut self) {
+        self.commit_edit();
+        if let Some(pattern) = self.redo_stack.pop() {
+            self.undo_stack.push(std::mem::replace(&mut self.pattern, pattern));
         }
     }
     
     fn save_pattern(&mut self, ctx: &egui::Context) {
-        if let Some(path) = rfd::FileDialog::new()
+        if let Some(path) = self.save_dialog("json")
             .add_filter("JSON", &["json"])
-            .set_title("Save Pattern")
+            .set_title("Export Pattern Only")
             .save_file() {
-                match serde_json::to_string_pretty(&self.pattern) {
+                match pattern_to_json(&self.pattern) {
                     Ok(json) => {
                         match fs::write(&path, json) {
-                            Ok(_) => self.update_status(ctx, "Pattern saved successfully", false),
+                            Ok(_) => {
+                                self.add_recent_file(&path);
+                                self.pattern_path = Some(path);
+                                self.clear_recovery(ctx);
+                                self.update_status(ctx, "Pattern saved successfully", false);
+                            }
                             Err(e) => self.update_status(ctx, &format!("Failed to save pattern: {}", e), true),
                         }
                     }
@@ -353,158 +702,1491 @@ impl FractalApp {
         }
     }
 
+    fn current_document(&self) -> FractalDocument {
+        FractalDocument {
+            pattern: self.pattern.clone(),
+            iterations: self.iterations,
+            decay: self.decay,
+            background: self.use_background.then_some(self.background),
+            level_decays: self.use_decay_curve.then(|| self.level_decays.clone()),
+            layers: self.layers.clone(),
+        }
+    }
+
+    // Restores the pattern and render settings of a project
+    fn apply_document(&mut self, document: FractalDocument) {
+        self.pattern = document.pattern;
+        self.iterations = document.iterations.min(max_iterations(self.pattern.longest_side()));
+        self.decay = document.decay;
+        self.use_background = document.background.is_some();
+        if let Some(background) = document.background {
+            self.background = background;
+        }
+        self.use_decay_curve = document.level_decays.is_some();
+        if let Some(level_decays) = document.level_decays {
+            self.level_decays = level_decays;
+        }
+        self.layers = document.layers;
+    }
+
+    // Saves the pattern with its render settings as a FractalDocument
+    fn save_project(&mut self, ctx: &egui::Context) {
+        if let Some(path) = self.save_dialog(DOCUMENT_EXTENSION)
+            .add_filter("Fractal project", &[DOCUMENT_EXTENSION])
+            .set_title("Save Project")
+            .save_file() {
+                let document = self.current_document();
+                let result = document_to_json(&document)
+                    .and_then(|json| Ok(fs::write(&path, json)?));
+                match result {
+                    Ok(()) => {
+                        self.add_recent_file(&path);
+                        self.pattern_path = Some(path);
+                        self.clear_recovery(ctx);
+                        self.update_status(ctx, "Project saved successfully", false);
+                    }
+                    Err(e) => self.update_status(ctx, &format!("Failed to save project: {}", e), true),
+                }
+        }
+    }
+
     fn load_pattern(&mut self, ctx: &egui::Context) {
         if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Project or pattern", &[DOCUMENT_EXTENSION, "json"])
+            .add_filter("Fractal project", &[DOCUMENT_EXTENSION])
             .add_filter("JSON", &["json"])
-            .set_title("Load Pattern")
+            .set_title("Open")
             .pick_file() {
-                match load_pattern_from_file(path.to_str().unwrap_or_default()) {
-                    Ok(pattern) => {
-                        self.pattern = pattern;
-                        self.update_status(ctx, "Pattern loaded successfully", false);
-                        self.update_preview(ctx);
-                    }
-                    Err(e) => {
-                        self.update_status(ctx, &format!("Failed to load pattern: {}", e), true);
-                    }
-                }
+                self.open_pattern(ctx, path);
         }
     }
 
-    fn reset_view(&mut self) {
-        self.zoom_level = 1.0;
-        self.pan_offset = egui::Vec2::ZERO;
+    // Opens a project, restoring its settings, or a plain pattern
+    fn open_pattern(&mut self, ctx: &egui::Context, path: PathBuf) {
+        if is_document(&path) {
+            self.open_project(ctx, path);
+            return;
+        }
+        match load_pattern_from_file(path.to_str().unwrap_or_default()) {
+            Ok(pattern) => {
+                self.pattern = pattern;
+                self.add_recent_file(&path);
+                self.pattern_path = Some(path);
+                self.update_status(ctx, "Pattern loaded successfully", false);
+                self.update_preview(ctx);
+            }
+            Err(e) => {
+                self.update_status(ctx, &format!("Failed to load pattern: {}", e), true);
+            }
+        }
     }
 
-    fn fit_factor(&self, preview_rect: egui::Rect) -> f32 {
-        if let Some(texture) = &self.preview_texture {
-            return (preview_rect.size() / texture.size_vec2()).min_elem()
+    fn open_project(&mut self, ctx: &egui::Context, path: PathBuf) {
+        match load_document_from_file(path.to_str().unwrap_or_default()) {
+            Ok(document) => {
+                self.apply_document(document);
+                self.add_recent_file(&path);
+                self.pattern_path = Some(path);
+                self.update_status(ctx, "Project loaded successfully", false);
+                self.update_preview(ctx);
+            }
+            Err(e) => self.update_status(ctx, &format!("Failed to load project: {}", e), true),
         }
-        0.0
     }
 
-    fn handle_zoom(&mut self, zoom_delta: f32, mouse_pos: egui::Pos2, preview_rect: egui::Rect) {
-        if let Some(texture) = &self.preview_texture {
-            let old_zoom = self.zoom_level;
-
+    // Moves path to the front of the recent files, dropping the oldest, and
+    // remembers the name of the pattern it holds
+    fn add_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|recent| recent != path);
+        self.recent_files.insert(0, path.to_path_buf());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+        if self.pattern.name.is_empty() {
+            self.recent_names.remove(path);
+        } else {
+            self.recent_names.insert(path.to_path_buf(), self.pattern.name.clone());
+        }
+        let recent_files = &self.recent_files;
+        self.recent_names.retain(|path, _| recent_files.contains(path));
+    }
 
-            // Calculate new zoom level with bounds
-            self.zoom_level = (self.zoom_level * (1.0 + zoom_delta * -0.1))
-                .clamp(0.5, 20.0/self.fit_factor(preview_rect));
-            
-            // Calculate the texture size at both zoom levels
-            let old_size = texture.size_vec2() * self.fit_factor(preview_rect) * old_zoom;
-            let new_size = texture.size_vec2() * self.fit_factor(preview_rect) * self.zoom_level;
+    // A save dialog suggesting a file named after the pattern, when it has a name
+    fn save_dialog(&self, extension: &str) -> rfd::FileDialog {
+        let dialog = rfd::FileDialog::new();
+        let name: String = self.pattern.name.trim().chars()
+            .map(|c| if c.is_control() || r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+            .collect();
+        if name.is_empty() {
+            dialog
+        } else {
+            dialog.set_file_name(format!("{}.{}", name, extension))
+        }
+    }
 
-            // Calculate normalized mouse position relative to the preview rect
-            let preview_size = preview_rect.size();
-            let rel_mouse = (mouse_pos - self.pan_offset - preview_rect.min - preview_size / 2.0) / old_size;
-            
-            // Adjust pan offset to keep the point under cursor stable
-            let size_diff = new_size - old_size;
-            self.pan_offset -= size_diff * rel_mouse;
-            
-            // Clamp pan offset to keep image in view
-            self.clamp_pan_offset(preview_rect);
+    fn open_recent_file(&mut self, ctx: &egui::Context, path: PathBuf) {
+        if path.exists() {
+            self.open_pattern(ctx, path);
+        } else {
+            self.recent_files.retain(|recent| *recent != path);
+            self.update_status(ctx, &format!("{} no longer exists and was removed from recent files", path.display()), true);
         }
     }
-    
-    fn clamp_pan_offset(&mut self, preview_rect: egui::Rect) {
-        if let Some(texture) = &self.preview_texture {
-            let preview_size = preview_rect.size();
-            let scaled_texture_size = texture.size_vec2() * self.fit_factor(preview_rect) * self.zoom_level;
-            
-            // Calculate the maximum allowed offset
-            let max_offset = (scaled_texture_size - preview_size).abs().max(scaled_texture_size)/2.0;
 
-            // Clamp the offset
-            self.pan_offset = self.pan_offset.clamp(-max_offset, max_offset);
+    fn layers_ui(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        let mut swap = None;
+        let count = self.layers.len();
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(&layer.name);
+                egui::ComboBox::from_id_salt(("layer_blend", i))
+                    .selected_text(layer.blend_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in BlendMode::ALL {
+                            ui.selectable_value(&mut layer.blend_mode, mode, mode.name());
+                        }
+                    });
+                ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("Opacity"));
+                if ui.add_enabled(i + 1 < count, egui::Button::new("⬆")).on_hover_text("Move up").clicked() {
+                    swap = Some(i);
+                }
+                if ui.add_enabled(i > 0, egui::Button::new("⬇")).on_hover_text("Move down").clicked() {
+                    swap = Some(i - 1);
+                }
+                if ui.button("✖").on_hover_text("Remove layer").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = swap {
+            self.layers.swap(i, i + 1);
         }
+        if let Some(i) = remove {
+            self.layers.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add copy").on_hover_text("Layer a copy of the current pattern").clicked() {
+                self.add_layer(format!("Copy {}", count + 1), self.pattern.clone());
+            }
+            if ui.button("Add random").on_hover_text("Layer a pattern randomized from the seed").clicked() {
+                let pattern = Pattern::random(self.pattern.base_rows, self.pattern.base_dim, self.random_seed);
+                self.add_layer(format!("Seed {}", self.random_seed), pattern);
+            }
+            if ui.button("Add from file…").clicked() {
+                self.load_layer(ui.ctx());
+            }
+        });
     }
 
-    fn update_preview(&mut self, ctx: &egui::Context) {
-        let fractal = generate_fractal(self.iterations, &self.pattern, self.decay);
-        let size = 1 << self.iterations;
-        
-        let mut image = image::RgbaImage::new(size as u32, size as u32);
-        for (y, row) in fractal.iter().enumerate() {
-            for (x, &color) in row.iter().enumerate() {
-                image.put_pixel(x as u32, y as u32, color.to_rgba());
+    fn dimension_ui(&mut self, ui: &mut egui::Ui) {
+        if ui.add(egui::Slider::new(&mut self.dimension_threshold, 0.0..=0.99).text("Alpha threshold"))
+            .on_hover_text("Only cells more opaque than this are counted")
+            .changed() {
+            // Recount over the cached grid when it is the one being previewed
+            if let Some((key, fractal)) = &self.fractal_cache {
+                if Some(*key) == self.preview_key {
+                    self.box_dimension = box_counting_dimension(fractal, self.dimension_threshold);
+                }
+            }
+        }
+        match &self.box_dimension {
+            Some(estimate) => {
+                ui.label(format!("Box-counting dimension: {:.3}", estimate.dimension));
+                for (size, count) in &estimate.counts {
+                    ui.label(format!("{0}x{0} boxes: {1} occupied", size, count));
+                }
+            }
+            None => {
+                ui.label("Too few cells above the threshold to estimate");
             }
         }
+    }
 
-        let color_image = egui::ColorImage::from_rgba_unmultiplied(
-            [size as _, size as _],
-            &image.into_raw(),
-        );
+    fn add_layer(&mut self, name: String, pattern: Pattern) {
+        self.layers.push(Layer { name, pattern, blend_mode: BlendMode::Lerp, opacity: 0.5 });
+    }
 
-        let mut tex_options = egui::TextureOptions::default();
-        tex_options.magnification = egui::TextureFilter::Nearest;
+    fn load_layer(&mut self, ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_title("Load Layer Pattern")
+            .pick_file() {
+                match load_pattern_from_file(path.to_str().unwrap_or_default()) {
+                    Ok(pattern) => {
+                        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                        self.add_layer(name, pattern);
+                        self.update_status(ctx, "Layer added", false);
+                    }
+                    Err(e) => {
+                        self.update_status(ctx, &format!("Failed to load layer: {}", e), true);
+                    }
+                }
+        }
+    }
 
-        self.preview_texture = Some(ctx.load_texture(
-            "preview",
-            color_image,
-            tex_options,
-        ));
+    fn load_image_colors(&mut self, ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Image", IMAGE_EXTENSIONS)
+            .set_title("Load Colors from Image")
+            .pick_file() {
+                self.open_image_colors(ctx, &path);
+        }
     }
 
-    fn export_preview(&mut self, ctx: &egui::Context) {
+    // Restores the pattern and settings recorded in a PNG exported by the app
+    fn import_from_png(&mut self, ctx: &egui::Context) {
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("PNG", &["png"])
-            .set_title("Export Preview")
-            .save_file() {
-                // Generate the fractal data
-                let fractal = generate_fractal(self.iterations, &self.pattern, self.decay);
-                let size = 1 << self.iterations;
-                
-                // Create the image
-                let mut image = ImageBuffer::new(size as u32, size as u32);
-                for (y, row) in fractal.iter().enumerate() {
-                    for (x, &color) in row.iter().enumerate() {
-                        image.put_pixel(x as u32, y as u32, color.to_rgba());
+            .set_title("Import from PNG")
+            .pick_file() {
+                match document_from_png(path.to_str().unwrap_or_default()) {
+                    Ok(document) => {
+                        self.apply_document(document);
+                        self.update_status(ctx, "Parameters imported successfully", false);
+                        self.update_preview(ctx);
                     }
+                    Err(e) => self.update_status(ctx, &format!("Failed to import from PNG: {}", e), true),
                 }
+        }
+    }
 
-                // Save the image
-                match image.save(&path) {
-                    Ok(_) => self.update_status(ctx, "Preview exported successfully", false),
-                    Err(e) => self.update_status(ctx, &format!("Failed to export preview: {}", e), true),
+    // Colors go into the grid being edited, like the palette and Fill
+    fn open_image_colors(&mut self, ctx: &egui::Context, path: &Path) {
+        match read_tile_colors(&self.pattern, path.to_str().unwrap_or_default()) {
+            Ok(colors) => {
+                let pixels = edited_pixels(&mut self.pattern, self.edit_initial);
+                for (row, colors) in pixels.iter_mut().zip(colors) {
+                    for (pixel, color) in row.iter_mut().zip(colors) {
+                        pixel.color = color;
+                    }
                 }
+                self.update_status(ctx, "Colors loaded successfully", false);
+                self.update_preview(ctx);
+            }
+            Err(e) => {
+                self.update_status(ctx, &format!("Failed to load colors: {}", e), true);
+            }
         }
     }
 
-    fn update_preview_panel(&mut self, ui: &mut egui::Ui) {
-        
-        if self.preview_texture.is_none() {
+    // Loads a pattern or image file dropped onto the window, going by its extension
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        for path in dropped.into_iter().filter_map(|file| file.path) {
+            let extension = path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if extension == "json" || extension == DOCUMENT_EXTENSION {
+                self.open_pattern(ctx, path);
+            } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+                self.open_image_colors(ctx, &path);
+            } else {
+                self.update_status(ctx, &format!("Unsupported file: {}", path.display()), true);
+            }
+        }
+    }
+
+    // Dims the window and names the files while they're dragged over it
+    fn draw_drop_overlay(&self, ctx: &egui::Context) {
+        let hovered = ctx.input(|i| i.raw.hovered_files.clone());
+        if hovered.is_empty() {
             return;
         }
-        let (preview_response, painter) = ui.allocate_painter(
-            ui.available_size(),
-            egui::Sense::drag()
+        let names: Vec<String> = hovered.iter()
+            .map(|file| match &file.path {
+                Some(path) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                None => file.mime.clone(),
+            })
+            .collect();
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+        let screen = ctx.screen_rect();
+        painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(180));
+        painter.text(
+            screen.center(),
+            egui::Align2::CENTER_CENTER,
+            format!("Drop to load:\n{}", names.join("\n")),
+            egui::FontId::proportional(20.0),
+            egui::Color32::WHITE,
         );
-        let preview_rect = preview_response.rect;
+    }
 
-        // Handle zooming with scroll wheel
-        let zoom_delta = -ui.input(|i| i.smooth_scroll_delta.y / 50.0);
-        if zoom_delta != 0.0 && preview_rect.contains(ui.input(|i| i.pointer.hover_pos().unwrap_or_default())) {
-            self.handle_zoom(
-                zoom_delta,
-                ui.input(|i| i.pointer.hover_pos().unwrap_or_default()),
-                preview_rect
-            );
+    // Arrow keys move the selected editor cell, clamped at the edges; Tab and
+    // Shift+Tab step through the cells in reading order, wrapping around.
+    // Only called while no widget has focus, so Tab focus navigation still works
+    // inside the controls
+    fn handle_selection_keys(&mut self, ctx: &egui::Context) {
+        let (rows, cols) = (self.pattern.base_rows, self.pattern.base_dim);
+        let (y, x) = self.selected_cell;
+        let moved = ctx.input_mut(|i| {
+            let (mut y, mut x) = (y.min(rows - 1), x.min(cols - 1));
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+                y = y.saturating_sub(1);
+            }
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
+                y = (y + 1).min(rows - 1);
+            }
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowLeft) {
+                x = x.saturating_sub(1);
+            }
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowRight) {
+                x = (x + 1).min(cols - 1);
+            }
+            let cells = rows * cols;
+            let index = y * cols + x;
+            let index = if i.consume_key(egui::Modifiers::SHIFT, egui::Key::Tab) {
+                (index + cells - 1) % cells
+            } else if i.consume_key(egui::Modifiers::NONE, egui::Key::Tab) {
+                (index + 1) % cells
+            } else {
+                index
+            };
+            (index / cols, index % cols)
+        });
+        if moved != self.selected_cell {
+            self.selected_cell = moved;
+            self.scroll_to_selection = true;
         }
+    }
 
-        // Handle panning
-        if preview_response.dragged() {
-            self.pan_offset += preview_response.drag_delta();
-            self.dragging = true;
-            self.clamp_pan_offset(preview_rect);
-        } else {
-            self.dragging = false;
-        }
+    // A zoom level of 1.0 means the image exactly fits the preview rect
+    // (see fit_factor); resetting the view fits and centers it
+    fn reset_view(&mut self) {
+        self.zoom_level = 1.0;
+        self.pan_offset = egui::Vec2::ZERO;
+        self.descent.clear();
+    }
+
+    // The part of the image the descent has reached, as fractions of its
+    // width and height. Each step narrows it to one of the pattern's
+    // subdivisions, which by self-similarity repeats the whole at finer detail.
+    fn descent_region(&self) -> egui::Rect {
+        let divisions = egui::vec2(self.pattern.base_dim as f32, self.pattern.base_rows as f32);
+        let mut region = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1.0, 1.0));
+        for &(row, col) in &self.descent {
+            let size = region.size() / divisions;
+            region = egui::Rect::from_min_size(region.min + size * egui::vec2(col as f32, row as f32), size);
+        }
+        region
+    }
+
+    // Steps one level into the subdivision under pos, which must lie within
+    // the image rect on screen. The deepest descent shows a single base tile.
+    fn descend_at(&mut self, pos: egui::Pos2, image_rect: egui::Rect, pixels_per_point: f32) {
+        if self.descent.len() + 1 >= self.iterations as usize {
+            return;
+        }
+        let region = self.descent_region();
+        let rel = ((pos - image_rect.min) / image_rect.size() - region.min.to_vec2()) / region.size();
+        if !(0.0..1.0).contains(&rel.x) || !(0.0..1.0).contains(&rel.y) {
+            return;
+        }
+        let (rows, cols) = (self.pattern.base_rows, self.pattern.base_dim);
+        let row = ((rel.y * rows as f32) as usize).min(rows - 1);
+        let col = ((rel.x * cols as f32) as usize).min(cols - 1);
+        self.descent.push((row, col));
+        self.show_descent(pixels_per_point);
+    }
+
+    fn ascend(&mut self, pixels_per_point: f32) {
+        if self.descent.pop().is_some() {
+            self.show_descent(pixels_per_point);
+        }
+    }
+
+    // Zooms so the descent's region fills the preview, centered. Pan and
+    // zoom limits still apply, so the deepest regions may be shown smaller.
+    fn show_descent(&mut self, pixels_per_point: f32) {
+        self.descent.truncate(self.iterations.saturating_sub(1) as usize);
+        let (Some(preview_rect), Some(texture)) = (self.last_preview_rect, self.displayed_texture()) else {
+            return;
+        };
+        let Some(fit) = self.usable_fit_factor(preview_rect) else {
+            return;
+        };
+        let fitted = texture.size_vec2() * fit;
+        let region = self.descent_region();
+        self.zoom_level = (preview_rect.size() / (fitted * region.size())).min_elem();
+        if let Some((min_zoom, max_zoom)) = self.zoom_range(preview_rect, pixels_per_point) {
+            self.zoom_level = self.zoom_level.clamp(min_zoom, max_zoom);
+        }
+        // The image is centered at the pan offset, so moving the region's
+        // center there takes the offset from the center to it
+        self.pan_offset = fitted * self.zoom_level * (egui::vec2(0.5, 0.5) - region.center().to_vec2());
+        self.clamp_pan_offset(preview_rect);
+    }
+
+    // Zoom level at which one fractal cell covers one physical screen pixel.
+    // While a live render is shown this still refers to the full render.
+    fn pixel_perfect_zoom(&self, preview_rect: egui::Rect, pixels_per_point: f32) -> Option<f32> {
+        let fit = self.usable_fit_factor(preview_rect)?;
+        let displayed = self.displayed_texture()?.size_vec2().x;
+        let full = self.preview_texture.as_ref().map_or(displayed, |texture| texture.size_vec2().x);
+        Some(full / (pixels_per_point * displayed * fit))
+    }
+
+    // The image may be larger than the window at 100%; panning reaches the rest
+    fn zoom_to_pixel_perfect(&mut self, pixels_per_point: f32) {
+        let Some(preview_rect) = self.last_preview_rect else {
+            return;
+        };
+        if let Some(zoom) = self.pixel_perfect_zoom(preview_rect, pixels_per_point) {
+            self.zoom_level = zoom;
+            self.pan_offset = egui::Vec2::ZERO;
+        }
+    }
+
+    fn copy_view(&mut self, ctx: &egui::Context) {
+        ctx.copy_text(format!("{} {} {}", self.zoom_level, self.pan_offset.x, self.pan_offset.y));
+        self.update_status(ctx, "View copied to clipboard", false);
+    }
+
+    // Keeps a typed-in view within the limits scrolling and dragging obey
+    fn clamp_view(&mut self, pixels_per_point: f32) {
+        let Some(preview_rect) = self.last_preview_rect else {
+            return;
+        };
+        if let Some((min_zoom, max_zoom)) = self.zoom_range(preview_rect, pixels_per_point) {
+            self.zoom_level = self.zoom_level.clamp(min_zoom, max_zoom);
+        }
+        self.clamp_pan_offset(preview_rect);
+    }
+
+    // Zoom limits. Zooming out stops at half the fitted size, or at 100% if
+    // that is smaller. Zooming in stops once one fractal pixel covers
+    // max_pixel_size physical pixels, which is pixel_perfect_zoom *
+    // max_pixel_size, so the limit follows the render's resolution rather
+    // than the window. None when there is nothing to zoom.
+    fn zoom_range(&self, preview_rect: egui::Rect, pixels_per_point: f32) -> Option<(f32, f32)> {
+        let min = 0.5_f32;
+        let pixel_perfect = self.pixel_perfect_zoom(preview_rect, pixels_per_point)?;
+        Some((min.min(pixel_perfect), (pixel_perfect * self.max_pixel_size).max(min)))
+    }
+
+    fn fit_factor(&self, preview_rect: egui::Rect) -> f32 {
+        if let Some(texture) = self.displayed_texture() {
+            return (preview_rect.size() / texture.size_vec2()).min_elem()
+        }
+        0.0
+    }
+
+    // The fit factor is zero or non-finite when the preview rect or texture is
+    // degenerate (e.g. a collapsed window); view math must be skipped then or
+    // it produces inf/NaN offsets that blank the preview
+    fn usable_fit_factor(&self, preview_rect: egui::Rect) -> Option<f32> {
+        let fit = self.fit_factor(preview_rect);
+        (fit.is_finite() && fit > 0.0).then_some(fit)
+    }
+
+    fn handle_zoom(&mut self, zoom_delta: f32, mouse_pos: egui::Pos2, preview_rect: egui::Rect, pixels_per_point: f32) {
+        let Some(fit) = self.usable_fit_factor(preview_rect) else {
+            return;
+        };
+        if let Some(texture_size) = self.displayed_texture().map(|texture| texture.size_vec2()) {
+            let old_zoom = self.zoom_level;
+
+            // Calculate new zoom level with bounds
+            let Some((min_zoom, max_zoom)) = self.zoom_range(preview_rect, pixels_per_point) else {
+                return;
+            };
+            self.zoom_level = (self.zoom_level * (1.0 + zoom_delta * -0.1))
+                .clamp(min_zoom, max_zoom);
+            
+            // Calculate the texture size at both zoom levels
+            let old_size = texture_size * fit * old_zoom;
+            let new_size = texture_size * fit * self.zoom_level;
+
+            // Calculate normalized mouse position relative to the preview rect
+            let preview_size = preview_rect.size();
+            let rel_mouse = (mouse_pos - self.pan_offset - preview_rect.min - preview_size / 2.0) / old_size;
+            
+            // Adjust pan offset to keep the point under cursor stable
+            let size_diff = new_size - old_size;
+            self.pan_offset -= size_diff * rel_mouse;
+            
+            // Clamp pan offset to keep image in view
+            self.clamp_pan_offset(preview_rect);
+        }
+    }
+    
+    // Arrow keys move the view by KEY_PAN_STEP times the zoom; PageUp/PageDown and +/- zoom
+    // about the center of the preview
+    fn handle_view_keys(&mut self, ui: &egui::Ui, preview_rect: egui::Rect, pixels_per_point: f32) {
+        let (pan, zoom_delta) = ui.input(|i| {
+            if !i.modifiers.is_none() {
+                return (egui::Vec2::ZERO, 0.0);
+            }
+            let pressed = |keys: &[egui::Key]| keys.iter().filter(|&&key| i.key_pressed(key)).count() as f32;
+            // Moving the view right slides the image left
+            let pan = egui::vec2(
+                pressed(&[egui::Key::ArrowLeft]) - pressed(&[egui::Key::ArrowRight]),
+                pressed(&[egui::Key::ArrowUp]) - pressed(&[egui::Key::ArrowDown]),
+            ) * KEY_PAN_STEP * self.zoom_level;
+            let zoom_in = pressed(&[egui::Key::PageUp, egui::Key::Plus, egui::Key::Equals]);
+            let zoom_out = pressed(&[egui::Key::PageDown, egui::Key::Minus]);
+            (pan, zoom_out - zoom_in)
+        });
+
+        if zoom_delta != 0.0 {
+            self.handle_zoom(zoom_delta, preview_rect.center(), preview_rect, pixels_per_point);
+        }
+        if pan != egui::Vec2::ZERO {
+            self.pan_offset += pan;
+            self.clamp_pan_offset(preview_rect);
+        }
+    }
+
+    fn clamp_pan_offset(&mut self, preview_rect: egui::Rect) {
+        let Some(fit) = self.usable_fit_factor(preview_rect) else {
+            return;
+        };
+        if let Some(texture_size) = self.displayed_texture().map(|texture| texture.size_vec2()) {
+            let max_offset = max_pan_offset(texture_size * fit * self.zoom_level, preview_rect.size());
+            self.pan_offset = self.pan_offset.clamp(-max_offset, max_offset);
+        }
+    }
+
+    // Starts generating the preview in the background, replacing any preview
+    // still in progress. The texture is swapped in by poll_preview_job.
+    fn update_preview(&mut self, ctx: &egui::Context) {
+        self.cancel_preview();
+
+        let key = self.render_key();
+        if let Some((cached_key, fractal)) = self.fractal_cache.take() {
+            if cached_key == key {
+                self.set_preview_texture(ctx, key, &fractal);
+                self.showing_live = false;
+                self.fractal_cache = Some((cached_key, fractal));
+                return;
+            }
+        }
+
+        let levels_done = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, result) = mpsc::channel();
+        let (level_sender, levels) = mpsc::channel();
+        let progress = levels_done.clone();
+        let cancelled = cancel.clone();
+        let pattern = self.pattern.clone();
+        let layers = self.layers.clone();
+        let iterations = self.iterations;
+        let decay_curve = self.decay_curve();
+        let display_options = self.display_options.clone();
+        let ctx = ctx.clone();
+
+        std::thread::spawn(move || {
+            let started = Instant::now();
+            let outcome = catch_panic(|| {
+                // Each level is uploaded here rather than on the UI thread, so the
+                // copy and conversion don't stall a frame. Layers only join in at
+                // the end since they're rendered separately.
+                let mut fractal = render_pattern_progressive(&pattern, iterations, &decay_curve, |level, colors| {
+                    progress.store(level as usize, Ordering::Relaxed);
+                    let _ = level_sender.send(fractal_texture(&ctx, "preview", colors, &display_options));
+                    ctx.request_repaint();
+                    !cancelled.load(Ordering::Relaxed)
+                })?;
+                composite_layers(&mut fractal, &layers, |layer| {
+                    render_pattern_with_curve(layer, iterations, &decay_curve)
+                });
+                Some(fractal)
+            });
+            // A cancelled render sends nothing
+            if let Some(outcome) = outcome.transpose() {
+                let _ = sender.send((outcome, started.elapsed()));
+                ctx.request_repaint();
+            }
+        });
+
+        self.preview_job = Some(PreviewJob {
+            key,
+            levels_done,
+            total_levels: iterations,
+            cancel,
+            levels,
+            result,
+            previous: self.preview_texture.clone().map(|texture| (texture, self.preview_key)),
+        });
+    }
+
+    fn cancel_preview(&mut self) {
+        if let Some(job) = self.preview_job.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Uploads the finished preview once the background render completes
+    fn poll_preview_job(&mut self, ctx: &egui::Context) {
+        let Some(job) = &self.preview_job else {
+            return;
+        };
+        // Only the newest level is worth showing if several arrived this frame
+        if let Some(texture) = job.levels.try_iter().last() {
+            // Take over from the live render once the level is more detailed
+            // and still reflects the current settings
+            if self.showing_live && job.key == self.render_key() {
+                let live_size = self.live_texture.as_ref().map(|texture| texture.size_vec2());
+                self.showing_live = live_size.is_some_and(|size| size.x >= texture.size_vec2().x);
+            }
+            self.preview_texture = Some(texture);
+            self.preview_key = None;
+            self.preview_partial = true;
+        }
+        let Some(job) = &self.preview_job else {
+            return;
+        };
+        match job.result.try_recv() {
+            Ok((Err(message), _)) => {
+                if let Some((texture, key)) = self.preview_job.take().and_then(|job| job.previous) {
+                    self.preview_texture = Some(texture);
+                    self.preview_key = key;
+                    self.preview_partial = false;
+                }
+                self.update_status(ctx, &format!("Rendering failed: {}", message), true);
+            }
+            Ok((Ok(fractal), elapsed)) => {
+                let key = job.key;
+                self.preview_job = None;
+                if self.render_times.len() == RENDER_TIME_SAMPLES {
+                    self.render_times.pop_front();
+                }
+                self.render_times.push_back(elapsed);
+                self.set_preview_texture(ctx, key, &fractal);
+                // Keep showing the live render if it reflects newer edits
+                self.showing_live &= self.live_render.as_ref().is_some_and(|(live_key, _)| *live_key != key);
+                self.fractal_cache = Some((key, fractal));
+            }
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.preview_job = None;
+                self.update_status(ctx, "Preview generation stopped unexpectedly", true);
+            }
+        }
+    }
+
+    fn expansions(&self) -> usize {
+        self.iterations.saturating_sub(1) as usize
+    }
+
+    // The per-level decay factors used for rendering; a single entry applies
+    // to every level
+    fn decay_curve(&self) -> Vec<f32> {
+        if self.use_decay_curve {
+            self.level_decays[..self.expansions().min(self.level_decays.len())].to_vec()
+        } else {
+            vec![self.decay]
+        }
+    }
+
+    fn render_key(&self) -> RenderKey {
+        self.render_key_for(&self.pattern, self.iterations)
+    }
+
+    fn render_key_for(&self, pattern: &Pattern, iterations: u32) -> RenderKey {
+        let mut hasher = DefaultHasher::new();
+        pattern.hash(&mut hasher);
+        self.layers.hash(&mut hasher);
+        let pattern_hash = hasher.finish();
+
+        let mut hasher = DefaultHasher::new();
+        for decay in self.decay_curve() {
+            decay.to_bits().hash(&mut hasher);
+        }
+        RenderKey {
+            iterations,
+            decay_hash: hasher.finish(),
+            pattern_hash,
+        }
+    }
+
+    // Returns the fractal for the current settings, rendering only if the
+    // cached one is stale
+    fn current_fractal(&mut self) -> Result<&[Vec<Color>], String> {
+        let key = self.render_key();
+        if self.fractal_cache.as_ref().map(|(cached_key, _)| *cached_key) != Some(key) {
+            let fractal = render_layered(&self.pattern, &self.layers, self.iterations, &self.decay_curve())?;
+            self.fractal_cache = Some((key, fractal));
+        }
+        Ok(&self.fractal_cache.as_ref().expect("cache was just filled").1)
+    }
+
+    fn set_preview_texture(&mut self, ctx: &egui::Context, key: RenderKey, fractal: &[Vec<Color>]) {
+        self.preview_texture = Some(fractal_texture(ctx, "preview", fractal, &self.display_options));
+        self.histogram = Some(luminance_histogram(fractal, HISTOGRAM_BINS));
+        self.color_counts = Some(color_counts(fractal, MAX_TOP_COLORS));
+        self.box_dimension = box_counting_dimension(fractal, self.dimension_threshold);
+        self.preview_key = Some(key);
+        self.preview_partial = false;
+    }
+
+    // Re-uploads the finished, live and compare textures after
+    // display_options changes, without rendering again
+    fn refresh_textures(&mut self, ctx: &egui::Context) {
+        if let Some((key, fractal)) = &self.fractal_cache {
+            if Some(*key) == self.preview_key {
+                self.preview_texture = Some(fractal_texture(ctx, "preview", fractal, &self.display_options));
+            }
+        }
+        if let Some((_, fractal)) = &self.live_render {
+            self.live_texture = Some(fractal_texture(ctx, "live_preview", fractal, &self.display_options));
+        }
+        if let Some((_, fractal)) = &self.compare_render {
+            self.compare_texture = Some(fractal_texture(ctx, "compare", fractal, &self.display_options));
+        }
+    }
+
+    // Keeps pattern B's render in step with the current settings while the
+    // comparison is shown, rendering in the background like the preview.
+    // B may have a larger base tile than A, so its iterations are capped to
+    // what fits.
+    fn update_compare(&mut self, ctx: &egui::Context) {
+        let Some(pattern) = self.compare_pattern.as_ref().filter(|_| self.compare) else {
+            return;
+        };
+        let iterations = self.iterations.min(max_iterations(pattern.longest_side()));
+        let key = self.render_key_for(pattern, iterations);
+
+        if let Some((job_key, result)) = &self.compare_job {
+            match result.try_recv() {
+                Ok(Ok(fractal)) => {
+                    let job_key = *job_key;
+                    self.compare_job = None;
+                    self.compare_texture = Some(fractal_texture(ctx, "compare", &fractal, &self.display_options));
+                    self.compare_render = Some((job_key, fractal));
+                }
+                // Turned off so the failing render isn't retried every frame
+                Ok(Err(message)) => {
+                    self.compare_job = None;
+                    self.compare = false;
+                    self.update_status(ctx, &format!("Rendering pattern B failed: {}", message), true);
+                    return;
+                }
+                Err(mpsc::TryRecvError::Empty) if *job_key == key => return,
+                Err(_) => self.compare_job = None,
+            }
+        }
+
+        if self.compare_render.as_ref().is_some_and(|(cached_key, _)| *cached_key == key) {
+            return;
+        }
+        let (sender, result) = mpsc::channel();
+        let pattern = pattern.clone();
+        let layers = self.layers.clone();
+        let decay_curve = self.decay_curve();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(render_layered(&pattern, &layers, iterations, &decay_curve));
+            ctx.request_repaint();
+        });
+        self.compare_job = Some((key, result));
+    }
+
+    fn swap_compare(&mut self, ctx: &egui::Context) {
+        if let Some(pattern) = &mut self.compare_pattern {
+            std::mem::swap(&mut self.pattern, pattern);
+            self.update_preview(ctx);
+        }
+    }
+
+    // Renders the current settings at a capped iteration count so edits show
+    // up immediately. Fewer iterations of the same generator give exactly the
+    // top levels of the full render, so colors and permutations match it.
+    fn update_live_preview(&mut self, ctx: &egui::Context) {
+        let iterations = self.iterations.min(iterations_within(self.pattern.longest_side(), LIVE_PREVIEW_SIZE));
+        let fractal = match render_layered(&self.pattern, &self.layers, iterations, &self.decay_curve()) {
+            Ok(fractal) => fractal,
+            // Turned off so the failing render isn't retried every frame
+            Err(message) => {
+                self.live_preview = false;
+                self.showing_live = false;
+                self.update_status(ctx, &format!("Live preview failed and was turned off: {}", message), true);
+                return;
+            }
+        };
+        self.live_texture = Some(fractal_texture(ctx, "live_preview", &fractal, &self.display_options));
+        self.live_render = Some((self.render_key(), fractal));
+        self.showing_live = true;
+    }
+
+    // Color data behind the displayed texture, if it is still held
+    fn displayed_fractal(&self) -> Option<&[Vec<Color>]> {
+        if self.showing_live {
+            return self.live_render.as_ref().map(|(_, fractal)| fractal.as_slice());
+        }
+        match &self.fractal_cache {
+            Some((key, fractal)) if Some(*key) == self.preview_key => Some(fractal),
+            _ => None,
+        }
+    }
+
+    // Settings behind the displayed texture
+    fn displayed_key(&self) -> Option<RenderKey> {
+        if self.showing_live {
+            self.live_render.as_ref().map(|(key, _)| *key)
+        } else {
+            self.preview_key
+        }
+    }
+
+    // Rebuilds the out-of-gamut overlay when the displayed render changes.
+    // It needs the raw colors, so nothing is shown while they aren't held.
+    fn update_gamut_overlay(&mut self, ctx: &egui::Context) {
+        let key = self.displayed_key();
+        if self.gamut_overlay.as_ref().map(|(overlay_key, _, _)| *overlay_key) == key {
+            return;
+        }
+        self.gamut_overlay = None;
+        let (Some(key), Some(fractal)) = (key, self.displayed_fractal()) else {
+            return;
+        };
+        let (width, height) = grid_size(fractal);
+        let mut flagged = 0;
+        let pixels = fractal.iter().flatten().map(|color| {
+            if color.in_gamut() {
+                egui::Color32::TRANSPARENT
+            } else {
+                flagged += 1;
+                egui::Color32::from_rgba_unmultiplied(255, 0, 255, 200)
+            }
+        }).collect();
+        let image = egui::ColorImage { size: [width, height], pixels };
+        let texture = ctx.load_texture("gamut_overlay", image, egui::TextureOptions::NEAREST);
+        self.gamut_overlay = Some((key, texture, flagged));
+    }
+
+    fn displayed_texture(&self) -> Option<&egui::TextureHandle> {
+        if self.showing_live {
+            self.live_texture.as_ref()
+        } else {
+            self.preview_texture.as_ref()
+        }
+    }
+
+    fn export_preview(&mut self, ctx: &egui::Context) {
+        if let Some(path) = self.save_dialog("png")
+            .add_filter("PNG", &["png"])
+            .add_filter("JPEG", &["jpg", "jpeg"])
+            .add_filter("WebP", &["webp"])
+            .add_filter("TIFF", &["tif", "tiff"])
+            .set_title("Export Preview")
+            .save_file() {
+                let format = match export_format(&path) {
+                    Ok(format) => format,
+                    Err(e) => {
+                        self.update_status(ctx, &e.to_string(), true);
+                        return;
+                    }
+                };
+
+                // Generate the fractal image. JPEG can't store alpha, so it
+                // always goes over the background color.
+                let mut options = self.export_options();
+                if format == ImageFormat::Jpeg {
+                    options.background = Some(self.background);
+                }
+                let region = self.export_view.then(|| self.visible_region()).flatten();
+                let supersampled;
+                let fractal = match self.ssaa_extra_levels() {
+                    Some(extra_levels) => {
+                        let decay_curve = self.decay_curve();
+                        supersampled = catch_panic(|| {
+                            let mut fractal = render_supersampled(&self.pattern, self.iterations, &decay_curve, extra_levels);
+                            composite_layers(&mut fractal, &self.layers, |layer| {
+                                render_supersampled(layer, self.iterations, &decay_curve, extra_levels)
+                            });
+                            fractal
+                        });
+                        supersampled.as_deref().map_err(String::clone)
+                    }
+                    None => self.current_fractal(),
+                };
+                let fractal = match fractal {
+                    Ok(fractal) => fractal,
+                    Err(message) => {
+                        self.update_status(ctx, &format!("Rendering failed: {}", message), true);
+                        return;
+                    }
+                };
+                let cropped;
+                let fractal = match region {
+                    Some(region) => {
+                        let (width, height) = grid_size(fractal);
+                        let (width, height) = (width as f32, height as f32);
+                        let (x, y) = ((region.min.x * width).floor(), (region.min.y * height).floor());
+                        let (right, bottom) = ((region.max.x * width).ceil(), (region.max.y * height).ceil());
+                        cropped = crop_fractal(
+                            fractal, x as usize, y as usize, (right - x) as usize, (bottom - y) as usize,
+                        );
+                        &cropped
+                    }
+                    None => fractal,
+                };
+                let image = export_image(fractal, &options);
+                let seams = options.tiled && !options.seamless && !edges_wrap(fractal);
+
+                // Save the image; PNGs carry the document that regenerates them,
+                // less any export settings it has no place for
+                let (result, unrecorded) = if format == ImageFormat::Png {
+                    let unrecorded = self.unrecorded_export_settings(&options, region.is_some());
+                    (save_png_with_document(&image, &path, &self.current_document()), unrecorded)
+                } else {
+                    (save_image(&image, &path, self.jpeg_quality), Vec::new())
+                };
+                match result {
+                    Ok(_) => {
+                        let mut message = if seams {
+                            "Preview exported; its edges don't match, so the tiles show seams".to_string()
+                        } else {
+                            "Preview exported successfully".to_string()
+                        };
+                        if !unrecorded.is_empty() {
+                            message += &format!("; its embedded parameters leave out {}", unrecorded.join(", "));
+                        }
+                        self.update_status(ctx, &message, false);
+                    }
+                    Err(e) => self.update_status(ctx, &format!("Failed to export preview: {}", e), true),
+                }
+        }
+    }
+
+    // Settings used for an export that a FractalDocument can't record, so
+    // importing the image back doesn't reproduce it exactly. The background
+    // is part of the document and the bit depth is kept by the file itself.
+    fn unrecorded_export_settings(&self, options: &ExportOptions, cropped: bool) -> Vec<&'static str> {
+        let mut unrecorded = Vec::new();
+        if self.ssaa_extra_levels().is_some() {
+            unrecorded.push("anti-aliasing");
+        }
+        if cropped {
+            unrecorded.push("the cropped view");
+        }
+        if options.gamma != 1.0 {
+            unrecorded.push("gamma");
+        }
+        if options.resize.is_some() {
+            unrecorded.push("the output size");
+        }
+        if options.seamless || options.tiled {
+            unrecorded.push("tiling");
+        }
+        if options.color_vision != ColorVision::Normal {
+            unrecorded.push("the color vision simulation");
+        }
+        if options.border > 0 {
+            unrecorded.push("the border");
+        }
+        if options.caption.is_some() {
+            unrecorded.push("the caption");
+        }
+        unrecorded
+    }
+
+    // Puts the rendered fractal on the system clipboard with the export settings
+    fn copy_image(&mut self, ctx: &egui::Context) {
+        let options = self.export_options();
+        let image = match self.current_fractal() {
+            Ok(fractal) => export_image(fractal, &options).to_rgba8(),
+            Err(message) => {
+                self.update_status(ctx, &format!("Rendering failed: {}", message), true);
+                return;
+            }
+        };
+        let data = arboard::ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: Cow::Owned(image.into_raw()),
+        };
+        let result = match &mut self.clipboard {
+            Some(clipboard) => clipboard.set_image(data),
+            None => arboard::Clipboard::new().and_then(|clipboard| {
+                self.clipboard.insert(clipboard).set_image(data)
+            }),
+        };
+        match result {
+            Ok(()) => self.update_status(ctx, "Image copied to clipboard", false),
+            Err(e) => self.update_status(ctx, &format!("Failed to copy image: {}", e), true),
+        }
+    }
+
+    // The part of the image visible in the preview, as fractions of its width
+    // and height from 0 to 1, so it applies at any resolution
+    fn visible_region(&self) -> Option<egui::Rect> {
+        let preview_rect = self.last_preview_rect?;
+        let fit = self.usable_fit_factor(preview_rect)?;
+        let size = self.displayed_texture()?.size_vec2() * fit * self.zoom_level;
+        let min = preview_rect.min + self.pan_offset + (preview_rect.size() - size) * 0.5;
+        let image_rect = egui::Rect::from_min_size(min, size);
+        let visible = image_rect.intersect(preview_rect);
+        if !visible.is_positive() {
+            return None;
+        }
+        Some(egui::Rect::from_min_max(
+            ((visible.min - image_rect.min) / size).to_pos2(),
+            ((visible.max - image_rect.min) / size).to_pos2(),
+        ))
+    }
+
+    // Sets everything a preset covers and renders with the new settings
+    fn apply_preset(&mut self, ctx: &egui::Context, preset: Preset) {
+        self.iterations = preset.iterations(self.pattern.longest_side());
+        self.decay = Preset::DECAY;
+        self.use_decay_curve = false;
+        self.ssaa = preset.ssaa_levels().is_some();
+        if let Some(levels) = preset.ssaa_levels() {
+            self.ssaa_levels = levels;
+        }
+        self.sixteen_bit = preset.sixteen_bit();
+        self.update_preview(ctx);
+    }
+
+    // The preset the current settings match, None once any was changed by hand
+    fn current_preset(&self) -> Option<Preset> {
+        Preset::ALL.into_iter().find(|&preset| {
+            self.iterations == preset.iterations(self.pattern.longest_side())
+                && self.decay == Preset::DECAY
+                && !self.use_decay_curve
+                && self.ssaa == preset.ssaa_levels().is_some()
+                && preset.ssaa_levels().is_none_or(|levels| self.ssaa_levels == levels)
+                && self.sixteen_bit == preset.sixteen_bit()
+        })
+    }
+
+    // Extra iterations rendered for anti-aliasing, limited so the supersampled
+    // grid stays within MAX_SSAA_SIZE; None when SSAA is off or can't fit
+    fn ssaa_extra_levels(&self) -> Option<u32> {
+        if !self.ssaa {
+            return None;
+        }
+        let headroom = iterations_within(self.pattern.longest_side(), MAX_SSAA_SIZE).saturating_sub(self.iterations);
+        let extra_levels = self.ssaa_levels.min(headroom);
+        (extra_levels > 0).then_some(extra_levels)
+    }
+
+    fn export_svg(&mut self, ctx: &egui::Context) {
+        // Every cell becomes an SVG element, so large renders are refused
+        let size = self.pattern.longest_side().pow(self.iterations);
+        if size > MAX_SVG_SIZE {
+            self.update_status(
+                ctx,
+                &format!("SVG export is limited to {0}x{0} cells; lower the iterations", MAX_SVG_SIZE),
+                true,
+            );
+            return;
+        }
+
+        if let Some(path) = self.save_dialog("svg")
+            .add_filter("SVG", &["svg"])
+            .set_title("Export SVG")
+            .save_file() {
+                let result = self.current_fractal()
+                    .and_then(|fractal| fs::write(&path, fractal_to_svg(fractal)).map_err(|e| e.to_string()));
+                match result {
+                    Ok(_) => self.update_status(ctx, "SVG exported successfully", false),
+                    Err(e) => self.update_status(ctx, &format!("Failed to export SVG: {}", e), true),
+                }
+        }
+    }
+
+    fn export_base_tile(&mut self, ctx: &egui::Context) {
+        if let Some(path) = self.save_dialog("png")
+            .add_filter("PNG", &["png"])
+            .set_title("Export Base Tile")
+            .save_file() {
+                match base_tile_image(&self.pattern, self.tile_scale).save(&path) {
+                    Ok(_) => self.update_status(ctx, "Base tile exported successfully", false),
+                    Err(e) => self.update_status(ctx, &format!("Failed to export base tile: {}", e), true),
+                }
+        }
+    }
+
+    fn export_contact_sheet(&mut self, ctx: &egui::Context) {
+        let decays: Result<Vec<f32>, _> = self.contact_decays
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect();
+        let decays = match decays {
+            Ok(decays) if !decays.is_empty() && decays.iter().all(|d| (0.0..=1.0).contains(d)) => decays,
+            _ => {
+                self.update_status(ctx, "Decay values must be comma-separated numbers between 0 and 1", true);
+                return;
+            }
+        };
+
+        if let Some(path) = self.save_dialog("png")
+            .add_filter("PNG", &["png"])
+            .set_title("Export Contact Sheet")
+            .save_file() {
+                let mut sheet = DynamicImage::ImageRgba8(
+                    contact_sheet(&self.pattern, self.contact_iterations, &decays, self.contact_columns),
+                );
+                if !self.pattern.name.is_empty() {
+                    draw_caption(&mut sheet, &Caption {
+                        text: self.pattern.name.clone(),
+                        corner: CaptionCorner::TopLeft,
+                        color: Color::new(1.0, 1.0, 1.0, 1.0),
+                    });
+                }
+                match sheet.save(&path) {
+                    Ok(_) => self.update_status(ctx, "Contact sheet exported successfully", false),
+                    Err(e) => self.update_status(ctx, &format!("Failed to export contact sheet: {}", e), true),
+                }
+        }
+    }
+
+    // Saves each recursion level as its own image, to show how the fractal builds up
+    fn export_levels(&mut self, ctx: &egui::Context) {
+        if let Some(dir) = rfd::FileDialog::new()
+            .set_title("Export Levels")
+            .pick_folder() {
+                let decay_curve = self.decay_curve();
+                let outcome = catch_panic(|| export_levels(&self.pattern, self.iterations, &decay_curve, &dir));
+                match outcome {
+                    Ok(Ok(())) => self.update_status(
+                        ctx,
+                        &format!("Exported {} levels successfully", self.iterations),
+                        false,
+                    ),
+                    Ok(Err(e)) => self.update_status(ctx, &format!("Failed to export levels: {}", e), true),
+                    Err(message) => self.update_status(ctx, &format!("Rendering failed: {}", message), true),
+                }
+        }
+    }
+
+    fn export_grid(&mut self, ctx: &egui::Context) {
+        // Nested JSON spends ~50 bytes per cell, so confirm before writing a huge file
+        let (width, height) = (self.pattern.base_dim.pow(self.iterations), self.pattern.base_rows.pow(self.iterations));
+        if width.max(height) > GRID_JSON_WARN_SIZE && !self.grid_flat {
+            let confirmed = rfd::MessageDialog::new()
+                .set_title("Large grid export")
+                .set_description(format!(
+                    "The grid is {}x{} cells and will make a very large JSON file. \
+                     The compact format is several times smaller. Export anyway?",
+                    width, height
+                ))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .show() == rfd::MessageDialogResult::Yes;
+            if !confirmed {
+                return;
+            }
+        }
+
+        if let Some(path) = self.save_dialog("json")
+            .add_filter("JSON", &["json"])
+            .set_title("Export Grid")
+            .save_file() {
+                let (iterations, decay_curve, flat) = (self.iterations, self.decay_curve(), self.grid_flat);
+                let result = self.current_fractal()
+                    .map_err(PatternError::ValidationError)
+                    .and_then(|fractal| fractal_to_json(fractal, iterations, &decay_curve, flat))
+                    .and_then(|json| Ok(fs::write(&path, json)?));
+                match result {
+                    Ok(_) => self.update_status(ctx, "Grid exported successfully", false),
+                    Err(e) => self.update_status(ctx, &format!("Failed to export grid: {}", e), true),
+                }
+        }
+    }
+
+    // Width over height of the rendered grid, which is 1 for square tiles
+    fn native_aspect(&self) -> f32 {
+        (self.pattern.base_dim as f32 / self.pattern.base_rows as f32).powi(self.iterations as i32)
+    }
+
+    // The export steps that change colors, which the preview applies too so
+    // it matches what's saved. Color vision simulation is always shown.
+    fn preview_options(&self) -> DisplayOptions {
+        let export = self.export_options();
+        DisplayOptions {
+            encode: ExportOptions {
+                gamma: export.gamma,
+                background: export.background,
+                color_vision: self.color_vision,
+                ..ExportOptions::default()
+            },
+            filter: if self.smooth_preview { egui::TextureFilter::Linear } else { egui::TextureFilter::Nearest },
+        }
+    }
+
+    fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            gamma: if self.gamma_correction { self.gamma } else { 1.0 },
+            sixteen_bit: self.sixteen_bit,
+            resize: self.custom_output_size.then_some((self.output_width, self.output_height)),
+            smooth_resize: self.smooth_resize,
+            background: self.use_background.then_some(self.background),
+            border: self.border_width,
+            border_color: self.border_color,
+            seamless: self.seamless_tile,
+            tiled: self.tiled_export,
+            color_vision: if self.export_color_vision { self.color_vision } else { ColorVision::Normal },
+            caption: self.use_caption.then(|| Caption {
+                text: if self.caption_text.trim().is_empty() {
+                    self.settings_summary()
+                } else {
+                    self.caption_text.clone()
+                },
+                corner: self.caption_corner,
+                color: self.caption_color,
+            }),
+        }
+    }
+
+    // Default caption text, describing how the image was rendered
+    fn settings_summary(&self) -> String {
+        if self.use_decay_curve {
+            format!("Iterations: {}, decay per level", self.iterations)
+        } else {
+            format!("Iterations: {}, decay: {:.2}", self.iterations, self.decay)
+        }
+    }
+
+    fn export_animation(&mut self) {
+        if let Some(path) = self.save_dialog("gif")
+            .add_filter("GIF", &["gif"])
+            .set_title("Export Animation")
+            .save_file() {
+                let frames_done = Arc::new(AtomicUsize::new(0));
+                let (sender, result) = mpsc::channel();
+                let progress = frames_done.clone();
+                let pattern = self.pattern.clone();
+                let layers = self.layers.clone();
+                let options = self.export_options();
+                let iterations = self.iterations;
+                let start_decay = self.animation_start_decay;
+                let end_decay = self.animation_end_decay;
+                let frame_count = self.animation_frames;
+
+                std::thread::spawn(move || {
+                    let outcome = catch_panic(|| export_decay_animation(
+                        &pattern, &layers, iterations, start_decay, end_decay, frame_count, &options, &path,
+                        |done| {
+                            progress.fetch_max(done, Ordering::Relaxed);
+                        },
+                    ));
+                    let _ = sender.send(outcome.and_then(|outcome| {
+                        outcome.map(|_| "Animation exported successfully").map_err(|e| e.to_string())
+                    }));
+                });
+
+                self.animation_export = Some(AnimationExport {
+                    frames_done,
+                    frame_count,
+                    result,
+                });
+        }
+    }
+
+    // Morphs from the current pattern to pattern B of the A/B compare
+    fn export_morph(&mut self, ctx: &egui::Context) {
+        let Some(target) = self.compare_pattern.clone() else {
+            self.update_status(ctx, "Set pattern B to morph towards first", true);
+            return;
+        };
+        if let Some(path) = self.save_dialog("gif")
+            .add_filter("GIF", &["gif"])
+            .set_title("Export Morph")
+            .save_file() {
+                let frames_done = Arc::new(AtomicUsize::new(0));
+                let (sender, result) = mpsc::channel();
+                let progress = frames_done.clone();
+                let pattern = self.pattern.clone();
+                let iterations = self.iterations;
+                let decay_curve = self.decay_curve();
+                let frame_count = self.animation_frames;
+                let mode = self.morph_mode;
+
+                std::thread::spawn(move || {
+                    let outcome = catch_panic(|| export_morph_animation(
+                        &pattern, &target, iterations, &decay_curve, frame_count, mode, &path,
+                        |done| {
+                            progress.fetch_max(done, Ordering::Relaxed);
+                        },
+                    ));
+                    let _ = sender.send(outcome.and_then(|outcome| {
+                        outcome.map(|used| {
+                            if used == mode {
+                                "Morph exported successfully"
+                            } else {
+                                "Morph exported with a cross-fade, since the permutations or settings differ"
+                            }
+                        }).map_err(|e| e.to_string())
+                    }));
+                });
+
+                self.animation_export = Some(AnimationExport {
+                    frames_done,
+                    frame_count,
+                    result,
+                });
+        }
+    }
+
+    // Loads a saved pattern into the B slot of the A/B compare
+    fn load_compare_pattern(&mut self, ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Pattern", &["json", DOCUMENT_EXTENSION])
+            .set_title("Load Pattern B")
+            .pick_file() {
+                match read_pattern(&path) {
+                    Ok(pattern) => {
+                        self.compare_pattern = Some(pattern);
+                        self.update_status(ctx, "Pattern B loaded", false);
+                    }
+                    Err(e) => self.update_status(ctx, &format!("Failed to load pattern B: {}", e), true),
+                }
+        }
+    }
+
+    // Reports the outcome of a finished animation export
+    fn poll_animation_export(&mut self, ctx: &egui::Context) {
+        let Some(export) = &self.animation_export else {
+            return;
+        };
+        match export.result.try_recv() {
+            Ok(Ok(message)) => {
+                self.animation_export = None;
+                self.update_status(ctx, message, false);
+            }
+            Ok(Err(e)) => {
+                self.animation_export = None;
+                self.update_status(ctx, &format!("Failed to export animation: {}", e), true);
+            }
+            Err(mpsc::TryRecvError::Empty) => ctx.request_repaint(),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.animation_export = None;
+                self.update_status(ctx, "Animation export stopped unexpectedly", true);
+            }
+        }
+    }
+
+    // Renders a PNG too large to hold in memory, a block at a time. Layers
+    // and whole-image export options are left out.
+    fn export_chunked(&mut self) {
+        if let Some(path) = self.save_dialog("png")
+            .add_filter("PNG", &["png"])
+            .set_title("Export Large PNG")
+            .save_file() {
+                let bands_done = Arc::new(AtomicUsize::new(0));
+                let (sender, result) = mpsc::channel();
+                let progress = bands_done.clone();
+                let pattern = self.pattern.clone();
+                let iterations = self.chunked_iterations;
+                let decay_curve = self.decay_curve();
+                let export = self.export_options();
+                let options = ExportOptions {
+                    gamma: export.gamma,
+                    sixteen_bit: export.sixteen_bit,
+                    background: export.background,
+                    color_vision: export.color_vision,
+                    ..ExportOptions::default()
+                };
+                let band_count = pattern.base_rows.pow(iterations).div_ceil(CHUNK_SIZE);
+
+                std::thread::spawn(move || {
+                    let outcome = catch_panic(|| export_png_chunked(
+                        &pattern, iterations, &decay_curve, &options, &path,
+                        |done| progress.store(done, Ordering::Relaxed),
+                    ));
+                    let _ = sender.send(outcome.and_then(|outcome| outcome.map_err(|e| e.to_string())));
+                });
+
+                self.chunked_export = Some(ChunkedExport {
+                    bands_done,
+                    band_count,
+                    result,
+                });
+        }
+    }
+
+    fn poll_chunked_export(&mut self, ctx: &egui::Context) {
+        let Some(export) = &self.chunked_export else {
+            return;
+        };
+        match export.result.try_recv() {
+            Ok(Ok(())) => {
+                self.chunked_export = None;
+                self.update_status(ctx, "Large PNG exported successfully", false);
+            }
+            Ok(Err(e)) => {
+                self.chunked_export = None;
+                self.update_status(ctx, &format!("Failed to export large PNG: {}", e), true);
+            }
+            Err(mpsc::TryRecvError::Empty) => ctx.request_repaint(),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.chunked_export = None;
+                self.update_status(ctx, "Large PNG export stopped unexpectedly", true);
+            }
+        }
+    }
+
+    fn update_preview_panel(&mut self, ui: &mut egui::Ui) {
+        
+        if self.displayed_texture().is_none() {
+            return;
+        }
+
+        // A full render at a new size invalidates the zoom and pan chosen for
+        // the old one, so re-fit before any view math runs this frame. Live
+        // renders and unfinished levels are skipped so the view doesn't reset
+        // while editing or as the render grows.
+        let texture_size = self.preview_texture.as_ref().map(|texture| texture.size_vec2());
+        if !self.preview_partial && texture_size != self.last_texture_size {
+            // The first texture keeps the view restored from settings
+            if self.last_texture_size.is_some() {
+                self.reset_view();
+            }
+            self.last_texture_size = texture_size;
+        }
+        let (preview_response, painter) = ui.allocate_painter(
+            ui.available_size(),
+            egui::Sense::click_and_drag()
+        );
+        let preview_rect = preview_response.rect;
+        self.last_preview_rect = Some(preview_rect);
+        let pixels_per_point = ui.ctx().pixels_per_point();
+
+        // Handle zooming with scroll wheel
+        let zoom_delta = -ui.input(|i| i.smooth_scroll_delta.y / 50.0);
+        if zoom_delta != 0.0 && preview_rect.contains(ui.input(|i| i.pointer.hover_pos().unwrap_or_default())) {
+            self.handle_zoom(
+                zoom_delta,
+                ui.input(|i| i.pointer.hover_pos().unwrap_or_default()),
+                preview_rect,
+                pixels_per_point,
+            );
+        }
+
+        // Dragging the A/B divider takes priority over panning beneath it
+        let compare_texture = self.compare_texture.as_ref().filter(|_| self.compare).cloned();
+        let divider_x = preview_rect.left() + preview_rect.width() * self.compare_split;
+        if compare_texture.is_some() {
+            let handle = egui::Rect::from_center_size(
+                egui::pos2(divider_x, preview_rect.center().y),
+                egui::vec2(COMPARE_HANDLE_WIDTH, preview_rect.height()),
+            );
+            let response = ui.interact(handle, ui.id().with("compare_divider"), egui::Sense::drag())
+                .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+            if response.dragged() {
+                self.compare_split = (self.compare_split + response.drag_delta().x / preview_rect.width()).clamp(0.0, 1.0);
+            }
+        }
+
+        // Handle panning
+        if preview_response.dragged() {
+            self.pan_offset += preview_response.drag_delta();
+            self.dragging = true;
+            self.clamp_pan_offset(preview_rect);
+        } else {
+            self.dragging = false;
+        }
+
+        // Keyboard panning and zooming, while the pointer is over the preview
+        // and no text field is taking the keys
+        self.preview_hovered = preview_response.hovered();
+        if preview_response.hovered() && !ui.ctx().wants_keyboard_input() {
+            self.handle_view_keys(ui, preview_rect, pixels_per_point);
+        }
 
         // Get texture reference after all mutable operations
-        let texture = self.preview_texture.as_ref().unwrap();
+        let texture = self.displayed_texture().unwrap();
         let texture_size = texture.size_vec2();
 
         // Calculate display rect
@@ -522,8 +2204,421 @@ impl FractalApp {
             egui::Rect::from_min_max(egui::Pos2::new(0.0, 0.0), egui::Pos2::new(1.0, 1.0)),
             egui::Color32::WHITE
         );
+
+        if let Some((_, overlay, _)) = self.gamut_overlay.as_ref().filter(|_| self.show_gamut) {
+            painter.image(
+                overlay.id(),
+                rect,
+                egui::Rect::from_min_max(egui::Pos2::ZERO, egui::pos2(1.0, 1.0)),
+                egui::Color32::WHITE,
+            );
+        }
+
+        // Pattern B fills the same rect, clipped to the right of the divider
+        if let Some(compare) = &compare_texture {
+            let clip = egui::Rect::from_min_max(egui::pos2(divider_x, preview_rect.top()), preview_rect.max);
+            painter.with_clip_rect(clip).image(
+                compare.id(),
+                rect,
+                egui::Rect::from_min_max(egui::Pos2::ZERO, egui::pos2(1.0, 1.0)),
+                egui::Color32::WHITE,
+            );
+            painter.vline(divider_x, preview_rect.y_range(), egui::Stroke::new(2.0, egui::Color32::WHITE));
+            for (label, align, offset) in [("A", egui::Align2::RIGHT_TOP, -6.0), ("B", egui::Align2::LEFT_TOP, 6.0)] {
+                painter.text(
+                    egui::pos2(divider_x + offset, preview_rect.top() + 6.0),
+                    align,
+                    label,
+                    egui::FontId::proportional(14.0),
+                    egui::Color32::WHITE,
+                );
+            }
+        }
+
+        // Subdivision grid, drawn over the texture so it tracks pan and zoom.
+        // Lines finer than the texture or closer than a couple of points
+        // would only darken the image.
+        if self.show_grid {
+            let level = self.grid_level as i32;
+            let divisions = egui::vec2(
+                (self.pattern.base_dim as f32).powi(level),
+                (self.pattern.base_rows as f32).powi(level),
+            );
+            let spacing = size / divisions;
+            if spacing.min_elem() >= 2.0 && divisions.x <= texture_size.x && divisions.y <= texture_size.y {
+                let stroke = egui::Stroke::new(1.0, self.grid_color);
+                for i in 1..divisions.x as usize {
+                    let x = rect.min.x + spacing.x * i as f32;
+                    painter.line_segment([egui::pos2(x, rect.min.y), egui::pos2(x, rect.max.y)], stroke);
+                }
+                for i in 1..divisions.y as usize {
+                    let y = rect.min.y + spacing.y * i as f32;
+                    painter.line_segment([egui::pos2(rect.min.x, y), egui::pos2(rect.max.x, y)], stroke);
+                }
+            }
+        }
+
+        // In descend mode, outline the subdivisions a click can step into
+        if self.descend_mode {
+            let region = self.descent_region();
+            let region_rect = egui::Rect::from_min_size(rect.min + region.min.to_vec2() * size, region.size() * size);
+            let stroke = egui::Stroke::new(1.0, egui::Color32::WHITE);
+            painter.rect_stroke(region_rect, 0.0, stroke, egui::StrokeKind::Middle);
+            let (rows, cols) = (self.pattern.base_rows, self.pattern.base_dim);
+            for i in 1..cols {
+                let x = region_rect.min.x + region_rect.width() * i as f32 / cols as f32;
+                painter.line_segment([egui::pos2(x, region_rect.min.y), egui::pos2(x, region_rect.max.y)], stroke);
+            }
+            for i in 1..rows {
+                let y = region_rect.min.y + region_rect.height() * i as f32 / rows as f32;
+                painter.line_segment([egui::pos2(region_rect.min.x, y), egui::pos2(region_rect.max.x, y)], stroke);
+            }
+            if preview_response.clicked() {
+                if let Some(pos) = preview_response.interact_pointer_pos().filter(|pos| rect.contains(*pos)) {
+                    self.descend_at(pos, rect, pixels_per_point);
+                }
+            }
+            if preview_response.secondary_clicked() {
+                self.ascend(pixels_per_point);
+            }
+        }
+
+        // Effective zoom, where 100% maps one fractal cell to one screen pixel
+        if let Some(pixel_perfect) = self.pixel_perfect_zoom(preview_rect, pixels_per_point) {
+            painter.text(
+                preview_rect.right_bottom() - egui::vec2(8.0, 8.0),
+                egui::Align2::RIGHT_BOTTOM,
+                format!("{:.0}%", self.zoom_level / pixel_perfect * 100.0),
+                egui::FontId::proportional(14.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        // Pixel inspector: map the cursor back to fractal coordinates
+        if self.dragging {
+            return;
+        }
+        let Some(mouse_pos) = preview_response.hover_pos() else {
+            return;
+        };
+        if !rect.contains(mouse_pos) {
+            return;
+        }
+        let Some(fractal) = self.displayed_fractal() else {
+            return;
+        };
+        let (width, height) = grid_size(fractal);
+        let rel = (mouse_pos - rect.min) / rect.size();
+        let x = ((rel.x * width as f32) as usize).min(width - 1);
+        let y = ((rel.y * height as f32) as usize).min(height - 1);
+        let color = fractal[y][x];
+        preview_response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("({}, {})", x, y));
+            ui.label(format!("R {:.3}  G {:.3}  B {:.3}  A {:.3}", color.r, color.g, color.b, color.a));
+        });
+    }
+
+
+    fn randomize_pattern(&mut self) {
+        let random = Pattern::random(self.pattern.base_rows, self.pattern.base_dim, self.random_seed);
+        self.pattern.pixels = random.pixels;
+        self.custom_perm_edit = None;
+    }
+
+    fn randomize_permutations(&mut self) {
+        self.pattern.randomize_permutations(self.random_seed);
+        self.custom_perm_edit = None;
+    }
+
+    // Paints every pixel of the grid being edited with fill_color, keeping
+    // the permutations
+    fn fill_pixels(&mut self) {
+        for pixel in edited_pixels(&mut self.pattern, self.edit_initial).iter_mut().flatten() {
+            pixel.color = self.fill_color;
+        }
+    }
+
+    // Goes back to the default pattern after confirming; the edit can be undone
+    fn reset_pattern(&mut self, ctx: &egui::Context) {
+        let confirmed = rfd::MessageDialog::new()
+            .set_title("Reset pattern")
+            .set_description("Replace the pattern with the default one? You can undo this.")
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show() == rfd::MessageDialogResult::Yes;
+        if !confirmed {
+            return;
+        }
+        self.pattern = create_base_pattern();
+        self.custom_perm_edit = None;
+        self.edit_initial = false;
+        self.update_preview(ctx);
+    }
+
+    // Fills the explorer with seeded variations of the current pattern,
+    // rendered small like the live preview
+    fn generate_mutations(&mut self, ctx: &egui::Context) {
+        let iterations = self.iterations.min(iterations_within(self.pattern.longest_side(), MUTATION_THUMBNAIL_SIZE));
+        let decay_curve = self.decay_curve();
+        let mutations = (0..MUTATION_COUNT).map(|i| {
+            let pattern = self.pattern.mutated(self.mutation_strength, self.random_seed.wrapping_add(i));
+            let fractal = render_layered(&pattern, &self.layers, iterations, &decay_curve)?;
+            let texture = fractal_texture(ctx, &format!("mutation_{}", i), &fractal, &self.display_options);
+            Ok((pattern, texture))
+        }).collect::<Result<_, String>>();
+        match mutations {
+            Ok(mutations) => self.mutations = mutations,
+            Err(message) => self.update_status(ctx, &format!("Rendering a variation failed: {}", message), true),
+        }
+    }
+
+    // Cost of the current settings, laid out bottom-up above the status line
+    fn stats_ui(&self, ui: &mut egui::Ui) {
+        let (rows, cols) = (self.pattern.base_rows as u64, self.pattern.base_dim as u64);
+        let pixels = rows.checked_pow(self.iterations)
+            .zip(cols.checked_pow(self.iterations))
+            .and_then(|(height, width)| height.checked_mul(width));
+        ui.label(match estimate_memory(self.pattern.base_rows, self.pattern.base_dim, self.iterations) {
+            Some(memory) => format!("Memory: ~{}", format_bytes(memory)),
+            None => "Memory: too much to count".to_string(),
+        });
+        ui.label(match pixels {
+            Some(pixels) => format!("Pixels: {}", pixels),
+            None => "Pixels: too many to count".to_string(),
+        });
+        ui.label(match self.render_times.back() {
+            Some(last) => {
+                let average = self.render_times.iter().sum::<Duration>() / self.render_times.len() as u32;
+                format!("Render: {} ms (avg {} ms)", last.as_millis(), average.as_millis())
+            }
+            None => "Render: -".to_string(),
+        });
+        ui.separator();
+    }
+
+    fn explore_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.mutation_strength, 0.0..=1.0).text("Strength"));
+        if ui.button("Generate").on_hover_text("Uses the seed above").clicked() {
+            self.generate_mutations(ui.ctx());
+        }
+
+        let mut adopted = None;
+        egui::Grid::new("mutations").show(ui, |ui| {
+            for (i, (_, texture)) in self.mutations.iter().enumerate() {
+                let size = texture.size_vec2() * (MUTATION_THUMBNAIL_SIZE as f32 / texture.size_vec2().max_elem());
+                if ui.add(egui::ImageButton::new((texture.id(), size)))
+                    .on_hover_text("Continue from this variation")
+                    .clicked() {
+                    adopted = Some(i);
+                }
+                if (i + 1) % MUTATION_COLUMNS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        // Adopting a variation moves on to fresh ones of it
+        if let Some(i) = adopted {
+            self.pattern = self.mutations.swap_remove(i).0;
+            self.custom_perm_edit = None;
+            self.random_seed = self.random_seed.wrapping_add(MUTATION_COUNT);
+            self.generate_mutations(ui.ctx());
+            self.update_preview(ui.ctx());
+        }
+    }
+
+    fn save_palette(&mut self, ctx: &egui::Context) {
+        let Some(path) = config_path(PALETTE_FILE) else {
+            self.update_status(ctx, "No config directory to save the palette in", true);
+            return;
+        };
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| {
+            let json = serde_json::to_string_pretty(&self.palette)?;
+            fs::write(&path, json)
+        });
+        if let Err(e) = result {
+            self.update_status(ctx, &format!("Failed to save palette: {}", e), true);
+        }
+    }
+
+    fn import_gpl_palette(&mut self, ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("GIMP Palette", &["gpl"])
+            .set_title("Import GIMP Palette")
+            .pick_file() {
+                match load_gpl_palette(path.to_str().unwrap_or_default()) {
+                    Ok(colors) => {
+                        let count = colors.len();
+                        self.palette.extend(colors);
+                        self.save_palette(ctx);
+                        self.update_status(ctx, &format!("Imported {} palette colors", count), false);
+                    }
+                    Err(e) => {
+                        self.update_status(ctx, &format!("Failed to import palette: {}", e), true);
+                    }
+                }
+        }
+    }
+
+    // Shows the open command palette, returning the command picked with a
+    // click or Enter. Escape or a click outside closes it.
+    fn show_command_palette(&mut self, ctx: &egui::Context) -> Option<Command> {
+        let palette = self.command_palette.as_mut()?;
+        let mut chosen = None;
+        let response = egui::Modal::new(egui::Id::new("command_palette")).show(ctx, |ui| {
+            ui.set_width(COMMAND_PALETTE_WIDTH);
+            let query = ui.add(egui::TextEdit::singleline(&mut palette.query)
+                .hint_text("Type a command")
+                .desired_width(f32::INFINITY));
+            query.request_focus();
+            if query.changed() {
+                palette.selected = 0;
+            }
+
+            let matches: Vec<Command> = Command::ALL.into_iter()
+                .filter(|command| command.matches(&palette.query))
+                .collect();
+            ui.input(|i| {
+                if i.key_pressed(egui::Key::ArrowDown) {
+                    palette.selected += 1;
+                }
+                if i.key_pressed(egui::Key::ArrowUp) {
+                    palette.selected = palette.selected.saturating_sub(1);
+                }
+            });
+            palette.selected = palette.selected.min(matches.len().saturating_sub(1));
+
+            ui.separator();
+            if matches.is_empty() {
+                ui.weak("No matching commands");
+            }
+            for (i, command) in matches.iter().enumerate() {
+                if ui.selectable_label(i == palette.selected, command.name()).clicked() {
+                    chosen = Some(*command);
+                }
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                chosen = chosen.or(matches.get(palette.selected).copied());
+            }
+        });
+        if chosen.is_some() || response.should_close() {
+            self.command_palette = None;
+        }
+        chosen
+    }
+
+    // Undo and redo are returned rather than applied, so they go through the
+    // same path as the shortcuts
+    fn run_command(&mut self, ctx: &egui::Context, command: Command) -> Option<HistoryAction> {
+        match command {
+            Command::SaveProject => self.save_project(ctx),
+            Command::Open => self.load_pattern(ctx),
+            Command::ExportPatternOnly => self.save_pattern(ctx),
+            Command::ExportImage => self.export_preview(ctx),
+            Command::ExportSvg => self.export_svg(ctx),
+            Command::ExportGrid => self.export_grid(ctx),
+            Command::ExportContactSheet => self.export_contact_sheet(ctx),
+            Command::ExportBaseTile => self.export_base_tile(ctx),
+            Command::ExportAnimation => self.export_animation(),
+            Command::UpdatePreview => self.update_preview(ctx),
+            Command::ResetView => self.reset_view(),
+            Command::PixelPerfect => self.zoom_to_pixel_perfect(ctx.pixels_per_point()),
+            Command::CopyView => self.copy_view(ctx),
+            Command::CopyImage => self.copy_image(ctx),
+            Command::Randomize => self.randomize_pattern(),
+            Command::ResetPattern => self.reset_pattern(ctx),
+            Command::Undo => return Some(HistoryAction::Undo),
+            Command::Redo => return Some(HistoryAction::Redo),
+            Command::ToggleLivePreview => {
+                self.live_preview = !self.live_preview;
+                self.showing_live &= self.live_preview;
+            }
+            Command::ToggleGrid => self.show_grid = !self.show_grid,
+            Command::ToggleCompare => self.compare = !self.compare && self.compare_pattern.is_some(),
+        }
+        None
+    }
+
+    // Writes the document to the recovery file every AUTOSAVE_INTERVAL
+    // seconds if it changed since the last write or save. Nothing is written
+    // while an earlier session's recovery file is still being offered.
+    fn autosave(&mut self, ctx: &egui::Context) {
+        self.autosave_timer += ctx.input(|i| i.unstable_dt).min(0.1);
+        if self.autosave_timer < AUTOSAVE_INTERVAL || self.recovery.is_some() {
+            return;
+        }
+        self.autosave_timer = 0.0;
+        let document = self.current_document();
+        if self.autosaved.as_ref().is_none_or(|autosaved| *autosaved != document) {
+            match write_recovery(&document) {
+                Ok(()) => self.autosaved = Some(document),
+                Err(e) => self.update_status(ctx, &format!("Failed to write recovery file: {}", e), true),
+            }
+        }
+    }
+
+    // Removes the recovery file once the document is safely saved; edits
+    // after this point are autosaved again
+    fn clear_recovery(&mut self, ctx: &egui::Context) {
+        self.autosaved = Some(self.current_document());
+        if let Err(e) = remove_recovery() {
+            self.update_status(ctx, &format!("Failed to remove recovery file: {}", e), true);
+        }
     }
 
+    // Offers to restore the document left behind by a session that didn't
+    // exit cleanly
+    fn show_recovery_prompt(&mut self, ctx: &egui::Context) {
+        let Some(document) = &self.recovery else {
+            return;
+        };
+        let mut restore = None;
+        egui::Modal::new(egui::Id::new("recovery_prompt")).show(ctx, |ui| {
+            ui.heading("Recover unsaved work?");
+            let name = if document.pattern.name.is_empty() { "an unnamed pattern" } else { &document.pattern.name };
+            ui.label(format!("The last session ended without saving {}.", name));
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    restore = Some(true);
+                }
+                if ui.button("Discard").clicked() {
+                    restore = Some(false);
+                }
+            });
+        });
+        match restore {
+            Some(true) => {
+                let document = self.recovery.take().expect("prompt is only shown with a document");
+                self.apply_document(document);
+                self.update_status(ctx, "Recovered unsaved work", false);
+                self.update_preview(ctx);
+            }
+            Some(false) => {
+                self.recovery = None;
+                self.clear_recovery(ctx);
+            }
+            None => {}
+        }
+    }
+
+    fn save_settings(&self) -> std::io::Result<()> {
+        let Some(path) = config_path(SETTINGS_FILE) else {
+            return Ok(());
+        };
+        let settings = Settings {
+            iterations: self.iterations,
+            decay: self.decay,
+            zoom_level: self.zoom_level,
+            pan_offset: self.pan_offset.into(),
+            pattern_path: self.pattern_path.clone(),
+            recent_files: self.recent_files.clone(),
+            recent_names: self.recent_names.clone(),
+            max_pixel_size: self.max_pixel_size,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&settings)?)
+    }
 
     fn update_status(&mut self, _ctx: &egui::Context, message: &str, is_error: bool) {
         self.status_message = Some((message.to_string(), is_error));
@@ -532,6 +2627,20 @@ impl FractalApp {
 }
 
 impl eframe::App for FractalApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // The window is already closing, so a failure can only be reported on stderr
+        if let Err(e) = self.save_settings() {
+            eprintln!("Failed to save settings: {}", e);
+        }
+        // Quitting normally is a clean exit, whether or not the work was
+        // saved, unless an earlier recovery file is still unanswered
+        if self.recovery.is_none() {
+            if let Err(e) = remove_recovery() {
+                eprintln!("Failed to remove recovery file: {}", e);
+            }
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         if let Some(timer) = &mut self.status_timer {
             *timer -= ctx.input(|i| i.unstable_dt).min(0.1);
@@ -540,81 +2649,803 @@ impl eframe::App for FractalApp {
                 self.status_timer = None;
             }
         }
+        self.poll_animation_export(ctx);
+        self.poll_chunked_export(ctx);
+        self.autosave(ctx);
+        let title = if self.pattern.name.is_empty() {
+            APP_TITLE.to_string()
+        } else {
+            format!("{} - {}", self.pattern.name, APP_TITLE)
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+        let preview_options = self.preview_options();
+        if preview_options != self.display_options {
+            self.display_options = preview_options;
+            self.refresh_textures(ctx);
+        }
+        self.poll_preview_job(ctx);
+        self.update_compare(ctx);
+
+        // Undo/redo are applied after the panel so they aren't recorded as edits
+        let mut history_action = None;
+        let pattern_before = self.pattern.clone();
+        self.handle_dropped_files(ctx);
+        self.draw_drop_overlay(ctx);
+
+        // Shortcuts are ignored while a text field has focus so typing isn't
+        // hijacked; there Ctrl+Z is left to the field's own undo
+        if !ctx.wants_keyboard_input() {
+            history_action = ctx.input_mut(|i| {
+                if i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)
+                    || i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y) {
+                    Some(HistoryAction::Redo)
+                } else if i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z) {
+                    Some(HistoryAction::Undo)
+                } else {
+                    None
+                }
+            });
+            let [save, load, export, preview, reset_view, command_palette] = ctx.input_mut(|i| [
+                i.consume_shortcut(&SAVE_SHORTCUT),
+                i.consume_shortcut(&LOAD_SHORTCUT),
+                i.consume_shortcut(&EXPORT_SHORTCUT),
+                i.consume_shortcut(&PREVIEW_SHORTCUT) || i.consume_key(egui::Modifiers::NONE, egui::Key::Space),
+                i.consume_shortcut(&RESET_VIEW_SHORTCUT),
+                i.consume_shortcut(&COMMAND_PALETTE_SHORTCUT),
+            ]);
+            if save {
+                self.save_project(ctx);
+            }
+            if load {
+                self.load_pattern(ctx);
+            }
+            if export {
+                self.export_preview(ctx);
+            }
+            if preview {
+                self.update_preview(ctx);
+            }
+            if reset_view {
+                self.reset_view();
+            }
+            if command_palette {
+                self.command_palette = Some(CommandPalette::default());
+            }
+            if !self.preview_hovered && ctx.memory(|m| m.focused().is_none()) {
+                self.handle_selection_keys(ctx);
+            }
+        }
+        self.show_recovery_prompt(ctx);
+        if let Some(command) = self.show_command_palette(ctx) {
+            history_action = self.run_command(ctx, command).or(history_action);
+        }
+
         egui::SidePanel::left("controls").show(ctx, |ui| {
             ui.heading("Pattern Controls");
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.undo_stack.is_empty() || self.pending_edit.is_some(), egui::Button::new("Undo")).clicked() {
+                    history_action = Some(HistoryAction::Undo);
+                }
+                if ui.add_enabled(!self.redo_stack.is_empty(), egui::Button::new("Redo")).clicked() {
+                    history_action = Some(HistoryAction::Redo);
+                }
+            });
             
+            // Base size controls; changing either rebuilds the pattern grid
+            let (mut rows, mut cols) = (self.pattern.base_rows, self.pattern.base_dim);
+            let resized = ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut cols, 2..=4).text("Columns")).changed()
+                    | ui.add(egui::Slider::new(&mut rows, 2..=4).text("Rows")).changed()
+            }).inner;
+            if resized {
+                self.pattern = self.pattern.resized(rows, cols);
+                self.custom_perm_edit = None;
+            }
+
+            // Quality presets; editing any setting they cover afterwards shows Custom
+            let mut chosen_preset = None;
+            egui::ComboBox::from_label("Quality preset")
+                .selected_text(self.current_preset().map_or("Custom", Preset::name))
+                .show_ui(ui, |ui| {
+                    for preset in Preset::ALL {
+                        if ui.selectable_label(self.current_preset() == Some(preset), preset.name()).clicked() {
+                            chosen_preset = Some(preset);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Sets iterations, decay, anti-aliasing and bit depth together");
+            if let Some(preset) = chosen_preset {
+                self.apply_preset(ui.ctx(), preset);
+            }
+
             // Iteration control
-            ui.add(egui::Slider::new(&mut self.iterations, 4..=11).text("Iterations"));
-            ui.add(egui::Slider::new(&mut self.decay, 0.0..=1.0).text("Decay"));
+            let max_iterations = max_iterations(self.pattern.longest_side());
+            self.iterations = self.iterations.min(max_iterations);
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut self.iterations, 1..=max_iterations).text("Iterations"));
+                let estimate = estimate_memory(self.pattern.base_rows, self.pattern.base_dim, self.iterations);
+                let label = estimate.map_or("too large".to_string(), |estimate| format!("~{}", format_bytes(estimate)));
+                if estimate.is_none_or(|estimate| estimate > DEFAULT_MEMORY_LIMIT) {
+                    ui.colored_label(egui::Color32::from_rgb(255, 0, 0), label);
+                } else {
+                    ui.label(label);
+                }
+            });
+            ui.add_enabled(!self.use_decay_curve, egui::Slider::new(&mut self.decay, 0.0..=1.0).text("Decay"));
+            ui.checkbox(&mut self.use_decay_curve, "Decay per level");
+            if self.use_decay_curve {
+                // One factor per expansion; new levels start from the last factor.
+                // Factors past the current iteration count are kept for later.
+                let expansions = self.expansions();
+                if self.level_decays.len() < expansions {
+                    let fill = self.level_decays.last().copied().unwrap_or(self.decay);
+                    self.level_decays.resize(expansions, fill);
+                }
+                for (i, decay) in self.level_decays[..expansions].iter_mut().enumerate() {
+                    ui.add(egui::Slider::new(decay, 0.0..=1.0).text(format!("Level {}", i + 2)));
+                }
+            }
+            egui::ComboBox::from_label("Blend mode")
+                .selected_text(self.pattern.blend_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in BlendMode::ALL {
+                        ui.selectable_value(&mut self.pattern.blend_mode, mode, mode.name());
+                    }
+                });
+            ui.checkbox(&mut self.pattern.symmetry, "Mirror symmetry")
+                .on_hover_text("Mirror the top-left quadrant for a centered, symmetric fractal");
+            if self.symmetry_readout.as_ref().is_none_or(|(pattern, _)| *pattern != self.pattern) {
+                self.symmetry_readout = Some((self.pattern.clone(), symmetry_group(&self.pattern)));
+            }
+            if let Some((_, group)) = &self.symmetry_readout {
+                let names: Vec<&str> = group.elements.iter().map(Permutation::get_name).collect();
+                ui.label(format!("Symmetry group: {}", group.name()))
+                    .on_hover_text(format!("Unchanged by: {}\nIgnores mirror symmetry and the permutation depth limit", names.join(", ")));
+            }
+            ui.horizontal(|ui| {
+                let mut limited = self.pattern.perm_depth_limit.is_some();
+                ui.checkbox(&mut limited, "Limit permutation depth")
+                    .on_hover_text("Only this many levels expand through their permutations; deeper ones place the base pattern as is");
+                let mut limit = self.pattern.perm_depth_limit.unwrap_or(self.iterations).min(max_iterations);
+                ui.add_enabled(limited, egui::Slider::new(&mut limit, 0..=max_iterations).text("Levels"));
+                self.pattern.perm_depth_limit = limited.then_some(limit);
+            });
+            egui::ComboBox::from_label("Compose order")
+                .selected_text(self.pattern.compose_order.name())
+                .show_ui(ui, |ui| {
+                    for order in ComposeOrder::ALL {
+                        ui.selectable_value(&mut self.pattern.compose_order, order, order.name());
+                    }
+                })
+                .response
+                .on_hover_text("Parent first flips and turns each pixel's sub-pattern along the image's axes; pixel first turns whole sub-patterns along with their parents");
+            ui.checkbox(&mut self.pattern.invert_alpha, "Invert alpha propagation")
+                .on_hover_text("Transparent cells get more sub-pattern detail instead of less");
+            ui.checkbox(&mut self.pattern.transparent_as_color, "Transparent as color")
+                .on_hover_text("Blend transparent cells like any other color rather than as holes in the recursion");
+            ui.add_enabled(
+                !self.pattern.transparent_as_color,
+                egui::Slider::new(&mut self.pattern.alpha_cutoff, 0.0..=1.0).text("Alpha cutoff"),
+            ).on_hover_text("Pixels less opaque than this become holes in the recursion, for crisper edges");
+            ui.checkbox(&mut self.pattern.premultiplied_alpha, "Premultiplied alpha")
+                .on_hover_text("Avoid dark fringes where colors fade into transparent ones");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.pattern.normalize, "Normalize range")
+                    .on_hover_text("Stretch the rendered colors to use the full range");
+                ui.add_enabled_ui(self.pattern.normalize, |ui| {
+                    egui::ComboBox::from_id_salt("normalize_mode")
+                        .selected_text(self.pattern.normalize_mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in NormalizeMode::ALL {
+                                ui.selectable_value(&mut self.pattern.normalize_mode, mode, mode.name());
+                            }
+                        });
+                });
+            });
+            egui::ComboBox::from_label("Color space")
+                .selected_text(self.pattern.color_space.name())
+                .show_ui(ui, |ui| {
+                    for space in ColorSpace::ALL {
+                        ui.selectable_value(&mut self.pattern.color_space, space, space.name());
+                    }
+                })
+                .response
+                .on_hover_text("How the pixel colors are read; switching keeps the numbers and changes how they look");
+            egui::ComboBox::from_label("Decay model")
+                .selected_text(self.pattern.decay_model.name())
+                .show_ui(ui, |ui| {
+                    for model in DecayModel::ALL {
+                        ui.selectable_value(&mut self.pattern.decay_model, model, model.name());
+                    }
+                });
             
             // Pattern editor
             ui.heading("Base Pattern");
-            for y in 0..2 {
-                for x in 0..2 {
-                    ui.group(|ui| {
-                        ui.label(format!("Pixel [{}, {}]", y, x));
-                        let pixel = &mut self.pattern.pixels[y][x];
-                        
-                        // Color controls
-                        let mut color = [pixel.color.r, pixel.color.g, pixel.color.b, pixel.color.a];
-                        if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
-                            pixel.color.r = color[0];
-                            pixel.color.g = color[1];
-                            pixel.color.b = color[2];
-                            pixel.color.a = color[3];
+            for warning in validate_pattern_warnings(&self.pattern) {
+                ui.colored_label(egui::Color32::from_rgb(255, 165, 0), format!("⚠ {}", warning));
+            }
+            let (rows, cols) = (self.pattern.base_rows, self.pattern.base_dim);
+            if self.selected_cell.0 >= rows || self.selected_cell.1 >= cols {
+                self.selected_cell = (0, 0);
+            }
+            ui.horizontal(|ui| {
+                let mut separate = self.pattern.initial_pixels.is_some();
+                if ui.checkbox(&mut separate, "Separate initial pattern")
+                    .on_hover_text("Place different pixels at the top level than the pattern stamped at every level below")
+                    .changed() {
+                    self.pattern.initial_pixels = separate.then(|| self.pattern.pixels.clone());
+                }
+                self.edit_initial &= separate;
+                if separate {
+                    ui.selectable_value(&mut self.edit_initial, false, "Recursion");
+                    ui.selectable_value(&mut self.edit_initial, true, "Initial");
+                }
+            });
+            ui.checkbox(&mut self.show_hsv, "HSV sliders");
+            let mut close_perm_editor = false;
+            let pixels = edited_pixels(&mut self.pattern, self.edit_initial);
+            // Rectangular tiles only offer the presets that keep their shape
+            let presets = Permutation::symmetries(rows, cols);
+            let scroll_to_selection = std::mem::take(&mut self.scroll_to_selection);
+            egui::Grid::new("base_pattern_grid").show(ui, |ui| {
+                for (y, row) in pixels.iter_mut().enumerate() {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        // The selected cell gets a heavier outline in the selection color
+                        let selected = self.selected_cell == (y, x);
+                        let mut frame = egui::Frame::group(ui.style());
+                        if selected {
+                            frame = frame.stroke(egui::Stroke::new(2.0, ui.visuals().selection.stroke.color));
                         }
-                        
-                        // Permutation selector
-                        let perm_options = ["Identity", "Rotate 90°", "Rotate 270°", "Flip H", "Flip V"];
-                        ui.horizontal(|ui| {
-                            ui.label("Permutation:");
-                            ui.push_id(format!("perm_select_{}_{}", y, x), |ui| {
-                                egui::ComboBox::from_label("")
-                                    .selected_text(pixel.perm.get_name())
-                                    .show_ui(ui, |ui| {
-                                        for (idx, name) in perm_options.iter().enumerate() {
-                                            if ui.selectable_label(
-                                                pixel.perm.get_name() == *name,
-                                                *name
-                                            ).clicked() {
-                                                pixel.perm = match idx {
-                                                    0 => Permutation::identity(),
-                                                    1 => Permutation::rotate_90(),
-                                                    2 => Permutation::rotate_270(),
-                                                    3 => Permutation::flip_h(),
-                                                    4 => Permutation::flip_v(),
-                                                    _ => Permutation::identity(),
-                                                };
+                        let cell_response = frame.show(ui, |ui| {
+                            ui.vertical(|ui| {
+                                if ui.selectable_label(
+                                    selected,
+                                    format!("Pixel [{}, {}]", y, x),
+                                ).clicked() {
+                                    self.selected_cell = (y, x);
+                                }
+                                
+                                // Color controls
+                                let mut color = [pixel.color.r, pixel.color.g, pixel.color.b, pixel.color.a];
+                                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                                    self.selected_cell = (y, x);
+                                    pixel.color.r = color[0];
+                                    pixel.color.g = color[1];
+                                    pixel.color.b = color[2];
+                                    pixel.color.a = color[3];
+                                }
+                                if self.show_hsv {
+                                    let (mut h, mut s, mut v, a) = pixel.color.to_hsv();
+                                    let mut changed = false;
+                                    changed |= ui.add(egui::Slider::new(&mut h, 0.0..=360.0).text("H")).changed();
+                                    changed |= ui.add(egui::Slider::new(&mut s, 0.0..=1.0).text("S")).changed();
+                                    changed |= ui.add(egui::Slider::new(&mut v, 0.0..=1.0).text("V")).changed();
+                                    if changed {
+                                        self.selected_cell = (y, x);
+                                        pixel.color = Color::from_hsv(h, s, v, a);
+                                    }
+                                }
+                                ui.add(egui::Slider::new(&mut pixel.decay, 0.0..=1.0).text("Decay"))
+                                    .on_hover_text("How quickly detail fades beneath this pixel; 1 follows the global decay");
+                                ui.horizontal(|ui| {
+                                    let mut separate = pixel.weight.is_some();
+                                    if ui.checkbox(&mut separate, "")
+                                        .on_hover_text("Set the weight separately; off uses the color's alpha")
+                                        .changed() {
+                                        pixel.weight = separate.then_some(pixel.color.a);
+                                    }
+                                    let mut weight = pixel.weight.unwrap_or(pixel.color.a);
+                                    if ui.add_enabled(separate, egui::Slider::new(&mut weight, 0.0..=1.0).text("Weight"))
+                                        .on_hover_text("How much this pixel's color holds against the sub-pattern beneath it")
+                                        .changed() {
+                                        pixel.weight = Some(weight);
+                                    }
+                                });
+                                
+                                // Permutation selector
+                                ui.horizontal(|ui| {
+                                    ui.label("Permutation:");
+                                    ui.push_id(format!("perm_select_{}_{}", y, x), |ui| {
+                                        egui::ComboBox::from_label("")
+                                            .selected_text(pixel.perm.get_name())
+                                            .show_ui(ui, |ui| {
+                                                for preset in &presets {
+                                                    if ui.selectable_label(
+                                                        pixel.perm == *preset,
+                                                        preset.get_name()
+                                                    ).clicked() {
+                                                        pixel.perm = preset.clone();
+                                                    }
+                                                }
+                                                if ui.selectable_label(pixel.perm.get_name() == "Custom", "Custom").clicked() {
+                                                    self.custom_perm_edit = Some(((y, x), pixel.perm.clone()));
+                                                }
+                                            });
+                                    });
+                                });
+
+                                // Custom permutation editor: pick the destination of each source cell
+                                if let Some((cell, draft)) = &mut self.custom_perm_edit {
+                                    if *cell == (y, x) {
+                                        ui.label("Destination of each cell:");
+                                        egui::Grid::new(format!("custom_perm_{}_{}", y, x)).show(ui, |ui| {
+                                            for (i, target) in draft.mapping.iter_mut().enumerate() {
+                                                egui::ComboBox::from_id_salt(("custom_perm_cell", y, x, i))
+                                                    .width(50.0)
+                                                    .selected_text(format!("{}, {}", target.0, target.1))
+                                                    .show_ui(ui, |ui| {
+                                                        for to_y in 0..rows {
+                                                            for to_x in 0..cols {
+                                                                ui.selectable_value(target, (to_y, to_x), format!("{}, {}", to_y, to_x));
+                                                            }
+                                                        }
+                                                    });
+                                                if i % cols == cols - 1 {
+                                                    ui.end_row();
+                                                }
+                                            }
+                                        });
+
+                                        // Only bijections are written back to the pattern
+                                        match validate_permutation(draft, rows, cols) {
+                                            Ok(()) => pixel.perm = draft.clone(),
+                                            Err(e) => {
+                                                ui.colored_label(egui::Color32::from_rgb(255, 0, 0), e.to_string());
                                             }
                                         }
-                                    });
+                                        if ui.button("Done").clicked() {
+                                            close_perm_editor = true;
+                                        }
+                                    }
+                                }
                             });
-                        });
-                    });
+                        }).response;
+                        if selected && scroll_to_selection {
+                            cell_response.scroll_to_me(None);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if close_perm_editor {
+                self.custom_perm_edit = None;
+            }
+
+            // Palette swatches apply to the selected cell
+            ui.label(format!("Palette (applies to pixel [{}, {}])", self.selected_cell.0, self.selected_cell.1));
+            let mut picked = None;
+            ui.horizontal_wrapped(|ui| {
+                for (i, swatch) in self.palette.iter().enumerate() {
+                    let rgba = swatch.to_rgba();
+                    let fill = egui::Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]);
+                    if ui.add(egui::Button::new("").fill(fill).min_size(egui::vec2(20.0, 20.0))).clicked() {
+                        picked = Some(i);
+                    }
                 }
+            });
+            if let Some(i) = picked {
+                let (y, x) = self.selected_cell;
+                edited_pixels(&mut self.pattern, self.edit_initial)[y][x].color = self.palette[i];
             }
+            ui.horizontal(|ui| {
+                if ui.button("Add to palette").clicked() {
+                    let (y, x) = self.selected_cell;
+                    self.palette.push(edited_pixels(&mut self.pattern, self.edit_initial)[y][x].color);
+                    self.save_palette(ctx);
+                }
+                if ui.button("Import GIMP palette").clicked() {
+                    self.import_gpl_palette(ctx);
+                }
+                if ui.add_enabled(!self.palette.is_empty(), egui::Button::new("Clear palette")).clicked() {
+                    self.palette.clear();
+                    self.save_palette(ctx);
+                }
+            });
             
             // Save/Load buttons
             ui.horizontal(|ui| {
-                if ui.button("Save Pattern").clicked() {
-                    self.save_pattern(ctx);
+                if ui.add(egui::Button::new("Save Project")
+                    .shortcut_text(ctx.format_shortcut(&SAVE_SHORTCUT))).clicked() {
+                    self.save_project(ctx);
                 }
-                if ui.button("Load Pattern").clicked() {
+                if ui.add(egui::Button::new("Open")
+                    .shortcut_text(ctx.format_shortcut(&LOAD_SHORTCUT))).clicked() {
                     self.load_pattern(ctx);
                 }
+                let mut recent = None;
+                ui.add_enabled_ui(!self.recent_files.is_empty(), |ui| {
+                    ui.menu_button("Recent", |ui| {
+                        for path in &self.recent_files {
+                            let name = match self.recent_names.get(path) {
+                                Some(name) => name.into(),
+                                None => path.file_name().unwrap_or(path.as_os_str()).to_string_lossy(),
+                            };
+                            if ui.button(name).on_hover_text(path.display().to_string()).clicked() {
+                                recent = Some(path.clone());
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                });
+                if let Some(path) = recent {
+                    self.open_recent_file(ctx, path);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                let name = ui.add(egui::TextEdit::singleline(&mut self.pattern.name).hint_text("Untitled"))
+                    .on_hover_text("Shown in the title bar and suggested as the file name when saving");
+                self.editing_name = name.has_focus();
+                // Clicking away starts the next edit, which mustn't be merged with this one
+                if name.lost_focus() {
+                    self.commit_edit();
+                }
             });
+            if ui.button("Export Pattern Only").clicked() {
+                self.save_pattern(ctx);
+            }
+            if ui.button("Load Colors from Image").clicked() {
+                self.load_image_colors(ctx);
+            }
+            if ui.button("Import from PNG")
+                .on_hover_text("Restore the pattern and settings an exported PNG was rendered with")
+                .clicked() {
+                self.import_from_png(ctx);
+            }
+            ui.horizontal(|ui| {
+                ui.label("Seed:");
+                ui.add(egui::DragValue::new(&mut self.random_seed));
+                if ui.button("Randomize").clicked() {
+                    self.randomize_pattern();
+                }
+                if ui.button("New seed").clicked() {
+                    self.random_seed = rand::random();
+                    self.randomize_pattern();
+                }
+                if ui.button("Reset Pattern").clicked() {
+                    self.reset_pattern(ctx);
+                }
+            });
+            // Bulk edits that change one half of the pixels and keep the other
+            ui.horizontal(|ui| {
+                if ui.button("Randomize permutations")
+                    .on_hover_text("Give every pixel a random preset from the seed, keeping the colors")
+                    .clicked() {
+                    self.randomize_permutations();
+                }
+                if ui.button("Randomize colors")
+                    .on_hover_text("Give every pixel a random color from the seed, keeping the permutations")
+                    .clicked() {
+                    self.pattern.randomize_colors(self.random_seed);
+                }
+            });
+            ui.horizontal(|ui| {
+                let mut color = [self.fill_color.r, self.fill_color.g, self.fill_color.b, self.fill_color.a];
+                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                    self.fill_color = Color::new(color[0], color[1], color[2], color[3]);
+                }
+                if ui.button("Fill color")
+                    .on_hover_text("Paint every pixel of the grid being edited with this color")
+                    .clicked() {
+                    self.fill_pixels();
+                }
+            });
+            ui.collapsing("Explore", |ui| self.explore_ui(ui));
 
             ui.horizontal(|ui| {
-                if ui.button("Update Preview").clicked() {
+                if ui.add(egui::Button::new("Update Preview")
+                    .shortcut_text(ctx.format_shortcut(&PREVIEW_SHORTCUT))).clicked() {
                     self.update_preview(ctx);
                 }
-                if ui.button("Reset View").clicked() {
+                if ui.add(egui::Button::new("Fit")
+                    .shortcut_text(ctx.format_shortcut(&RESET_VIEW_SHORTCUT))).clicked() {
                     self.reset_view();
                 }
+                if ui.button("100%").on_hover_text("One fractal pixel per screen pixel").clicked() {
+                    self.zoom_to_pixel_perfect(ctx.pixels_per_point());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.descend_mode, "Descend on click")
+                    .on_hover_text("Click a subdivision of the preview to zoom into it; right-click to go back up");
+                if ui.add_enabled(!self.descent.is_empty(), egui::Button::new("Back")).clicked() {
+                    self.ascend(ctx.pixels_per_point());
+                }
+                if !self.descent.is_empty() {
+                    ui.label(format!("Depth {}", self.descent.len()));
+                }
+            });
+            // Exact view values, for returning to the same framing later
+            ui.horizontal(|ui| {
+                let zoom = ui.add(egui::DragValue::new(&mut self.zoom_level).speed(0.01).prefix("Zoom "));
+                let x = ui.add(egui::DragValue::new(&mut self.pan_offset.x).prefix("X "));
+                let y = ui.add(egui::DragValue::new(&mut self.pan_offset.y).prefix("Y "));
+                if zoom.changed() || x.changed() || y.changed() {
+                    self.clamp_view(ctx.pixels_per_point());
+                }
+                if ui.button("Copy view").on_hover_text("Copy zoom, X and Y to the clipboard").clicked() {
+                    self.copy_view(ctx);
+                }
+            });
+            if ui.add(egui::Slider::new(&mut self.max_pixel_size, 1.0..=MAX_PIXEL_SIZE_LIMIT)
+                .logarithmic(true)
+                .suffix(" px")
+                .text("Max zoom"))
+                .on_hover_text("Screen pixels one fractal pixel can grow to when zoomed in")
+                .changed() {
+                self.clamp_view(ctx.pixels_per_point());
+            }
+            if ui.checkbox(&mut self.live_preview, "Live preview")
+                .on_hover_text("Show a low-resolution render while editing")
+                .changed() && !self.live_preview {
+                self.showing_live = false;
+            }
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_grid, "Grid overlay")
+                    .on_hover_text("Outline the cells of one recursion level");
+                ui.add_enabled_ui(self.show_grid, |ui| {
+                    ui.add(egui::Slider::new(&mut self.grid_level, 1..=3).text("Level"));
+                    ui.color_edit_button_srgba(&mut self.grid_color);
+                });
+            });
+            ui.checkbox(&mut self.smooth_preview, "Smooth preview")
+                .on_hover_text("Filter the preview linearly for smoother zoomed-out viewing instead of showing crisp cells");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_gamut, "Show out-of-gamut")
+                    .on_hover_text("Tint cells magenta whose color left 0..1 before clamping");
+                if self.show_gamut {
+                    self.update_gamut_overlay(ctx);
+                    if let Some((_, _, flagged)) = &self.gamut_overlay {
+                        ui.label(format!("{} cells", flagged));
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Simulate")
+                    .selected_text(self.color_vision.name())
+                    .show_ui(ui, |ui| {
+                        for vision in ColorVision::ALL {
+                            ui.selectable_value(&mut self.color_vision, vision, vision.name());
+                        }
+                    });
+                ui.checkbox(&mut self.export_color_vision, "Apply to exports");
+            });
+            ui.horizontal(|ui| {
+                let has_b = self.compare_pattern.is_some();
+                ui.add_enabled(has_b, egui::Checkbox::new(&mut self.compare, "Compare A/B"))
+                    .on_hover_text("Show pattern B right of a draggable divider");
+                if ui.button("Copy A→B").clicked() {
+                    self.compare_pattern = Some(self.pattern.clone());
+                }
+                if ui.button("Load B").clicked() {
+                    self.load_compare_pattern(ctx);
+                }
+                if ui.add_enabled(has_b, egui::Button::new("Swap")).clicked() {
+                    self.swap_compare(ctx);
+                }
+            });
+            if let Some(job) = &self.preview_job {
+                let done = job.levels_done.load(Ordering::Relaxed);
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    ui.add(egui::ProgressBar::new(done as f32 / job.total_levels as f32)
+                        .desired_width(160.0)
+                        .text(format!("Level {} / {}", done, job.total_levels)));
+                    cancel = ui.button("Cancel").clicked();
+                });
+                if cancel {
+                    self.cancel_preview();
+                    self.update_status(ctx, "Preview cancelled", false);
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.add(egui::Button::new("Export PNG")
+                    .shortcut_text(ctx.format_shortcut(&EXPORT_SHORTCUT))).clicked() {
+                    self.export_preview(ui.ctx());
+                }
+                if ui.button("Export SVG").clicked() {
+                    self.export_svg(ui.ctx());
+                }
+                if ui.button("Copy Image").clicked() {
+                    self.copy_image(ui.ctx());
+                }
+                ui.checkbox(&mut self.export_view, "Current view only")
+                    .on_hover_text("Crop the PNG to the area visible in the preview, at full resolution");
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Export Base Tile").on_hover_text("The base pattern alone, without recursion").clicked() {
+                    self.export_base_tile(ui.ctx());
+                }
+                ui.add(egui::DragValue::new(&mut self.tile_scale).range(1..=MAX_TILE_SCALE).suffix("x"));
+                if ui.button("Export Levels").on_hover_text("Each recursion level as its own PNG, at the same size").clicked() {
+                    self.export_levels(ui.ctx());
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Export Grid (JSON)").clicked() {
+                    self.export_grid(ui.ctx());
+                }
+                ui.checkbox(&mut self.grid_flat, "Compact")
+                    .on_hover_text("Write colors as one flat array of numbers");
+                if ui.button("Digest").on_hover_text("Hash of the rendered colors, for comparing outputs").clicked() {
+                    match self.current_fractal().map(fractal_digest) {
+                        Ok(digest) => self.update_status(ui.ctx(), &format!("Render digest: {:016x}", digest), false),
+                        Err(message) => self.update_status(ui.ctx(), &format!("Rendering failed: {}", message), true),
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.gamma_correction, "Gamma correction")
+                    .on_hover_text("Applies to the preview and exports");
+                ui.add_enabled(
+                    self.gamma_correction,
+                    egui::Slider::new(&mut self.gamma, 1.0..=3.0).text("Gamma"),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.sixteen_bit, "16-bit output")
+                    .on_hover_text("PNG and TIFF only");
+                ui.add(egui::Slider::new(&mut self.jpeg_quality, 1..=100).text("JPEG quality"));
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.ssaa, "Anti-alias (SSAA)")
+                    .on_hover_text("Render deeper and average down when exporting PNGs");
+                ui.add_enabled(self.ssaa, egui::Slider::new(&mut self.ssaa_levels, 1..=2).text("Extra levels"));
             });
-            if ui.button("Export PNG").clicked() {
-                self.export_preview(ui.ctx());
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.custom_output_size, "Output size");
+                ui.add_enabled_ui(self.custom_output_size, |ui| {
+                    let width = ui.add(egui::DragValue::new(&mut self.output_width).range(1..=MAX_OUTPUT_SIZE));
+                    ui.label("x");
+                    let height = ui.add(egui::DragValue::new(&mut self.output_height).range(1..=MAX_OUTPUT_SIZE));
+                    ui.checkbox(&mut self.lock_aspect, "Lock aspect")
+                        .on_hover_text("Keep the rendered fractal's proportions");
+                    ui.checkbox(&mut self.smooth_resize, "Smooth");
+
+                    // Whichever side was edited drives the other; otherwise
+                    // the height follows, so pattern changes are picked up too
+                    if self.lock_aspect {
+                        let aspect = self.native_aspect();
+                        let fit = |side: f32| (side.round() as u32).clamp(1, MAX_OUTPUT_SIZE);
+                        if height.changed() && !width.changed() {
+                            self.output_width = fit(self.output_height as f32 * aspect);
+                        } else {
+                            self.output_height = fit(self.output_width as f32 / aspect);
+                        }
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.seamless_tile, "Seamless tile");
+                ui.checkbox(&mut self.tiled_export, "Tiled export (2x2)");
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.use_background, "Background");
+                ui.add_enabled_ui(self.use_background, |ui| {
+                    let mut rgb = [self.background.r, self.background.g, self.background.b];
+                    if ui.color_edit_button_rgb(&mut rgb).changed() {
+                        self.background = Color::new(rgb[0], rgb[1], rgb[2], 1.0);
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Border:");
+                ui.add(egui::DragValue::new(&mut self.border_width).range(0..=MAX_BORDER_WIDTH).suffix(" px"));
+                ui.add_enabled_ui(self.border_width > 0, |ui| {
+                    let mut rgb = [self.border_color.r, self.border_color.g, self.border_color.b];
+                    if ui.color_edit_button_rgb(&mut rgb).changed() {
+                        self.border_color = Color::new(rgb[0], rgb[1], rgb[2], 1.0);
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.use_caption, "Caption")
+                    .on_hover_text("Stamp text onto exported images; the preview is left clean");
+                ui.add_enabled_ui(self.use_caption, |ui| {
+                    let summary = self.settings_summary();
+                    ui.add(egui::TextEdit::singleline(&mut self.caption_text)
+                        .hint_text(summary)
+                        .desired_width(160.0));
+                    let mut color = [self.caption_color.r, self.caption_color.g, self.caption_color.b, self.caption_color.a];
+                    if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                        self.caption_color = Color::new(color[0], color[1], color[2], color[3]);
+                    }
+                    egui::ComboBox::from_id_salt("caption_corner")
+                        .selected_text(self.caption_corner.name())
+                        .show_ui(ui, |ui| {
+                            for corner in CaptionCorner::ALL {
+                                ui.selectable_value(&mut self.caption_corner, corner, corner.name());
+                            }
+                        });
+                });
+            });
+
+            ui.collapsing("Layers", |ui| self.layers_ui(ui));
+
+            if let Some(histogram) = &self.histogram {
+                ui.collapsing("Histogram", |ui| draw_histogram(ui, histogram));
             }
+            if let Some(counts) = &self.color_counts {
+                ui.collapsing(format!("Colors ({} unique)", counts.unique), |ui| {
+                    ui.add(egui::Slider::new(&mut self.top_colors, 1..=MAX_TOP_COLORS).text("Most frequent"));
+                    draw_color_counts(ui, counts, self.top_colors);
+                });
+            }
+            if self.preview_key.is_some() {
+                ui.collapsing("Fractal Dimension", |ui| self.dimension_ui(ui));
+            }
+
+            ui.collapsing("Contact Sheet", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Decays:");
+                    ui.text_edit_singleline(&mut self.contact_decays);
+                });
+                let max_contact = iterations_within(self.pattern.longest_side(), CONTACT_CELL_SIZE);
+                self.contact_iterations = self.contact_iterations.min(max_contact);
+                ui.add(egui::Slider::new(&mut self.contact_iterations, 1..=max_contact).text("Iterations"));
+                ui.add(egui::Slider::new(&mut self.contact_columns, 1..=8).text("Columns"));
+                if ui.button("Export Contact Sheet").clicked() {
+                    self.export_contact_sheet(ui.ctx());
+                }
+            });
+
+            ui.collapsing("Large Export", |ui| {
+                let max_chunked = iterations_within(self.pattern.longest_side(), MAX_CHUNKED_SIZE);
+                self.chunked_iterations = self.chunked_iterations.min(max_chunked);
+                ui.add(egui::Slider::new(&mut self.chunked_iterations, 1..=max_chunked).text("Iterations"));
+                let (width, height) = (
+                    self.pattern.base_dim.pow(self.chunked_iterations),
+                    self.pattern.base_rows.pow(self.chunked_iterations),
+                );
+                ui.label(format!("{} x {} pixels, rendered {} x {} at a time", width, height, CHUNK_SIZE, CHUNK_SIZE));
+                if let Some(export) = &self.chunked_export {
+                    let done = export.bands_done.load(Ordering::Relaxed);
+                    ui.add(egui::ProgressBar::new(done as f32 / export.band_count as f32)
+                        .text(format!("Band {} / {}", done, export.band_count)));
+                } else if ui.add_enabled(!self.pattern.normalize, egui::Button::new("Export Large PNG"))
+                    .on_hover_text("Writes the PNG in parts, so it can be larger than memory allows. Layers, resizing, tiling, borders and captions are left out.")
+                    .on_disabled_hover_text("Normalizing needs the whole image")
+                    .clicked() {
+                    self.export_chunked();
+                }
+            });
+
+            // Decay sweep animation
+            ui.collapsing("Animation", |ui| {
+                ui.add(egui::Slider::new(&mut self.animation_start_decay, 0.0..=1.0).text("Start decay"));
+                ui.add(egui::Slider::new(&mut self.animation_end_decay, 0.0..=1.0).text("End decay"));
+                ui.add(egui::Slider::new(&mut self.animation_frames, 2..=120).text("Frames"));
+                if let Some(export) = &self.animation_export {
+                    let done = export.frames_done.load(Ordering::Relaxed);
+                    ui.add(egui::ProgressBar::new(done as f32 / export.frame_count as f32)
+                        .text(format!("Frame {} / {}", done, export.frame_count)));
+                } else {
+                    if ui.button("Export Animation").clicked() {
+                        self.export_animation();
+                    }
+                    ui.horizontal(|ui| {
+                        let has_b = self.compare_pattern.is_some();
+                        if ui.add_enabled(has_b, egui::Button::new("Export Morph to B"))
+                            .on_hover_text("Tween from this pattern to pattern B of the A/B compare")
+                            .on_disabled_hover_text("Copy or load a pattern B first")
+                            .clicked() {
+                            self.export_morph(ui.ctx());
+                        }
+                        egui::ComboBox::from_id_salt("morph_mode")
+                            .selected_text(self.morph_mode.name())
+                            .show_ui(ui, |ui| {
+                                for mode in MorphMode::ALL {
+                                    ui.selectable_value(&mut self.morph_mode, mode, mode.name());
+                                }
+                            });
+                    });
+                }
+            });
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                 if let Some((message, is_error)) = &self.status_message {
@@ -625,23 +3456,248 @@ impl eframe::App for FractalApp {
                     };
                     ui.colored_label(color, message);
                 }
+                self.stats_ui(ui);
             });
         });
 
+        // A drag on a color picker changes the pattern every frame, so the
+        // snapshot is only committed once the pointer is released, and
+        // likewise for typing a name once the field loses focus
+        if self.pattern != pattern_before && self.pending_edit.is_none() {
+            self.pending_edit = Some(pattern_before);
+        }
+        if !ctx.input(|i| i.pointer.any_down()) && !self.editing_name {
+            self.commit_edit();
+        }
+        match history_action {
+            Some(HistoryAction::Undo) => self.undo(),
+            Some(HistoryAction::Redo) => self.redo(),
+            None => {}
+        }
+
+        // Layers are composited cell for cell, so they follow the main
+        // pattern's dimension
+        let (rows, cols) = (self.pattern.base_rows, self.pattern.base_dim);
+        for layer in &mut self.layers {
+            if (layer.pattern.base_rows, layer.pattern.base_dim) != (rows, cols) {
+                layer.pattern = layer.pattern.resized(rows, cols);
+            }
+        }
+
+        // Live preview tracks every change at low resolution; the full render
+        // starts once the control being dragged is released
+        if self.live_preview {
+            let key = self.render_key();
+            if self.live_render.as_ref().map(|(live_key, _)| *live_key) != Some(key) {
+                self.update_live_preview(ctx);
+            }
+            if self.showing_live && self.live_full_key != Some(key) && !ctx.input(|i| i.pointer.any_down()) {
+                self.live_full_key = Some(key);
+                self.update_preview(ctx);
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             self.update_preview_panel(ui);
         });
     }
 }
 
+// Largest pan offset on each axis that keeps MIN_VISIBLE_FRACTION of the
+// image in view. The offset moves the image center away from the preview
+// center, so along an axis with image size s and preview size p the overlap
+// shrinks once the near image edge, at offset - s/2, passes p/2 - visible.
+// Solving for the offset gives (p + s)/2 - visible, where visible is the
+// fraction of whichever of s and p is smaller, so a zoomed-in image still
+// covers that share of the preview.
+fn max_pan_offset(image_size: egui::Vec2, preview_size: egui::Vec2) -> egui::Vec2 {
+    let visible = image_size.min(preview_size) * MIN_VISIBLE_FRACTION;
+    ((preview_size + image_size) / 2.0 - visible).max(egui::Vec2::ZERO)
+}
+
+// Projects are told apart from plain pattern files by their extension
+fn is_document(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(DOCUMENT_EXTENSION))
+}
+
+// The pattern from a project or plain pattern file
+fn read_pattern(path: &Path) -> Result<Pattern, PatternError> {
+    let path_str = path.to_str().unwrap_or_default();
+    if is_document(path) {
+        load_document_from_file(path_str).map(|document| document.pattern)
+    } else {
+        load_pattern_from_file(path_str)
+    }
+}
+
+// The pixels the pattern editor works on
+fn edited_pixels(pattern: &mut Pattern, initial: bool) -> &mut Vec<Vec<Pixel>> {
+    match &mut pattern.initial_pixels {
+        Some(pixels) if initial => pixels,
+        _ => &mut pattern.pixels,
+    }
+}
+
+// Runs a render, turning a panic into an error message so a bug in the
+// generator can't take unsaved work down with the app. The message is
+// logged, and the default hook has already printed where it happened.
+fn catch_panic<T>(render: impl FnOnce() -> T) -> Result<T, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(render)).map_err(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        eprintln!("Rendering panicked: {}", message);
+        message
+    })
+}
+
+// Renders a pattern with its layers composited on top
+fn render_layered(pattern: &Pattern, layers: &[Layer], iterations: u32, decay_curve: &[f32]) -> RenderOutcome {
+    catch_panic(|| {
+        let mut fractal = render_pattern_with_curve(pattern, iterations, decay_curve);
+        composite_layers(&mut fractal, layers, |layer| render_pattern_with_curve(layer, iterations, decay_curve));
+        fractal
+    })
+}
+
+// Location of a file in the app's config directory, following each
+// platform's convention
+fn config_path(file_name: &str) -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("fractals").join(file_name))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+// A missing or unreadable settings file starts with the defaults
+fn load_settings() -> Settings {
+    config_path(SETTINGS_FILE)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn write_recovery(document: &FractalDocument) -> Result<(), PatternError> {
+    let Some(path) = config_path(RECOVERY_FILE) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document_to_json(document)?)?;
+    Ok(())
+}
+
+// A recovery file that is already gone is not an error
+fn remove_recovery() -> std::io::Result<()> {
+    match config_path(RECOVERY_FILE).map(fs::remove_file) {
+        Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// A missing or unreadable palette file just starts an empty palette
+fn load_palette() -> Vec<Color> {
+    config_path(PALETTE_FILE)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+// Swatches for the most frequent colors, with their share of all cells on hover
+fn draw_color_counts(ui: &mut egui::Ui, counts: &ColorCounts, shown: usize) {
+    ui.horizontal_wrapped(|ui| {
+        for (rgba, count) in counts.top.iter().take(shown) {
+            let [r, g, b, a] = rgba.0;
+            let (rect, response) = ui.allocate_exact_size(egui::vec2(20.0, 20.0), egui::Sense::hover());
+            ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgba_unmultiplied(r, g, b, a));
+            response.on_hover_text(format!(
+                "#{:02X}{:02X}{:02X} alpha {}: {} cells ({:.1}%)",
+                r, g, b, a, count, *count as f32 / counts.cells.max(1) as f32 * 100.0,
+            ));
+        }
+    });
+}
+
+// Bars scaled to the fullest bin, with the transparent count underneath
+fn draw_histogram(ui: &mut egui::Ui, histogram: &Histogram) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(256.0, 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(20));
+
+    let max = histogram.bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let bar_width = rect.width() / histogram.bins.len() as f32;
+    for (i, &count) in histogram.bins.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let height = rect.height() * count as f32 / max;
+        let x = rect.left() + i as f32 * bar_width;
+        painter.rect_filled(
+            egui::Rect::from_min_max(egui::pos2(x, rect.bottom() - height), egui::pos2(x + bar_width, rect.bottom())),
+            0.0,
+            egui::Color32::from_gray(200),
+        );
+    }
+    ui.label(format!("Transparent cells: {}", histogram.transparent));
+}
+
+// Textures hold the same sRGB bytes an export with the given options would
+// write, which egui also displays as sRGB
+fn fractal_texture(
+    ctx: &egui::Context,
+    name: &str,
+    fractal: &[Vec<Color>],
+    options: &DisplayOptions,
+) -> egui::TextureHandle {
+    let (width, height) = grid_size(fractal);
+    let image = encode_fractal(fractal, &options.encode);
+
+    let color_image = egui::ColorImage::from_rgba_unmultiplied(
+        [width, height],
+        &image.into_raw(),
+    );
+
+    let tex_options = egui::TextureOptions {
+        magnification: options.filter,
+        minification: options.filter,
+        ..Default::default()
+    };
+
+    ctx.load_texture(name, color_image, tex_options)
+}
+
 fn main() -> Result<(), eframe::Error> {
+    // Any arguments select headless rendering instead of the GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default()
             .with_inner_size([1024.0, 768.0]),
         ..Default::default()
     };
     eframe::run_native(
-        "Fractal Generator",
+        APP_TITLE,
         options,
         Box::new(|cc| Ok(Box::new(FractalApp::new(cc))))
     )
@@ -0,0 +1,104 @@
+use std::fs;
+use std::process::Command;
+
+use fractals::{create_base_pattern, pattern_to_json};
+
+#[test]
+fn batch_keeps_dotted_names_apart() {
+    let root = std::env::temp_dir().join(format!("fractals_batch_{}", std::process::id()));
+    let (dir, out_dir) = (root.join("patterns"), root.join("renders"));
+    fs::create_dir_all(&dir).unwrap();
+    let json = pattern_to_json(&create_base_pattern()).unwrap();
+    for name in ["foo.v1.json", "foo.v2.json"] {
+        fs::write(dir.join(name), &json).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fractals"))
+        .args(["batch", "--dir", dir.to_str().unwrap(), "--out-dir", out_dir.to_str().unwrap(), "--iterations", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("2 rendered, 0 failed"));
+    assert!(out_dir.join("foo.v1.png").exists());
+    assert!(out_dir.join("foo.v2.png").exists());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn batch_flags_inputs_that_share_an_output() {
+    let root = std::env::temp_dir().join(format!("fractals_batch_dup_{}", std::process::id()));
+    let (dir, out_dir) = (root.join("patterns"), root.join("renders"));
+    fs::create_dir_all(&dir).unwrap();
+    let json = pattern_to_json(&create_base_pattern()).unwrap();
+    for name in ["bar.json", "bar.JSON"] {
+        fs::write(dir.join(name), &json).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fractals"))
+        .args(["batch", "--dir", dir.to_str().unwrap(), "--out-dir", out_dir.to_str().unwrap(), "--iterations", "2"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 rendered, 1 failed"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already rendered"));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn gamma_must_be_finite_and_positive() {
+    let dir = std::env::temp_dir().join(format!("fractals_gamma_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("pattern.json");
+    fs::write(&input, pattern_to_json(&create_base_pattern()).unwrap()).unwrap();
+
+    for gamma in ["NaN", "inf", "0", "-1"] {
+        let output = dir.join("out.png");
+        let status = Command::new(env!("CARGO_BIN_EXE_fractals"))
+            .args(["--input", input.to_str().unwrap(), "--output", output.to_str().unwrap(), "--gamma", gamma])
+            .output()
+            .unwrap()
+            .status;
+        assert_eq!(status.code(), Some(2), "--gamma {}", gamma);
+        assert!(!output.exists());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn renders_too_large_to_estimate_are_refused() {
+    let dir = std::env::temp_dir().join(format!("fractals_huge_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("pattern.json");
+    fs::write(&input, pattern_to_json(&create_base_pattern()).unwrap()).unwrap();
+    let output = dir.join("out.png");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_fractals"))
+        .args([
+            "--input", input.to_str().unwrap(), "--output", output.to_str().unwrap(),
+            "--iterations", "64", "--memory-limit", "18446744073709551615",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(1), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("more memory than can be counted"));
+    assert!(!output.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn iterations_overflowing_with_ssaa_are_a_usage_error() {
+    let dir = std::env::temp_dir().join(format!("fractals_ssaa_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("pattern.json");
+    fs::write(&input, pattern_to_json(&create_base_pattern()).unwrap()).unwrap();
+
+    let result = Command::new(env!("CARGO_BIN_EXE_fractals"))
+        .args([
+            "--input", input.to_str().unwrap(), "--output", dir.join("out.png").to_str().unwrap(),
+            "--iterations", "4294967295", "--ssaa", "1",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(2), "{}", String::from_utf8_lossy(&result.stderr));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,137 @@
+use fractals::{BlendMode, Color, ColorVision, DecayModel};
+
+#[test]
+fn to_rgba_maps_full_range() {
+    assert_eq!(Color::new(1.0, 1.0, 1.0, 1.0).to_rgba().0, [255, 255, 255, 255]);
+    assert_eq!(Color::new(0.0, 0.0, 0.0, 0.0).to_rgba().0, [0, 0, 0, 0]);
+}
+
+#[test]
+fn to_rgba_rounds_to_nearest() {
+    assert_eq!(Color::new(0.5, 0.5, 0.5, 0.5).to_rgba().0, [128, 128, 128, 128]);
+}
+
+#[test]
+fn to_rgba_clamps_out_of_range() {
+    assert_eq!(Color::new(-0.2, 1.3, 0.0, 1.0).to_rgba().0, [0, 255, 0, 255]);
+}
+
+#[test]
+fn blend_modes_match_hand_computed_values() {
+    let parent = Color::new(0.5, 0.2, 0.8, 1.0);
+    let base = Color::new(0.4, 0.5, 0.5, 0.25);
+    let channels = |c: Color| [c.r, c.g, c.b, c.a];
+
+    assert_eq!(channels(BlendMode::Lerp.target(&parent, &base)), [0.4, 0.5, 0.5, 0.25]);
+    assert_eq!(channels(BlendMode::Multiply.target(&parent, &base)), [0.2, 0.1, 0.4, 0.25]);
+    assert_eq!(channels(BlendMode::Screen.target(&parent, &base)), [0.7, 0.6, 0.9, 0.25]);
+    assert_eq!(channels(BlendMode::Add.target(&parent, &base)), [0.9, 0.7, 1.0, 0.25]);
+}
+
+#[test]
+fn over_composites_onto_background() {
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+
+    assert_eq!(Color::new(0.0, 0.0, 0.0, 0.0).over(&white).to_rgba().0, [255, 255, 255, 255]);
+    assert_eq!(Color::new(0.0, 0.0, 0.0, 0.5).over(&white).to_rgba().0, [128, 128, 128, 255]);
+    assert_eq!(Color::new(0.2, 0.4, 0.6, 1.0).over(&white).to_rgba().0, [51, 102, 153, 255]);
+}
+
+fn assert_close(a: Color, b: Color) {
+    let channels = |c: Color| [c.r, c.g, c.b, c.a];
+    for (x, y) in channels(a).into_iter().zip(channels(b)) {
+        assert!((x - y).abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn hsv_round_trips_and_keeps_alpha() {
+    for color in [
+        Color::new(1.0, 0.0, 0.0, 1.0),
+        Color::new(0.2, 0.7, 0.4, 0.3),
+        Color::new(0.9, 0.1, 0.8, 0.0),
+        Color::new(0.5, 0.5, 0.5, 0.6),
+    ] {
+        let (h, s, v, a) = color.to_hsv();
+        assert_close(Color::from_hsv(h, s, v, a), color);
+    }
+}
+
+#[test]
+fn hsv_handles_achromatic_and_wraps_hue() {
+    assert_eq!(Color::new(0.5, 0.5, 0.5, 1.0).to_hsv(), (0.0, 0.0, 0.5, 1.0));
+    assert_close(Color::from_hsv(123.0, 0.0, 0.5, 1.0), Color::new(0.5, 0.5, 0.5, 1.0));
+    assert_close(Color::from_hsv(480.0, 1.0, 1.0, 1.0), Color::from_hsv(120.0, 1.0, 1.0, 1.0));
+    assert_close(Color::from_hsv(-120.0, 1.0, 1.0, 1.0), Color::new(0.0, 0.0, 1.0, 1.0));
+}
+
+#[test]
+fn srgb_conversions_round_trip() {
+    let color = Color::new(0.1, 0.5, 0.9, 0.4);
+    assert_close(color.srgb_to_linear().linear_to_srgb(), color);
+    assert_close(Color::new(0.5, 0.5, 0.5, 0.4).srgb_to_linear(), Color::new(0.21404, 0.21404, 0.21404, 0.4));
+}
+
+#[test]
+fn in_gamut_flags_out_of_range_channels() {
+    assert!(Color::new(0.0, 0.5, 1.0, 1.0).in_gamut());
+    assert!(!Color::new(1.0001, 0.5, 0.5, 1.0).in_gamut());
+    assert!(!Color::new(0.5, -0.01, 0.5, 1.0).in_gamut());
+    assert!(!Color::new(0.5, 0.5, 0.5, f32::NAN).in_gamut());
+}
+
+#[test]
+fn color_vision_simulation_keeps_grays_and_merges_confused_hues() {
+    let gray = Color::new(0.5, 0.5, 0.5, 0.7);
+    for vision in ColorVision::ALL {
+        assert_close(vision.simulate(gray), Color::new(0.5, 0.5, 0.5, 0.7));
+    }
+
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let green = Color::new(0.0, 1.0, 0.0, 1.0);
+    assert_eq!(ColorVision::Normal.simulate(red).to_rgba(), red.to_rgba());
+    // Red-green deficiencies see both as shades of the same yellow
+    for vision in [ColorVision::Protanopia, ColorVision::Deuteranopia] {
+        let hue = |color: Color| vision.simulate(color).to_hsv().0;
+        assert!((hue(red) - hue(green)).abs() < 10.0);
+    }
+}
+
+#[test]
+fn premultiplied_mix_keeps_hue_when_fading_out() {
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 0.0, 0.0);
+
+    assert_close(DecayModel::Linear.mix(&red, &clear, 0.5), Color::new(0.5, 0.0, 0.0, 0.5));
+    for model in [DecayModel::Linear, DecayModel::Perceptual] {
+        assert_close(model.mix_premultiplied(&red, &clear, 0.5), Color::new(1.0, 0.0, 0.0, 0.5));
+    }
+    assert_close(Color::new(0.2, 0.4, 0.6, 0.5).premultiplied().unpremultiplied(), Color::new(0.2, 0.4, 0.6, 0.5));
+}
+
+#[test]
+fn average_linear_of_black_and_white_is_perceptual_mid_gray() {
+    let black = Color::new(0.0, 0.0, 0.0, 1.0);
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let gray = Color::average_linear(&[black, white]);
+
+    // Half the light of white, which sRGB encodes well above 0.5
+    assert!((gray.srgb_to_linear().r - 0.5).abs() < 1e-5);
+    assert!((gray.r - 0.7354).abs() < 1e-3);
+    assert_eq!([gray.r, gray.a], [gray.g, 1.0]);
+    assert_eq!(gray.to_rgba().0, [188, 188, 188, 255]);
+}
+
+#[test]
+fn average_linear_weights_rgb_by_alpha() {
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let clear_green = Color::new(0.0, 1.0, 0.0, 0.0);
+    let average = Color::average_linear(&[red, clear_green]);
+    assert_eq!(average.to_rgba().0, [255, 0, 0, 128]);
+    assert_eq!(average.a, 0.5);
+
+    let empty = Color::average_linear(&[]);
+    assert_eq!([empty.r, empty.g, empty.b, empty.a], [0.0; 4]);
+    let clear = Color::average_linear(&[clear_green, clear_green]);
+    assert_eq!(clear.a, 0.0);
+}
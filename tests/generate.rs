@@ -0,0 +1,130 @@
+use fractals::{
+    create_base_pattern, generate_fractal, generate_fractal_parallel, generate_fractal_seeded,
+    pattern_from_json, Color, ComposeOrder, Pattern, PatternError, Permutation,
+};
+
+fn assert_color_close(a: Color, b: Color) {
+    let channels = |c: Color| [c.r, c.g, c.b, c.a];
+    for (x, y) in channels(a).into_iter().zip(channels(b)) {
+        assert!((x - y).abs() < 1e-6, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn one_iteration_is_exactly_the_base_colors() {
+    let pattern = create_base_pattern();
+    let expected: Vec<Vec<Color>> = pattern.pixels.iter()
+        .map(|row| row.iter().map(|pixel| pixel.color).collect())
+        .collect();
+
+    assert_eq!(generate_fractal(1, &pattern, 0.5), expected);
+    assert_eq!(generate_fractal_parallel(1, &pattern, 0.5), expected);
+}
+
+#[test]
+fn output_side_doubles_each_iteration() {
+    let pattern = create_base_pattern();
+    for iterations in 1..=7 {
+        let side = 1 << iterations;
+        for fractal in [
+            generate_fractal(iterations, &pattern, 0.5),
+            generate_fractal_parallel(iterations, &pattern, 0.5),
+        ] {
+            assert_eq!(fractal.len(), side);
+            assert!(fractal.iter().all(|row| row.len() == side));
+        }
+    }
+}
+
+#[test]
+fn no_decay_tiles_the_base_pattern() {
+    // With decay 1.0 the blend never weakens, so every cell is replaced
+    // outright by its base color. Identity permutations keep the base
+    // pattern in the same orientation at every level.
+    let mut pattern = create_base_pattern();
+    for pixel in pattern.pixels.iter_mut().flatten() {
+        pixel.perm = Permutation::identity(2);
+    }
+
+    let fractal = generate_fractal(5, &pattern, 1.0);
+    for (y, row) in fractal.iter().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            assert_color_close(color, pattern.pixels[y % 2][x % 2].color);
+        }
+    }
+}
+
+#[test]
+fn seeded_generation_starts_from_the_initial_pattern() {
+    let pattern = create_base_pattern();
+    assert_eq!(generate_fractal_seeded(4, &pattern, &pattern, 0.5).unwrap(), generate_fractal(4, &pattern, 0.5));
+
+    let initial = Pattern::random(2, 2, 7);
+    let top = generate_fractal_seeded(1, &initial, &pattern, 0.5).unwrap();
+    for (y, row) in top.iter().enumerate() {
+        for (x, &color) in row.iter().enumerate() {
+            assert_color_close(color, initial.pixels[y][x].color);
+        }
+    }
+
+    // The same render through the pattern's own field, as saved files hold it
+    let mut seeded = pattern.clone();
+    seeded.initial_pixels = Some(initial.pixels.clone());
+    let fractal = generate_fractal(4, &seeded, 0.5);
+    assert_eq!(fractal, generate_fractal_seeded(4, &initial, &pattern, 0.5).unwrap());
+    assert_eq!(fractal, generate_fractal_parallel(4, &seeded, 0.5));
+    assert_ne!(fractal, generate_fractal(4, &pattern, 0.5));
+}
+
+#[test]
+fn initial_pattern_must_match_the_recursion_size() {
+    let pattern = create_base_pattern();
+    let result = generate_fractal_seeded(3, &pattern.resized(3, 3), &pattern, 0.5);
+    assert!(matches!(result, Err(PatternError::ValidationError(_))));
+
+    let mut value = serde_json::to_value(&pattern).unwrap();
+    value["initial_pixels"] = serde_json::to_value(&pattern.resized(2, 3).pixels).unwrap();
+    assert!(matches!(pattern_from_json(&value.to_string()), Err(PatternError::ValidationError(_))));
+}
+
+#[test]
+fn perm_depth_limit_stops_composing_permutations_past_its_level() {
+    let pattern = create_base_pattern();
+    let identity = Permutation::identity_rect(2, 2);
+    assert!(pattern.pixels.iter().flatten().any(|pixel| pixel.perm != identity));
+    let with_limit = |limit| Pattern { perm_depth_limit: limit, ..pattern.clone() };
+    let unlimited = generate_fractal(5, &pattern, 0.5);
+
+    // The cells of the last level are never expanded, so limiting to the
+    // ones before it changes nothing
+    assert!(generate_fractal(5, &with_limit(Some(4)), 0.5) == unlimited);
+
+    // A limit of zero expands everything as if no pixel were permuted
+    let mut unpermuted = pattern.clone();
+    for pixel in unpermuted.pixels.iter_mut().flatten() {
+        pixel.perm = identity.clone();
+    }
+    assert!(generate_fractal(5, &with_limit(Some(0)), 0.5) == generate_fractal(5, &unpermuted, 0.5));
+
+    let limited = generate_fractal(5, &with_limit(Some(3)), 0.5);
+    assert!(limited != unlimited);
+    assert!(generate_fractal_parallel(5, &with_limit(Some(3)), 0.5) == limited);
+}
+
+#[test]
+fn compose_order_only_matters_for_permutations_that_do_not_commute() {
+    let pattern = create_base_pattern();
+    let pixel_first = Pattern { compose_order: ComposeOrder::PixelFirst, ..pattern.clone() };
+    let parent_first = generate_fractal(5, &pattern, 0.5);
+    let reordered = generate_fractal(5, &pixel_first, 0.5);
+    assert!(reordered != parent_first);
+    assert!(generate_fractal_parallel(5, &pixel_first, 0.5) == reordered);
+
+    // Quarter turns commute with each other, so both orders agree
+    let mut turns = pattern.clone();
+    for (i, pixel) in turns.pixels.iter_mut().flatten().enumerate() {
+        pixel.perm = (0..i).fold(Permutation::identity(2), |perm, _| perm.compose(&Permutation::rotate_90(2)));
+    }
+    let turns_pixel_first = Pattern { compose_order: ComposeOrder::PixelFirst, ..turns.clone() };
+    assert!(generate_fractal(5, &turns, 0.5) == generate_fractal(5, &turns_pixel_first, 0.5));
+}
@@ -0,0 +1,18 @@
+use fractals::{parse_gpl, Color};
+
+#[test]
+fn parses_gimp_palette_rows() {
+    let text = "GIMP Palette\nName: Test\nColumns: 4\n# comment\n\n255   0 0\tRed\n  0 128 255 Sky blue  \n";
+    let colors = parse_gpl(text).unwrap();
+
+    assert_eq!(colors.len(), 2);
+    assert_eq!(colors[0].to_rgba(), Color::new(1.0, 0.0, 0.0, 1.0).to_rgba());
+    assert_eq!(colors[1].to_rgba().0, [0, 128, 255, 255]);
+}
+
+#[test]
+fn rejects_missing_header_and_bad_rows() {
+    assert!(parse_gpl("255 0 0 Red\n").is_err());
+    assert!(parse_gpl("GIMP Palette\n255 0\n").is_err());
+    assert!(parse_gpl("GIMP Palette\n256 0 0 Too bright\n").is_err());
+}
@@ -0,0 +1,148 @@
+use fractals::{
+    create_base_pattern, document_from_json, document_to_json, fractal_to_json, pattern_from_json,
+    pattern_to_json, render_pattern, BlendMode, Color, FractalDocument, Layer, PatternError,
+    DOCUMENT_FORMAT_VERSION, PATTERN_FORMAT_VERSION,
+};
+
+#[test]
+fn versioned_format_round_trips() {
+    let pattern = create_base_pattern();
+    let json = pattern_to_json(&pattern).unwrap();
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], PATTERN_FORMAT_VERSION);
+    assert!(value["pattern"].is_object());
+
+    assert!(pattern_from_json(&json).unwrap() == pattern);
+}
+
+#[test]
+fn bare_legacy_pattern_still_loads() {
+    let pattern = create_base_pattern();
+    let json = serde_json::to_string(&pattern).unwrap();
+
+    assert!(pattern_from_json(&json).unwrap() == pattern);
+}
+
+#[test]
+fn unknown_version_is_rejected() {
+    let json = pattern_to_json(&create_base_pattern())
+        .unwrap()
+        .replacen(&format!("\"version\": {}", PATTERN_FORMAT_VERSION), "\"version\": 99", 1);
+
+    match pattern_from_json(&json) {
+        Err(PatternError::ValidationError(message)) => assert!(message.contains("99")),
+        _ => panic!("expected a validation error for an unknown version"),
+    }
+}
+
+#[test]
+fn grid_json_has_metadata_and_both_layouts() {
+    let fractal = render_pattern(&create_base_pattern(), 2, 0.5);
+
+    let nested: serde_json::Value =
+        serde_json::from_str(&fractal_to_json(&fractal, 2, &[0.5], false).unwrap()).unwrap();
+    assert_eq!(nested["iterations"], 2);
+    assert_eq!(nested["size"], 4);
+    assert_eq!(nested["colors"][1][0]["a"], fractal[1][0].a);
+
+    let flat: serde_json::Value =
+        serde_json::from_str(&fractal_to_json(&fractal, 2, &[0.5], true).unwrap()).unwrap();
+    assert_eq!(flat["colors"].as_array().unwrap().len(), 4 * 4 * 4);
+    assert_eq!(flat["colors"][4 * 4 + 3], fractal[1][0].a);
+}
+
+#[test]
+fn document_round_trips_with_settings() {
+    let document = FractalDocument {
+        pattern: create_base_pattern(),
+        iterations: 7,
+        decay: 0.3,
+        background: Some(Color::new(0.1, 0.2, 0.3, 1.0)),
+        level_decays: Some(vec![0.9, 0.6, 0.3]),
+        layers: vec![Layer {
+            name: "Overlay".to_string(),
+            pattern: create_base_pattern(),
+            blend_mode: BlendMode::Multiply,
+            opacity: 0.25,
+        }],
+    };
+    let json = document_to_json(&document).unwrap();
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], DOCUMENT_FORMAT_VERSION);
+    assert!(document_from_json(&json).unwrap() == document);
+
+    // Documents saved before the decay curve and layers were recorded still load
+    let mut old = value.clone();
+    let saved = old["document"].as_object_mut().unwrap();
+    saved.remove("level_decays");
+    saved.remove("layers");
+    let loaded = document_from_json(&old.to_string()).unwrap();
+    assert!(loaded.level_decays.is_none());
+    assert!(loaded.layers.is_empty());
+}
+
+#[test]
+fn document_settings_are_validated() {
+    let document = FractalDocument {
+        pattern: create_base_pattern(),
+        iterations: 0,
+        decay: 0.5,
+        background: None,
+        level_decays: None,
+        layers: Vec::new(),
+    };
+    let json = document_to_json(&document).unwrap();
+
+    match document_from_json(&json) {
+        Err(PatternError::ValidationError(message)) => assert!(message.contains("iterations")),
+        _ => panic!("expected a validation error for zero iterations"),
+    }
+    assert!(document_from_json(&pattern_to_json(&create_base_pattern()).unwrap()).is_err());
+}
+
+#[test]
+fn patch_changes_only_the_fields_it_names() {
+    let original = create_base_pattern();
+    let mut pattern = original.clone();
+    let patch = serde_json::json!({
+        "pixels": {"0": {"1": {"color": {"r": 1.0, "g": 0.0}}}},
+        "symmetry": true,
+    });
+    pattern.apply_patch(&patch).unwrap();
+
+    assert_eq!(pattern.pixels[0][1].color, Color::new(1.0, 0.0, 0.2, 1.0));
+    assert!(pattern.symmetry);
+    pattern.pixels[0][1].color = original.pixels[0][1].color;
+    pattern.symmetry = original.symmetry;
+    assert!(pattern == original);
+}
+
+#[test]
+fn invalid_patches_are_rejected_and_leave_the_pattern_unchanged() {
+    let original = create_base_pattern();
+    for patch in [
+        serde_json::json!({"pixels": {"0": {"5": {"color": {"r": 1.0}}}}}),
+        serde_json::json!({"colour": 1}),
+        serde_json::json!({"pixels": {"0": {"0": {"color": {"r": 2.0}}}}}),
+    ] {
+        let mut pattern = original.clone();
+        assert!(matches!(pattern.apply_patch(&patch), Err(PatternError::ValidationError(_))), "{}", patch);
+        assert!(pattern == original);
+    }
+}
+
+#[test]
+fn pattern_name_round_trips_and_defaults_to_empty() {
+    let mut pattern = create_base_pattern();
+    pattern.name = "Blue carpet".to_string();
+    let loaded = pattern_from_json(&pattern_to_json(&pattern).unwrap()).unwrap();
+    assert_eq!(loaded.name, "Blue carpet");
+
+    // Files saved before names existed load as unnamed
+    let mut value: serde_json::Value = serde_json::from_str(&pattern_to_json(&pattern).unwrap()).unwrap();
+    value["pattern"].as_object_mut().unwrap().remove("name");
+    let loaded = pattern_from_json(&value.to_string()).unwrap();
+    assert_eq!(loaded.name, "");
+}
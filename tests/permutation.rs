@@ -0,0 +1,200 @@
+use fractals::{
+    create_base_pattern, generate_fractal, symmetry_group, validate_permutation, Color, Pattern,
+    Permutation,
+};
+
+#[test]
+fn two_quarter_turns_make_a_half_turn() {
+    for dim in 2..=4 {
+        assert_eq!(
+            Permutation::rotate_90(dim).compose(&Permutation::rotate_90(dim)),
+            Permutation::rotate_180(dim)
+        );
+    }
+}
+
+#[test]
+fn new_presets_are_named() {
+    assert_eq!(Permutation::rotate_180(2).get_name(), "Rotate 180°");
+    assert_eq!(Permutation::transpose(3).get_name(), "Transpose");
+    assert_eq!(Permutation::anti_transpose(3).get_name(), "Anti-transpose");
+}
+
+#[test]
+fn transposes_mirror_across_diagonals() {
+    let grid = vec![vec![1, 2], vec![3, 4]];
+    assert_eq!(Permutation::transpose(2).apply(&grid), vec![vec![1, 3], vec![2, 4]]);
+    assert_eq!(Permutation::anti_transpose(2).apply(&grid), vec![vec![4, 2], vec![3, 1]]);
+}
+
+fn presets(dim: usize) -> Vec<Permutation> {
+    vec![
+        Permutation::identity(dim),
+        Permutation::rotate_90(dim),
+        Permutation::rotate_180(dim),
+        Permutation::rotate_270(dim),
+        Permutation::flip_h(dim),
+        Permutation::flip_v(dim),
+        Permutation::transpose(dim),
+        Permutation::anti_transpose(dim),
+    ]
+}
+
+fn numbered_grid(dim: usize) -> Vec<Vec<usize>> {
+    (0..dim).map(|y| (0..dim).map(|x| y * dim + x).collect()).collect()
+}
+
+#[test]
+fn opposite_flips_make_a_half_turn() {
+    for dim in 2..=4 {
+        assert_eq!(
+            Permutation::flip_h(dim).compose(&Permutation::flip_v(dim)),
+            Permutation::rotate_180(dim)
+        );
+        assert_eq!(
+            Permutation::flip_v(dim).compose(&Permutation::flip_h(dim)),
+            Permutation::rotate_180(dim)
+        );
+    }
+}
+
+#[test]
+fn identity_is_neutral_on_both_sides() {
+    for dim in 2..=4 {
+        let identity = Permutation::identity(dim);
+        for perm in presets(dim) {
+            assert_eq!(identity.compose(&perm), perm);
+            assert_eq!(perm.compose(&identity), perm);
+        }
+    }
+}
+
+#[test]
+fn compose_is_associative() {
+    for dim in 2..=4 {
+        let presets = presets(dim);
+        for a in &presets {
+            for b in &presets {
+                for c in &presets {
+                    assert_eq!(a.compose(b).compose(c), a.compose(&b.compose(c)));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn presets_are_closed_under_composition() {
+    for dim in 2..=4 {
+        let presets = presets(dim);
+        for a in &presets {
+            for b in &presets {
+                assert!(presets.contains(&a.compose(b)), "{} then {}", a.get_name(), b.get_name());
+            }
+        }
+    }
+}
+
+#[test]
+fn compose_applies_left_operand_first() {
+    // Flip H then a quarter turn differs from the reverse order, so this pins the order down
+    for dim in 2..=4 {
+        let grid = numbered_grid(dim);
+        for a in presets(dim) {
+            for b in presets(dim) {
+                assert_eq!(a.compose(&b).apply(&grid), b.apply(&a.apply(&grid)));
+            }
+        }
+        let flip = Permutation::flip_h(dim);
+        let turn = Permutation::rotate_90(dim);
+        assert_ne!(flip.compose(&turn), turn.compose(&flip));
+    }
+}
+
+#[test]
+fn rectangular_grids_keep_only_shape_preserving_presets() {
+    let symmetries = Permutation::symmetries(2, 3);
+    let names: Vec<_> = symmetries.iter().map(Permutation::get_name).collect();
+    assert_eq!(names, ["Identity", "Rotate 180°", "Flip H", "Flip V"]);
+
+    let grid = vec![vec![1, 2, 3], vec![4, 5, 6]];
+    assert_eq!(symmetries[2].apply(&grid), vec![vec![3, 2, 1], vec![6, 5, 4]]);
+    assert_eq!(symmetries[2].compose(&symmetries[3]), symmetries[1]);
+    for perm in &symmetries {
+        assert_eq!(perm.shape(), (2, 3));
+        validate_permutation(perm, 2, 3).unwrap();
+    }
+}
+
+#[test]
+fn inverse_undoes_a_permutation() {
+    let grid = numbered_grid(3);
+    for perm in presets(3) {
+        assert_eq!(perm.inverse().apply(&perm.apply(&grid)), grid);
+    }
+    let wide = Permutation::symmetries(2, 3)[3].clone();
+    assert_eq!(wide.compose(&wide.inverse()), Permutation::identity_rect(2, 3));
+}
+
+// Whether each base grid symmetry, applied to the whole render, leaves it unchanged
+fn rendered_symmetries(pattern: &Pattern, iterations: u32) -> Vec<bool> {
+    let fractal = generate_fractal(iterations, pattern, 0.5);
+    let (height, width) = (fractal.len(), fractal[0].len());
+    Permutation::symmetries(height, width).iter()
+        .map(|g| g.apply(&fractal) == fractal)
+        .collect()
+}
+
+#[test]
+fn symmetry_group_matches_the_rendered_fractal() {
+    let mut nontrivial = 0;
+    for (rows, cols, iterations) in [(2, 2, 5), (3, 3, 3), (2, 3, 3)] {
+        for seed in 0..100 {
+            // Two colors make symmetric arrangements common enough to test
+            let mut pattern = Pattern::random(rows, cols, seed);
+            for pixel in pattern.pixels.iter_mut().flatten() {
+                let level = if pixel.color.r > 0.5 { 1.0 } else { 0.0 };
+                pixel.color = Color::new(level, level, level, 1.0);
+            }
+
+            let group = symmetry_group(&pattern);
+            let expected = rendered_symmetries(&pattern, iterations);
+            let found: Vec<bool> = Permutation::symmetries(rows, cols).iter()
+                .map(|g| group.elements.contains(g))
+                .collect();
+            assert_eq!(found, expected, "{}x{} seed {}", rows, cols, seed);
+            if group.elements.len() > 1 {
+                nontrivial += 1;
+            }
+        }
+    }
+    assert!(nontrivial > 0);
+}
+
+#[test]
+fn symmetry_groups_are_named() {
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let black = Color::new(0.0, 0.0, 0.0, 1.0);
+    let mut pattern = create_base_pattern();
+    for pixel in pattern.pixels.iter_mut().flatten() {
+        pixel.color = white;
+        pixel.perm = Permutation::identity(2);
+    }
+    assert_eq!(symmetry_group(&pattern).name(), "D4");
+
+    // One dark corner leaves only the mirror through it
+    pattern.pixels[0][0].color = black;
+    assert_eq!(symmetry_group(&pattern).elements, vec![Permutation::identity(2), Permutation::transpose(2)]);
+    assert_eq!(symmetry_group(&pattern).name(), "D1");
+
+    // Two opposite dark corners add the other diagonal and the half turn
+    pattern.pixels[1][1].color = black;
+    assert_eq!(symmetry_group(&pattern).name(), "D2");
+
+    let mut wide = pattern.resized(2, 3);
+    for pixel in wide.pixels.iter_mut().flatten() {
+        pixel.color = white;
+    }
+    assert_eq!(symmetry_group(&wide).name(), "D2");
+    assert_eq!(symmetry_group(&create_base_pattern()).name(), "trivial");
+}
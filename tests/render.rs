@@ -0,0 +1,853 @@
+use fractals::{
+    base_tile_image, box_counting_dimension, check_memory, color_counts, composite_layers,
+    contact_sheet, create_base_pattern, crop_fractal, document_from_png, downsample, edges_wrap,
+    encode_fractal, estimate_memory, export_decay_animation, export_format, export_image,
+    export_levels, export_morph_animation, export_png_chunked, fractal_digest,
+    generate_fractal_parallel, generate_fractal_with_curve, generate_region, grid_size,
+    load_colors_from_image, load_pattern_from_file, luminance_histogram, normalize_range,
+    read_tile_colors, render_digest, render_image, render_pattern, render_pattern_progressive,
+    render_pattern_with_curve, render_supersampled, save_png_with_document, seamless_tile,
+    validate_pattern, validate_pattern_warnings, BlendMode, Caption, CaptionCorner, Color,
+    ColorSpace, ComposeOrder, DecayModel, ExportOptions, FractalDocument, Layer, MorphMode,
+    NormalizeMode, Pattern, PatternError, Permutation, Pixel, DEFAULT_MEMORY_LIMIT,
+};
+
+fn assert_color_eq(a: Color, b: Color) {
+    assert_eq!([a.r, a.g, a.b, a.a], [b.r, b.g, b.b, b.a]);
+}
+
+#[test]
+fn single_iteration_is_the_base_pattern() {
+    let pattern = create_base_pattern();
+    let fractal = render_pattern(&pattern, 1, 0.5);
+
+    assert_eq!(fractal.len(), 2);
+    for (y, row) in fractal.iter().enumerate() {
+        assert_eq!(row.len(), 2);
+        for (x, &color) in row.iter().enumerate() {
+            assert_color_eq(color, pattern.pixels[y][x].color);
+        }
+    }
+}
+
+#[test]
+fn render_image_matches_grid() {
+    let pattern = create_base_pattern();
+    let fractal = render_pattern(&pattern, 6, 0.5);
+    let image = render_image(&pattern, 6, 0.5);
+
+    assert_eq!(image.dimensions(), (64, 64));
+    assert_eq!(*image.get_pixel(5, 9), fractal[9][5].to_rgba());
+}
+
+#[test]
+fn three_by_three_pattern_renders_powers_of_three() {
+    let pixel = |a| Pixel {
+        color: Color::new(0.0, 0.0, 0.0, a),
+        perm: Permutation::identity(3),
+        decay: 1.0,
+        weight: None,
+    };
+    let pattern = Pattern {
+        base_dim: 3,
+        base_rows: 3,
+        pixels: (0..3)
+            .map(|y| (0..3).map(|x| pixel(if (y, x) == (1, 1) { 0.0 } else { 1.0 })).collect())
+            .collect(),
+        blend_mode: BlendMode::Lerp,
+        decay_model: DecayModel::Linear,
+        symmetry: false,
+        invert_alpha: false,
+        transparent_as_color: false,
+        premultiplied_alpha: false,
+        normalize: false,
+        normalize_mode: NormalizeMode::Luminance,
+        initial_pixels: None,
+        name: String::new(),
+        perm_depth_limit: None,
+        color_space: ColorSpace::default(),
+        compose_order: ComposeOrder::default(),
+        alpha_cutoff: 0.0,
+    };
+    validate_pattern(&pattern).unwrap();
+
+    assert_eq!(render_image(&pattern, 4, 0.5).dimensions(), (81, 81));
+}
+
+#[test]
+fn legacy_pattern_file_loads_as_2x2() {
+    let pattern = load_pattern_from_file("patterns/bricks.json").unwrap();
+    assert_eq!(pattern.base_dim, 2);
+}
+
+#[test]
+fn rejects_non_square_pattern() {
+    let mut pattern = create_base_pattern();
+    pattern.pixels[1].pop();
+    assert!(validate_pattern(&pattern).is_err());
+}
+
+#[test]
+fn random_patterns_are_valid_and_reproducible() {
+    for (rows, cols) in [(2, 2), (3, 3), (4, 4), (2, 3), (4, 2)] {
+        for seed in 0..20 {
+            let pattern = Pattern::random(rows, cols, seed);
+            assert_eq!((pattern.base_rows, pattern.base_dim), (rows, cols));
+            validate_pattern(&pattern).unwrap();
+            assert!(pattern == Pattern::random(rows, cols, seed));
+        }
+    }
+    assert!(Pattern::random(2, 2, 1) != Pattern::random(2, 2, 2));
+}
+
+#[test]
+fn mutations_are_small_valid_and_reproducible() {
+    let pattern = Pattern::random(3, 2, 7);
+    assert!(pattern.mutated(0.0, 5) == pattern);
+
+    for seed in 0..20 {
+        let mutant = pattern.mutated(0.1, seed);
+        validate_pattern(&mutant).unwrap();
+        assert!(mutant == pattern.mutated(0.1, seed));
+        for (before, after) in pattern.pixels.iter().flatten().zip(mutant.pixels.iter().flatten()) {
+            assert!((before.color.r - after.color.r).abs() <= 0.1 + 1e-6);
+            assert!((before.color.a - after.color.a).abs() <= 0.1 + 1e-6);
+        }
+    }
+    assert!(pattern.mutated(0.5, 1) != pattern.mutated(0.5, 2));
+}
+
+#[test]
+fn randomizing_colors_or_permutations_keeps_the_other() {
+    let pattern = Pattern::random(2, 3, 7);
+    let colors = |pattern: &Pattern| pattern.pixels.iter().flatten().map(|pixel| pixel.color).collect::<Vec<_>>();
+    let perms = |pattern: &Pattern| pattern.pixels.iter().flatten().map(|pixel| pixel.perm.clone()).collect::<Vec<_>>();
+
+    let mut recolored = pattern.clone();
+    recolored.randomize_colors(3);
+    validate_pattern(&recolored).unwrap();
+    assert!(perms(&recolored) == perms(&pattern));
+    assert!(colors(&recolored) != colors(&pattern));
+    let mut again = pattern.clone();
+    again.randomize_colors(3);
+    assert!(again == recolored);
+
+    let mut permuted = pattern.clone();
+    permuted.randomize_permutations(3);
+    validate_pattern(&permuted).unwrap();
+    assert!(colors(&permuted) == colors(&pattern));
+}
+
+#[test]
+fn seamless_tile_wraps_at_the_edges() {
+    let fractal = render_pattern(&create_base_pattern(), 5, 0.5);
+    assert!(!edges_wrap(&fractal));
+
+    let tile = seamless_tile(&fractal);
+    assert_eq!(tile.len(), fractal.len());
+    assert!(edges_wrap(&tile));
+    // The interior outside the blended band is untouched
+    assert!(tile[16][16] == fractal[16][16]);
+}
+
+#[test]
+fn decay_curve_generalizes_scalar_decay() {
+    let pattern = create_base_pattern();
+    let scalar = render_pattern(&pattern, 6, 0.5);
+
+    // A single value and a uniform curve both repeat the factor at every level
+    assert!(render_pattern_with_curve(&pattern, 6, &[0.5]) == scalar);
+    assert!(render_pattern_with_curve(&pattern, 6, &[0.5; 5]) == scalar);
+    assert!(generate_fractal_with_curve(6, &pattern, &[0.5, 0.5]) == scalar);
+
+    assert!(render_pattern_with_curve(&pattern, 6, &[0.9, 0.1]) != scalar);
+}
+
+#[test]
+fn symmetry_mirrors_both_axes() {
+    for mut pattern in [create_base_pattern(), Pattern::random(3, 3, 7), Pattern::random(2, 3, 7)] {
+        pattern.symmetry = true;
+        let fractal = render_pattern(&pattern, 3, 0.5);
+        let (width, height) = grid_size(&fractal);
+        for y in 0..height {
+            for x in 0..width {
+                assert!(fractal[y][x] == fractal[y][width - 1 - x]);
+                assert!(fractal[y][x] == fractal[height - 1 - y][x]);
+            }
+        }
+    }
+}
+
+#[test]
+fn downsample_averages_blocks_with_alpha_weighting() {
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 1.0, 0.0);
+    let grid = vec![vec![red, clear], vec![clear, red]];
+
+    let small = downsample(&grid, 2);
+    assert_eq!(small.len(), 1);
+    // Transparent samples only lower the alpha; they don't tint the color
+    assert_eq!(small[0][0].to_rgba().0, [255, 0, 0, 128]);
+
+    let pattern = create_base_pattern();
+    assert_eq!(render_supersampled(&pattern, 5, &[0.5], 1).len(), 32);
+    assert!(downsample(&grid, 1) == grid);
+}
+
+#[test]
+fn memory_guard_refuses_huge_renders() {
+    assert!(estimate_memory(2, 2, 11).unwrap() < DEFAULT_MEMORY_LIMIT);
+    assert_eq!(estimate_memory(4, 4, 40), None);
+    assert_eq!(estimate_memory(2, 3, 2), estimate_memory(6, 6, 1));
+
+    assert!(check_memory(2, 2, 8, DEFAULT_MEMORY_LIMIT).is_ok());
+    assert!(matches!(
+        check_memory(4, 4, 12, DEFAULT_MEMORY_LIMIT),
+        Err(PatternError::ValidationError(_))
+    ));
+    // No limit lets through a render too large to estimate
+    assert!(matches!(check_memory(2, 2, 64, u64::MAX), Err(PatternError::ValidationError(_))));
+}
+
+#[test]
+fn composite_layers_applies_opacity() {
+    let pattern = create_base_pattern();
+    let base = render_pattern(&pattern, 3, 0.5);
+    let top = vec![vec![Color::new(0.2, 0.4, 0.6, 1.0); base.len()]; base.len()];
+    let layer = |opacity| Layer {
+        name: String::new(),
+        pattern: pattern.clone(),
+        blend_mode: BlendMode::Lerp,
+        opacity,
+    };
+
+    let mut hidden = base.clone();
+    composite_layers(&mut hidden, &[layer(0.0)], |_| top.clone());
+    assert_eq!(hidden, base);
+
+    let mut covered = base.clone();
+    composite_layers(&mut covered, &[layer(1.0)], |_| top.clone());
+    assert_eq!(covered, top);
+
+    // A layer whose size doesn't match the base is skipped
+    let mut mismatched = base.clone();
+    composite_layers(&mut mismatched, &[layer(1.0)], |_| vec![vec![top[0][0]; 2]; 2]);
+    assert_eq!(mismatched, base);
+}
+
+#[test]
+fn contact_sheet_lays_out_labeled_cells() {
+    let pattern = create_base_pattern();
+    let sheet = contact_sheet(&pattern, 3, &[0.2, 0.4, 0.6], 2);
+
+    // Two columns of 8px cells, two rows with a label strip under each
+    assert_eq!(sheet.dimensions(), (8 + 2 * (8 + 8), 8 + 2 * (8 + 23 + 8)));
+    let first = render_image(&pattern, 3, 0.2);
+    assert_eq!(sheet.get_pixel(8 + 3, 8 + 5), first.get_pixel(3, 5));
+}
+
+#[test]
+fn color_counts_quantize_and_rank() {
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let fractal = vec![
+        vec![red, Color::new(0.999, 0.0, 0.0, 1.0)],
+        vec![Color::new(0.0, 0.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0, 0.0)],
+    ];
+    let counts = color_counts(&fractal, 2);
+
+    assert_eq!(counts.cells, 4);
+    assert_eq!(counts.unique, 3);
+    assert_eq!(counts.top.len(), 2);
+    assert_eq!(counts.top[0], (red.to_rgba(), 2));
+}
+
+#[test]
+fn render_digest_is_stable() {
+    let pattern = create_base_pattern();
+    let digest = render_digest(&pattern, 6, 0.5);
+
+    assert_eq!(digest, fractal_digest(&render_pattern(&pattern, 6, 0.5)));
+    assert_ne!(digest, render_digest(&pattern, 6, 0.4));
+    // Changing this value means the rendered output changed
+    assert_eq!(format!("{:016x}", digest), "ba64c64e8cd98ff1");
+}
+
+#[test]
+fn rectangular_patterns_scale_each_axis() {
+    let pattern = Pattern::random(2, 3, 11);
+    let fractal = render_pattern(&pattern, 3, 0.5);
+
+    assert_eq!(grid_size(&fractal), (27, 8));
+    assert_eq!(render_image(&pattern, 3, 0.5).dimensions(), (27, 8));
+    assert!(fractal == generate_fractal_parallel(3, &pattern, 0.5));
+    assert_eq!(grid_size(&render_supersampled(&pattern, 2, &[0.5], 1)), (9, 4));
+}
+
+#[test]
+fn export_format_follows_extension() {
+    use std::path::Path;
+
+    assert_eq!(export_format(Path::new("out.JPG")).unwrap(), image::ImageFormat::Jpeg);
+    assert_eq!(export_format(Path::new("out.webp")).unwrap(), image::ImageFormat::WebP);
+    assert_eq!(export_format(Path::new("out.tif")).unwrap(), image::ImageFormat::Tiff);
+    assert!(export_format(Path::new("out.bmp")).is_err());
+    assert!(export_format(Path::new("out")).is_err());
+}
+
+#[test]
+fn pixel_decay_fades_detail_beneath_it() {
+    let pattern = create_base_pattern();
+    let uniform = render_pattern(&pattern, 4, 0.8);
+
+    let mut faded = pattern.clone();
+    faded.pixels[0][0].decay = 0.0;
+    let fractal = render_pattern(&faded, 4, 0.8);
+    assert!(fractal != uniform);
+    // The top-left quadrant keeps its seed color once its children stop blending
+    for row in &fractal[..8] {
+        for color in &row[..8] {
+            assert_eq!(color.to_rgba(), pattern.pixels[0][0].color.to_rgba());
+        }
+    }
+
+    faded.pixels[0][0].decay = 1.5;
+    assert!(validate_pattern(&faded).is_err());
+}
+
+#[test]
+fn crop_fractal_clamps_to_bounds() {
+    let fractal = render_pattern(&create_base_pattern(), 3, 0.5);
+
+    let crop = crop_fractal(&fractal, 2, 5, 3, 2);
+    assert_eq!(grid_size(&crop), (3, 2));
+    assert!(crop[1][2] == fractal[6][4]);
+
+    assert_eq!(grid_size(&crop_fractal(&fractal, 6, 6, 10, 10)), (2, 2));
+    assert_eq!(grid_size(&crop_fractal(&fractal, 9, 0, 4, 4)), (0, 4));
+}
+
+#[test]
+fn progressive_levels_match_shallower_renders() {
+    let mut pattern = create_base_pattern();
+    pattern.symmetry = true;
+    // Small enough for the serial generator, then large enough for the parallel one
+    for iterations in [5, 9] {
+        let mut seen = Vec::new();
+        let fractal = render_pattern_progressive(&pattern, iterations, &[0.6], |level, colors| {
+            assert_eq!(*colors, render_pattern(&pattern, level, 0.6));
+            seen.push(level);
+            true
+        }).unwrap();
+
+        assert_eq!(seen, (2..iterations).collect::<Vec<_>>());
+        assert_eq!(fractal, render_pattern(&pattern, iterations, 0.6));
+    }
+}
+
+#[test]
+fn progressive_render_cancels() {
+    let pattern = create_base_pattern();
+    let fractal = render_pattern_progressive(&pattern, 6, &[0.5], |level, _| level < 3);
+    assert!(fractal.is_none());
+}
+
+#[test]
+fn transparent_as_color_ignores_alpha_for_recursion() {
+    let mut pattern = create_base_pattern();
+    pattern.transparent_as_color = true;
+    let mut opaque = pattern.clone();
+    for pixel in opaque.pixels.iter_mut().flatten() {
+        pixel.color.a = 1.0;
+    }
+    let channels = |fractal: &[Vec<Color>]| -> Vec<[f32; 3]> {
+        fractal.iter().flatten().map(|c| [c.r, c.g, c.b]).collect()
+    };
+
+    // Only the output alpha still reflects the transparent pixel
+    let fractal = render_pattern(&pattern, 5, 0.5);
+    assert_eq!(channels(&fractal), channels(&render_pattern(&opaque, 5, 0.5)));
+    pattern.transparent_as_color = false;
+    assert_ne!(channels(&fractal), channels(&render_pattern(&pattern, 5, 0.5)));
+}
+
+#[test]
+fn border_surrounds_the_image_without_filling_transparency() {
+    let fractal = render_pattern(&create_base_pattern(), 3, 0.5);
+    let options = ExportOptions {
+        border: 2,
+        border_color: Color::new(1.0, 0.0, 0.0, 1.0),
+        ..ExportOptions::default()
+    };
+    let image = export_image(&fractal, &options).into_rgba8();
+
+    assert_eq!(image.dimensions(), (12, 12));
+    assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(11, 6).0, [255, 0, 0, 255]);
+    for (y, row) in fractal.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            assert_eq!(*image.get_pixel(x as u32 + 2, y as u32 + 2), color.to_rgba());
+        }
+    }
+
+    let wide = export_image(&fractal, &ExportOptions { sixteen_bit: true, ..options });
+    assert_eq!(wide.color(), image::ColorType::Rgba16);
+    assert_eq!((wide.width(), wide.height()), (12, 12));
+}
+
+#[test]
+fn animation_frames_stay_in_order() {
+    use image::AnimationDecoder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pattern = create_base_pattern();
+    let path = std::env::temp_dir().join(format!("fractals_animation_{}.gif", std::process::id()));
+    let progress = AtomicUsize::new(0);
+    export_decay_animation(&pattern, &[], 4, 0.0, 1.0, 6, &ExportOptions::default(), &path, |done| {
+        progress.fetch_max(done, Ordering::Relaxed);
+    }).unwrap();
+    assert_eq!(progress.into_inner(), 6);
+
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(frames.len(), 6);
+
+    // GIF quantizes colors, so each frame need only be closest to its own decay
+    let distance = |a: &image::RgbaImage, b: &image::RgbaImage| -> u64 {
+        a.pixels().zip(b.pixels())
+            .flat_map(|(p, q)| p.0.into_iter().zip(q.0).map(|(x, y)| x.abs_diff(y) as u64))
+            .sum()
+    };
+    let expected: Vec<_> = (0..6).map(|i| render_image(&pattern, 4, i as f32 / 5.0)).collect();
+    for (i, frame) in frames.iter().enumerate() {
+        let closest = (0..6).min_by_key(|&j| distance(frame.buffer(), &expected[j])).unwrap();
+        assert_eq!(closest, i);
+    }
+
+    // Frames go through the same conversion as image exports
+    let options = ExportOptions { border: 2, ..ExportOptions::default() };
+    export_decay_animation(&pattern, &[], 4, 0.0, 1.0, 2, &options, &path, |_| {}).unwrap();
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(frames[0].buffer().dimensions(), (16 + 4, 16 + 4));
+}
+
+#[test]
+fn normalize_range_stretches_visible_colors() {
+    let flat = vec![vec![
+        Color::new(0.2, 0.1, 0.1, 1.0),
+        Color::new(0.4, 0.3, 0.3, 1.0),
+        Color::new(0.8, 0.8, 0.8, 1.0),
+        Color::new(0.9, 0.9, 0.9, 0.0),
+    ]];
+
+    // Channels scale together so hues are kept; the transparent cell doesn't count
+    let mut luminance = flat.clone();
+    normalize_range(&mut luminance, NormalizeMode::Luminance);
+    let (min, max) = (flat[0][0].luminance(), flat[0][2].luminance());
+    assert!(luminance[0][0].luminance().abs() < 1e-5);
+    assert!((luminance[0][1].luminance() - (flat[0][1].luminance() - min) / (max - min)).abs() < 1e-5);
+    assert!((luminance[0][1].to_hsv().0 - flat[0][1].to_hsv().0).abs() < 1e-3);
+    assert_color_eq(luminance[0][2], Color::new(1.0, 1.0, 1.0, 1.0));
+    assert_color_eq(luminance[0][3], flat[0][3]);
+
+    let mut channels = flat.clone();
+    normalize_range(&mut channels, NormalizeMode::Channels);
+    assert_color_eq(channels[0][0], Color::new(0.0, 0.0, 0.0, 1.0));
+    assert_color_eq(channels[0][2], Color::new(1.0, 1.0, 1.0, 1.0));
+
+    let mut uniform = vec![vec![Color::new(0.5, 0.2, 0.1, 1.0); 2]];
+    normalize_range(&mut uniform, NormalizeMode::Luminance);
+    assert_color_eq(uniform[0][0], Color::new(0.5, 0.2, 0.1, 1.0));
+}
+
+#[test]
+fn normalize_flag_applies_after_generation() {
+    let mut pattern = create_base_pattern();
+    let mut expected = render_pattern(&pattern, 5, 0.5);
+    normalize_range(&mut expected, NormalizeMode::Channels);
+
+    pattern.normalize = true;
+    pattern.normalize_mode = NormalizeMode::Channels;
+    assert_eq!(render_pattern(&pattern, 5, 0.5), expected);
+}
+
+#[test]
+fn preview_and_export_encode_mid_gray_alike() {
+    let gray = vec![vec![Color::new(0.5, 0.5, 0.5, 1.0)]];
+    for gamma in [1.0, 2.2] {
+        let options = ExportOptions { gamma, ..ExportOptions::default() };
+        let preview = encode_fractal(&gray, &options);
+        let export = export_image(&gray, &options).into_rgba8();
+        assert_eq!(preview, export);
+
+        // Stored values are already sRGB, so gamma 1.0 writes them unchanged
+        let expected = (0.5_f32.powf(1.0 / gamma) * 255.0).round() as u8;
+        assert_eq!(preview.get_pixel(0, 0).0, [expected, expected, expected, 255]);
+    }
+}
+
+#[test]
+fn premultiplied_blending_avoids_dark_fringes() {
+    let pixel = |color| Pixel { color, perm: Permutation::identity(2), decay: 1.0, weight: None };
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 0.0, 0.0);
+    let mut pattern = create_base_pattern();
+    pattern.pixels = vec![vec![pixel(red), pixel(clear)], vec![pixel(clear), pixel(red)]];
+
+    // Red fading into transparent black turns darker unless premultiplied
+    let visible_reds = |pattern: &Pattern| -> Vec<f32> {
+        render_pattern(pattern, 4, 0.5).into_iter().flatten()
+            .filter(|color| color.a > 0.0)
+            .map(|color| color.r)
+            .collect()
+    };
+    assert!(visible_reds(&pattern).iter().any(|&r| r < 1.0));
+    pattern.premultiplied_alpha = true;
+    assert!(visible_reds(&pattern).iter().all(|&r| (r - 1.0).abs() < 1e-6));
+}
+
+#[test]
+fn base_tile_image_scales_each_cell_to_a_block() {
+    let mut pattern = create_base_pattern().resized(2, 3);
+    pattern.pixels[1][2].color = Color::new(0.0, 0.0, 1.0, 0.5);
+
+    let image = base_tile_image(&pattern, 4);
+    assert_eq!(image.dimensions(), (12, 8));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let color = pattern.pixels[y as usize / 4][x as usize / 4].color;
+        assert_eq!(*pixel, color.to_rgba());
+    }
+    assert_eq!(base_tile_image(&pattern, 0).dimensions(), (3, 2));
+}
+
+#[test]
+fn morph_interpolates_colors_and_cross_fades_other_changes() {
+    use image::AnimationDecoder;
+
+    let from = create_base_pattern();
+    let mut to = from.clone();
+    for pixel in to.pixels.iter_mut().flatten() {
+        pixel.color = Color::new(1.0, 1.0, 0.0, 1.0);
+    }
+    assert!(from.can_interpolate_to(&to));
+    assert_eq!(from.interpolated(&to, 1.0).pixels[0][0].color, to.pixels[0][0].color);
+
+    let path = std::env::temp_dir().join(format!("fractals_morph_{}.gif", std::process::id()));
+    let used = export_morph_animation(&from, &to, 3, &[0.5], 4, MorphMode::Interpolate, &path, |_| {}).unwrap();
+    assert_eq!(used, MorphMode::Interpolate);
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+    assert_eq!(frames.len(), 4);
+    // The last frame is pattern b itself, which is solid yellow
+    assert!(frames[3].buffer().pixels().all(|pixel| pixel.0 == [255, 255, 0, 255]));
+
+    // Permutations can't be interpolated, so a change to one falls back to a cross-fade
+    to.pixels[0][0].perm = Permutation::identity(2);
+    assert!(!from.can_interpolate_to(&to));
+    let used = export_morph_animation(&from, &to, 3, &[0.5], 2, MorphMode::Interpolate, &path, |_| {}).unwrap();
+    assert_eq!(used, MorphMode::CrossFade);
+
+    let result = export_morph_animation(&from, &from.resized(3, 3), 3, &[0.5], 2, MorphMode::CrossFade, &path, |_| {});
+    assert!(matches!(result, Err(PatternError::ValidationError(_))));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn pixel_weight_replaces_alpha_in_the_blend() {
+    // Opaque colors weighted like transparent ones render the same first
+    // expansion as the transparent originals, but keep their own alpha.
+    // Deeper levels differ since a weight doesn't fade like blended alpha.
+    let mut transparent = create_base_pattern();
+    for pixel in transparent.pixels.iter_mut().flatten() {
+        pixel.color.a = 0.25;
+    }
+    let mut weighted = transparent.clone();
+    for pixel in weighted.pixels.iter_mut().flatten() {
+        pixel.weight = Some(pixel.color.a);
+        pixel.color.a = 1.0;
+    }
+    validate_pattern(&weighted).unwrap();
+
+    let expected = render_pattern(&transparent, 2, 0.5);
+    let fractal = render_pattern(&weighted, 2, 0.5);
+    for (color, expected) in fractal.iter().flatten().zip(expected.iter().flatten()) {
+        assert_color_eq(Color { a: expected.a, ..*color }, *expected);
+        assert_eq!(color.a, 1.0);
+    }
+
+    weighted.pixels[0][0].weight = Some(1.5);
+    assert!(validate_pattern(&weighted).is_err());
+}
+
+#[test]
+fn export_levels_writes_each_level_at_the_final_size() {
+    let pattern = create_base_pattern().resized(2, 3);
+    let dir = std::env::temp_dir().join(format!("fractals_levels_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    export_levels(&pattern, 3, &[0.5], &dir).unwrap();
+
+    for level in 1..=3 {
+        let image = image::open(dir.join(format!("level_{:02}.png", level))).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (27, 8));
+        // Each cell of a level covers a block of the scaled-up image
+        let fractal = render_pattern(&pattern, level, 0.5);
+        let (block_x, block_y) = (27 / fractal[0].len() as u32, 8 / fractal.len() as u32);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            assert_eq!(*pixel, fractal[(y / block_y) as usize][(x / block_x) as usize].to_rgba());
+        }
+    }
+    assert!(!dir.join("level_04.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn caption_is_drawn_into_the_chosen_corner_only() {
+    let mut pattern = create_base_pattern();
+    for pixel in pattern.pixels.iter_mut().flatten() {
+        pixel.color = Color::new(0.0, 0.0, 0.0, 1.0);
+    }
+    let fractal = render_pattern(&pattern, 7, 0.5);
+    let options = ExportOptions {
+        caption: Some(Caption {
+            text: "Iterations: 7".to_string(),
+            corner: CaptionCorner::BottomRight,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        }),
+        ..ExportOptions::default()
+    };
+    let image = export_image(&fractal, &options).into_rgba8();
+
+    assert_eq!(image.dimensions(), (128, 128));
+    let lit = |x_range: std::ops::Range<u32>, y_range: std::ops::Range<u32>| {
+        y_range.flat_map(|y| x_range.clone().map(move |x| (x, y)))
+            .filter(|&(x, y)| image.get_pixel(x, y).0[0] > 0)
+            .count()
+    };
+    assert!(lit(64..128, 96..128) > 0);
+    assert_eq!(lit(0..128, 0..96), 0);
+    assert_eq!(lit(0..32, 0..128), 0);
+
+    let blank = export_image(&fractal, &ExportOptions::default()).into_rgba8();
+    assert!(blank.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+}
+
+#[test]
+fn degenerate_patterns_get_warnings_but_stay_valid() {
+    let pattern = create_base_pattern();
+    assert!(validate_pattern_warnings(&pattern).is_empty());
+
+    let mut flat = pattern.clone();
+    for pixel in flat.pixels.iter_mut().flatten() {
+        pixel.color = Color::new(0.2, 0.4, 0.6, 1.0);
+    }
+    validate_pattern(&flat).unwrap();
+    let warnings = validate_pattern_warnings(&flat);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("same color"));
+
+    // A differing initial pattern keeps the render from being flat, but one
+    // that matches the recursion pattern does nothing
+    flat.initial_pixels = Some(pattern.pixels.clone());
+    assert!(validate_pattern_warnings(&flat).is_empty());
+    flat.initial_pixels = Some(flat.pixels.clone());
+    assert_eq!(validate_pattern_warnings(&flat).len(), 2);
+
+    let mut clear = pattern.clone();
+    for pixel in clear.pixels.iter_mut().flatten() {
+        pixel.color.a = 0.0;
+    }
+    let warnings = validate_pattern_warnings(&clear);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("transparent"));
+}
+
+#[test]
+fn linear_pattern_colors_are_encoded_before_generation() {
+    let srgb = create_base_pattern();
+    let mut linear = srgb.clone();
+    linear.color_space = ColorSpace::Linear;
+    for pixel in linear.pixels.iter_mut().flatten() {
+        pixel.color = pixel.color.srgb_to_linear();
+    }
+
+    // The same colors stored either way render the same
+    let (a, b) = (render_pattern(&srgb, 3, 0.5), render_pattern(&linear, 3, 0.5));
+    for (a, b) in a.iter().flatten().zip(b.iter().flatten()) {
+        assert_eq!(a.to_rgba(), b.to_rgba());
+    }
+    assert_eq!(base_tile_image(&srgb, 1), base_tile_image(&linear, 1));
+}
+
+#[test]
+fn generated_regions_match_the_whole_render() {
+    let base = Pattern::random(2, 3, 11);
+    let mut mirrored = base.clone();
+    mirrored.symmetry = true;
+    let mut limited = base.clone();
+    limited.perm_depth_limit = Some(2);
+    limited.initial_pixels = Some(Pattern::random(2, 3, 12).pixels);
+
+    for pattern in [&base, &mirrored, &limited] {
+        let whole = render_pattern_with_curve(pattern, 4, &[0.6, 0.3]);
+        for (x, y, width, height) in [(0, 0, 81, 16), (5, 3, 17, 9), (40, 0, 1, 16), (30, 7, 40, 2), (80, 15, 1, 1)] {
+            let region = generate_region(pattern, 4, &[0.6, 0.3], (x, y), (width, height)).unwrap();
+            assert!(region == crop_fractal(&whole, x, y, width, height), "{}x{} at ({}, {})", width, height, x, y);
+        }
+    }
+
+    assert!(generate_region(&base, 4, &[0.5], (80, 0), (2, 1)).is_err());
+    let mut normalized = base.clone();
+    normalized.normalize = true;
+    assert!(generate_region(&normalized, 4, &[0.5], (0, 0), (1, 1)).is_err());
+}
+
+#[test]
+fn chunked_png_export_matches_the_whole_render() {
+    let pattern = create_base_pattern().resized(3, 3);
+    let options = ExportOptions { gamma: 2.2, ..ExportOptions::default() };
+    let path = std::env::temp_dir().join(format!("fractals_chunked_{}.png", std::process::id()));
+    let bands = std::cell::Cell::new(0);
+    export_png_chunked(&pattern, 6, &[0.5], &options, &path, |done| bands.set(done)).unwrap();
+
+    // 729 pixels square takes two bands of two blocks each
+    assert_eq!(bands.get(), 2);
+    let written = image::open(&path).unwrap().into_rgba8();
+    let expected = encode_fractal(&render_pattern(&pattern, 6, 0.5), &options);
+    assert!(written == expected);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn box_counting_dimension_matches_known_shapes() {
+    let opaque = Color::new(1.0, 1.0, 1.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 0.0, 0.0);
+    let grid = |f: &dyn Fn(usize, usize) -> bool| -> Vec<Vec<Color>> {
+        (0..64).map(|y| (0..64).map(|x| if f(y, x) { opaque } else { clear }).collect()).collect()
+    };
+
+    let filled = box_counting_dimension(&grid(&|_, _| true), 0.5).unwrap();
+    assert!((filled.dimension - 2.0).abs() < 1e-4);
+    assert_eq!(filled.counts.first(), Some(&(1, 64 * 64)));
+    assert_eq!(filled.counts.last(), Some(&(32, 4)));
+
+    let line = box_counting_dimension(&grid(&|y, _| y == 10), 0.5).unwrap();
+    assert!((line.dimension - 1.0).abs() < 1e-4);
+
+    // Pascal's triangle mod 2 is the Sierpinski triangle
+    let sierpinski = box_counting_dimension(&grid(&|y, x| x & y == 0), 0.5).unwrap();
+    assert!((sierpinski.dimension - 3f32.log2()).abs() < 1e-4);
+
+    // Nothing is above the threshold
+    assert!(box_counting_dimension(&grid(&|_, _| true), 1.0).is_none());
+}
+
+#[test]
+fn exported_png_carries_its_document() {
+    let document = FractalDocument {
+        pattern: Pattern { name: "Ünïcode".to_string(), ..Pattern::random(2, 3, 5) },
+        iterations: 3,
+        decay: 0.4,
+        background: None,
+        level_decays: Some(vec![0.7, 0.2]),
+        layers: vec![Layer {
+            name: String::new(),
+            pattern: Pattern::random(2, 3, 6),
+            blend_mode: BlendMode::Screen,
+            opacity: 0.5,
+        }],
+    };
+    let fractal = render_pattern(&document.pattern, document.iterations, document.decay);
+    let path = std::env::temp_dir().join(format!("fractals_parameters_{}.png", std::process::id()));
+
+    for sixteen_bit in [false, true] {
+        let image = export_image(&fractal, &ExportOptions { sixteen_bit, ..ExportOptions::default() });
+        save_png_with_document(&image, &path, &document).unwrap();
+        let read = document_from_png(path.to_str().unwrap()).unwrap();
+        assert!(read == document);
+        let reopened = image::open(&path).unwrap();
+        assert_eq!((reopened.width(), reopened.height()), (27, 8));
+        assert!(reopened == image);
+    }
+
+    // A PNG without the chunk has nothing to import
+    export_image(&fractal, &ExportOptions::default()).save(&path).unwrap();
+    assert!(matches!(document_from_png(path.to_str().unwrap()), Err(PatternError::ValidationError(_))));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn alpha_cutoff_turns_faint_cells_into_holes() {
+    let mut pattern = Pattern::random(2, 2, 9);
+    for (i, pixel) in pattern.pixels.iter_mut().flatten().enumerate() {
+        pixel.color.a = [0.1, 0.3, 0.8, 1.0][i];
+        pixel.weight = None;
+    }
+    let with_cutoff = |cutoff| Pattern { alpha_cutoff: cutoff, ..pattern.clone() };
+    assert!(render_pattern(&with_cutoff(0.0), 4, 0.5) == render_pattern(&pattern, 4, 0.5));
+    assert!(render_pattern(&with_cutoff(0.2), 4, 0.5) != render_pattern(&pattern, 4, 0.5));
+
+    // Below the cutoff, a top-level pixel expands like one weighted zero
+    let mut holes = pattern.clone();
+    holes.pixels[0][0].weight = Some(0.0);
+    holes.pixels[0][1].weight = Some(0.0);
+    assert!(render_pattern(&with_cutoff(0.5), 2, 0.5) == render_pattern(&holes, 2, 0.5));
+
+    assert!(validate_pattern(&with_cutoff(1.5)).is_err());
+}
+
+#[test]
+fn renaming_keeps_the_render_hash() {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    let hash = |value: &dyn Fn(&mut DefaultHasher)| {
+        let mut hasher = DefaultHasher::new();
+        value(&mut hasher);
+        hasher.finish()
+    };
+    let pattern = create_base_pattern();
+    let renamed = Pattern { name: "Renamed".to_string(), ..pattern.clone() };
+    assert_eq!(hash(&|h| pattern.hash(h)), hash(&|h| renamed.hash(h)));
+    let changed = Pattern { alpha_cutoff: 0.5, ..pattern.clone() };
+    assert_ne!(hash(&|h| pattern.hash(h)), hash(&|h| changed.hash(h)));
+
+    let layer = Layer { name: "A".to_string(), pattern, blend_mode: BlendMode::Lerp, opacity: 0.5 };
+    let renamed_layer = Layer { name: "B".to_string(), ..layer.clone() };
+    assert_eq!(hash(&|h| layer.hash(h)), hash(&|h| renamed_layer.hash(h)));
+}
+
+#[test]
+fn luminance_histogram_bins_visible_cells() {
+    let black = Color::new(0.0, 0.0, 0.0, 1.0);
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 0.0, 0.0);
+    let fractal = vec![vec![black, white], vec![white, clear]];
+
+    let histogram = luminance_histogram(&fractal, 4);
+    assert_eq!(histogram.bins, vec![1, 0, 0, 2]);
+    assert_eq!(histogram.transparent, 1);
+
+    let empty = luminance_histogram(&fractal, 0);
+    assert!(empty.bins.is_empty());
+    assert_eq!(empty.transparent, 1);
+}
+
+#[test]
+fn tile_colors_come_from_an_image_the_size_of_the_tile() {
+    let path = std::env::temp_dir().join(format!("fractals_tile_colors_{}.png", std::process::id()));
+    let image = image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8 * 100, y as u8 * 200, 0, 255]));
+    image.save(&path).unwrap();
+
+    let mut pattern = Pattern::random(2, 3, 4);
+    let colors = read_tile_colors(&pattern, path.to_str().unwrap()).unwrap();
+    assert_eq!(grid_size(&colors), (3, 2));
+    assert_eq!(colors[1][2].to_rgba().0, [200, 200, 0, 255]);
+
+    let perms: Vec<_> = pattern.pixels.iter().flatten().map(|pixel| pixel.perm.clone()).collect();
+    load_colors_from_image(&mut pattern, path.to_str().unwrap()).unwrap();
+    assert!(pattern.pixels.iter().flatten().map(|pixel| pixel.color).eq(colors.into_iter().flatten()));
+    assert!(pattern.pixels.iter().flatten().map(|pixel| pixel.perm.clone()).eq(perms));
+
+    assert!(read_tile_colors(&Pattern::random(2, 2, 4), path.to_str().unwrap()).is_err());
+    std::fs::remove_file(&path).unwrap();
+}